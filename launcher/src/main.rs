@@ -0,0 +1,104 @@
+use renderer_backend::app::{self, Entry};
+
+const HELP: &str = "\
+Runs the fluid and collision simulations in one window, F3 switches between them
+
+Usage: launcher [fluid|collisions] [OPTIONS]
+
+The options go to the simulation that starts first, run `launcher fluid --help` or
+`launcher collisions --help` to see them. The other simulation starts with its defaults.
+F5 starts the current simulation over.
+
+Options:
+  -h, --help          Print this help
+  -V, --version       Print the version
+";
+
+const SCREEN_SIZE: [u32; 2] = [1200, 600]; // The window size both simulations start with by default
+const NAMES: [&str; 2] = ["fluid", "collisions"];
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Launch(usize, Vec<String>), // Which simulation starts first and the options for it
+    Help,
+    Version,
+}
+
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (first, options) = match parse(&args) {
+        Command::Launch(first, options) => (first, options),
+        Command::Help => {
+            print!("{}", HELP);
+            return;
+        }
+        Command::Version => {
+            println!("launcher {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+    };
+    // The help of a simulation is printed before there is a window, rather than failing to launch it
+    let helps = [rust_fluid::help, rust_collisions::help];
+    if let Some(help) = helps[first](&options) {
+        print!("{}", help);
+        return;
+    }
+
+    let mut entries = [
+        Entry { name: "Fluid", launch: rust_fluid::launch, args: vec![] },
+        Entry { name: "Collisions", launch: rust_collisions::launch, args: vec![] },
+    ];
+    entries[first].args = options;
+    if let Err(error) = app::run(SCREEN_SIZE, &entries, first) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+// The version can also follow the name of a simulation, the simulations don't have one of their own
+fn parse(args: &[String]) -> Command {
+    let (first, options) = match args.first().map(String::as_str) {
+        Some("-h" | "--help") => return Command::Help,
+        Some(name) => match NAMES.iter().position(|&known| known == name) {
+            Some(first) => (first, args[1..].to_vec()),
+            None => (0, args.to_vec()),
+        },
+        None => (0, vec![]),
+    };
+    match options.first().map(String::as_str) {
+        Some("-V" | "--version") => Command::Version,
+        _ => Command::Launch(first, options),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn the_first_argument_picks_the_simulation() {
+        assert_eq!(parse(&args("")), Command::Launch(0, vec![]));
+        assert_eq!(parse(&args("collisions --seed 4")), Command::Launch(1, args("--seed 4")));
+        assert_eq!(parse(&args("fluid")), Command::Launch(0, vec![]));
+        // Without a name the options go to the fluid
+        assert_eq!(parse(&args("--solver pbf")), Command::Launch(0, args("--solver pbf")));
+        assert_eq!(parse(&args("--help")), Command::Help);
+        assert_eq!(parse(&args("--version")), Command::Version);
+        assert_eq!(parse(&args("collisions -V")), Command::Version);
+        assert_eq!(parse(&args("collisions --help")), Command::Launch(1, args("--help")));
+    }
+
+    #[test]
+    fn the_simulations_print_their_help_before_launching() {
+        assert!(rust_collisions::help(&args("--seed 4 --help")).is_some_and(|help| help.contains("--particles XxY")));
+        assert!(rust_collisions::help(&args("--seed 4")).is_none());
+        assert!(rust_fluid::help(&args("--help")).is_some_and(|help| help.contains("--solver")));
+        assert!(rust_fluid::help(&args("--solver pbf")).is_none());
+    }
+}
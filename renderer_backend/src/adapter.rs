@@ -0,0 +1,64 @@
+// Picks the adapter to run on from --backend and --adapter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Gpu, // WGPU_ADAPTER_NAME or the default adapter
+    Cpu, // The software fallback adapter, like llvmpipe
+}
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gpu" => Some(Backend::Gpu),
+            "cpu" => Some(Backend::Cpu),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdapterOptions {
+    pub backend: Backend,
+    pub name: Option<String>, // Part of the adapter name, matched without case, overrides the backend
+}
+
+// The adapter to run on, it has to be able to present to the surface when there is one
+pub async fn select_adapter(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface<'_>>,
+    options: &AdapterOptions,
+) -> Result<wgpu::Adapter, String> {
+    let supported = |adapter: &wgpu::Adapter| surface.is_none_or(|surface| adapter.is_surface_supported(surface));
+
+    if let Some(name) = &options.name {
+        let adapters = instance.enumerate_adapters(wgpu::Backends::all());
+        let names: Vec<String> = adapters.iter().map(|adapter| adapter.get_info().name).collect();
+        return adapters
+            .into_iter()
+            .find(|adapter| adapter.get_info().name.to_lowercase().contains(&name.to_lowercase()) && supported(adapter))
+            .ok_or_else(|| format!("No adapter matches {:?}, the adapters are: {}", name, names.join(", ")));
+    }
+
+    match options.backend {
+        Backend::Gpu => wgpu::util::initialize_adapter_from_env_or_default(instance, surface)
+            .await
+            .ok_or_else(|| "No adapter found".to_string()),
+        Backend::Cpu => instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::LowPower,
+                force_fallback_adapter: true,
+                compatible_surface: surface,
+            })
+            .await
+            .ok_or_else(|| "No software adapter found".to_string()),
+    }
+}
+
+// Whether there is an adapter to run on without a window, the tests and benchmarks skip themselves without one
+pub async fn adapter_available(options: &AdapterOptions) -> bool {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+    select_adapter(&instance, None, options).await.is_ok()
+}
@@ -0,0 +1,264 @@
+use std::path::Path;
+use std::time::Duration;
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, Event, KeyEvent, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    window::Window,
+};
+use crate::window::{create_window, FrameInterval};
+
+const DEFAULT_FRAME_INTERVAL: Duration = Duration::from_millis(10); // Until the first simulation asks for its own
+
+// What the event loop needs from a simulation drawn in its window
+pub trait Simulation<'a> {
+    // Builds the simulation in `window` from its command line options
+    fn init(window: &'a Window, args: &[String]) -> Result<Self, String>
+    where
+        Self: Sized;
+
+    // How long to wait between frames
+    fn frame_interval(&self) -> Duration;
+
+    // Advances the simulation by a frame, unless it is paused
+    fn step(&mut self);
+
+    // Draws the simulation, its panel and its HUD to the window
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError>;
+
+    fn resize(&mut self, size: PhysicalSize<u32>);
+
+    // Gives the simulation a window event before the event loop's own keys, returns whether it used the event
+    fn handle_input(&mut self, event: &WindowEvent) -> bool;
+
+    // Starts the simulation over from how it started
+    fn reset(&mut self);
+
+    // The numbers shown in the HUD, such as the frame rate and the particle count
+    fn diagnostics(&self) -> Vec<(&'static str, String)>;
+
+    // Called before the simulation is closed or switched away from
+    fn close(&mut self) {}
+}
+
+// Builds a simulation in a window, the launcher keeps one for each simulation it can switch to
+pub type Launch = for<'w> fn(&'w Window, &[String]) -> Result<Box<dyn Simulation<'w> + 'w>, String>;
+
+// A simulation the event loop can start, with the options it starts with
+pub struct Entry {
+    pub name: &'static str,
+    pub launch: Launch,
+    pub args: Vec<String>,
+}
+
+// The diagnostics as `name: value` lines
+pub fn format_diagnostics(diagnostics: &[(&'static str, String)]) -> String {
+    diagnostics
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Opens a window of `size` and runs `entries[first]` in it until the window is closed
+// F3 closes the current simulation and starts the next one in the same window, and F5 resets it
+pub fn run(size: [u32; 2], entries: &[Entry], first: usize) -> Result<(), String> {
+    let interval = FrameInterval::new(DEFAULT_FRAME_INTERVAL);
+    let (event_loop, window) = create_window(size, interval.clone());
+    let window = &window;
+
+    let switchable = entries.len() > 1;
+    let mut current = first;
+    let mut simulation = start(window, &entries[current], &interval, switchable)?;
+    let mut error = None;
+    let error_slot = &mut error; // Set when neither simulation could be started after a switch
+
+    event_loop
+        .run(move |event, elwt| match event {
+            Event::UserEvent(..) => {
+                window.request_redraw();
+            }
+
+            Event::WindowEvent {
+                window_id,
+                ref event,
+            } if window_id == window.id() => match event {
+                WindowEvent::Resized(physical_size) => simulation.resize(*physical_size),
+
+                WindowEvent::CloseRequested => {
+                    println!("Closing window");
+                    simulation.close();
+                    elwt.exit();
+                }
+
+                WindowEvent::RedrawRequested => {
+                    simulation.step();
+                    match simulation.render() {
+                        Ok(_) => {}
+                        Err(wgpu::SurfaceError::Lost) => simulation.resize(window.inner_size()),
+                        Err(wgpu::SurfaceError::OutOfMemory) => elwt.exit(),
+                        Err(e) => eprintln!("{:?}", e),
+                    }
+                }
+
+                // The simulation gets the other events first, the keys below only see the ones it didn't use
+                _ if simulation.handle_input(event) => {}
+
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: PhysicalKey::Code(KeyCode::Escape),
+                            state: ElementState::Pressed,
+                            repeat: false,
+                            ..
+                        },
+                    ..
+                } => {
+                    println!("Closing window");
+                    simulation.close();
+                    elwt.exit();
+                }
+
+                // Switch to the next simulation
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: PhysicalKey::Code(KeyCode::F3),
+                            state: ElementState::Pressed,
+                            repeat: false,
+                            ..
+                        },
+                    ..
+                } if switchable => {
+                    println!("Leaving {}:\n{}", entries[current].name, format_diagnostics(&simulation.diagnostics()));
+                    simulation.close();
+                    // The surface of the old simulation has to go before the new one is made for the same window
+                    drop(std::mem::replace(&mut simulation, Box::new(Closed)));
+                    let next = (current + 1) % entries.len();
+                    match start(window, &entries[next], &interval, switchable) {
+                        Ok(started) => {
+                            simulation = started;
+                            current = next;
+                        }
+                        Err(next_error) => {
+                            eprintln!("Could not start {}: {}", entries[next].name, next_error);
+                            match start(window, &entries[current], &interval, switchable) {
+                                Ok(started) => simulation = started,
+                                Err(current_error) => {
+                                    *error_slot = Some(current_error);
+                                    elwt.exit();
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Start the current simulation over
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: PhysicalKey::Code(KeyCode::F5),
+                            state: ElementState::Pressed,
+                            repeat: false,
+                            ..
+                        },
+                    ..
+                } => simulation.reset(),
+
+                _ => {}
+            },
+
+            _ => {}
+        })
+        .map_err(|error| error.to_string())?;
+
+    error.map_or(Ok(()), Err)
+}
+
+// The HUD of a simulation, its diagnostics and whether it is paused
+pub fn hud_text(diagnostics: &[(&'static str, String)], paused: bool) -> String {
+    let mut hud = format_diagnostics(diagnostics);
+    if paused {
+        hud += "\nPaused";
+    }
+    hud
+}
+
+// Writes one row per particle to a CSV file under a header of `columns`, creating the directory it goes in
+pub fn write_particles<const N: usize>(path: &Path, columns: [&str; N], rows: impl IntoIterator<Item = [f32; N]>) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).map_err(|error| format!("Could not create {}: {}", directory.display(), error))?;
+    }
+    let mut csv = columns.join(",") + "\n";
+    for row in rows {
+        csv += &row.map(|value| value.to_string()).join(",");
+        csv += "\n";
+    }
+    std::fs::write(path, csv).map_err(|error| format!("Could not write {}: {}", path.display(), error))
+}
+
+// Builds the simulation of `entry` and lets it set the frame rate
+fn start<'w>(window: &'w Window, entry: &Entry, interval: &FrameInterval, switchable: bool) -> Result<Box<dyn Simulation<'w> + 'w>, String> {
+    println!("Starting {}", entry.name);
+    let simulation = (entry.launch)(window, &entry.args)?;
+    interval.set(simulation.frame_interval());
+    if switchable {
+        window.set_title(&format!("{} (F3 switches simulation)", entry.name));
+    } else {
+        window.set_title(entry.name);
+    }
+    Ok(simulation)
+}
+
+// Holds the place of a simulation between closing one and starting the next
+struct Closed;
+
+impl<'a> Simulation<'a> for Closed {
+    fn init(_window: &'a Window, _args: &[String]) -> Result<Self, String> {
+        Ok(Closed)
+    }
+
+    fn frame_interval(&self) -> Duration {
+        DEFAULT_FRAME_INTERVAL
+    }
+
+    fn step(&mut self) {}
+
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        Ok(())
+    }
+
+    fn resize(&mut self, _size: PhysicalSize<u32>) {}
+
+    fn handle_input(&mut self, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {}
+
+    fn diagnostics(&self) -> Vec<(&'static str, String)> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_are_one_line_each() {
+        let diagnostics = [("FPS", "60".to_string()), ("Particles", "625".to_string())];
+        assert_eq!(format_diagnostics(&diagnostics), "FPS: 60\nParticles: 625");
+        assert_eq!(format_diagnostics(&[]), "");
+        assert_eq!(hud_text(&diagnostics, true), "FPS: 60\nParticles: 625\nPaused");
+    }
+
+    #[test]
+    fn particles_are_written_one_per_row() {
+        let path = std::env::temp_dir().join(format!("renderer_backend_particles_{}", std::process::id())).join("particles.csv");
+        write_particles(&path, ["x", "y"], [[1.0, 2.5], [-3.0, 4.0]]).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(csv, "x,y\n1,2.5\n-3,4\n");
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind {
+    Uniform,
+    Storage { read_only: bool },
+}
+
+// One buffer a pipeline binds, the name is the variable in the shader
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferBinding {
+    pub name: String,
+    pub binding: u32,
+    pub kind: BufferKind,
+    pub visibility: wgpu::ShaderStages,
+}
+
+// Declares the buffers a pipeline uses, by hand or read from the shader with ShaderReflection
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BindGroupLayoutBuilder {
+    bindings: Vec<BufferBinding>,
+}
+
+impl BindGroupLayoutBuilder {
+
+    pub fn new() -> Self {
+        BindGroupLayoutBuilder {
+            bindings: Vec::new(),
+        }
+    }
+
+    pub fn storage(self, name: &str, binding: u32, read_only: bool, visibility: wgpu::ShaderStages) -> Self {
+        self.buffer(name, binding, BufferKind::Storage { read_only }, visibility)
+    }
+
+    pub fn uniform(self, name: &str, binding: u32, visibility: wgpu::ShaderStages) -> Self {
+        self.buffer(name, binding, BufferKind::Uniform, visibility)
+    }
+
+    // Declaring a buffer again adds the new stages to its visibility
+    pub fn buffer(mut self, name: &str, binding: u32, kind: BufferKind, visibility: wgpu::ShaderStages) -> Self {
+        match self.bindings.iter_mut().find(|existing| existing.binding == binding) {
+            Some(existing) => {
+                assert!(
+                    existing.name == name && existing.kind == kind,
+                    "Binding {} is declared as both {} and {}", binding, existing.name, name
+                );
+                existing.visibility |= visibility;
+            }
+            None => {
+                self.bindings.push(BufferBinding {
+                    name: name.to_string(),
+                    binding,
+                    kind,
+                    visibility,
+                });
+                self.bindings.sort_by_key(|binding| binding.binding);
+            }
+        }
+        self
+    }
+
+    pub fn storage_buffer_count(&self, stage: wgpu::ShaderStages) -> usize {
+        self.bindings
+            .iter()
+            .filter(|binding| matches!(binding.kind, BufferKind::Storage { .. }) && binding.visibility.contains(stage))
+            .count()
+    }
+
+    pub fn build(self, device: &wgpu::Device, label: &str) -> PipelineBindings {
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = self.bindings
+            .iter()
+            .map(|binding| wgpu::BindGroupLayoutEntry {
+                binding: binding.binding,
+                visibility: binding.visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: match binding.kind {
+                        BufferKind::Uniform => wgpu::BufferBindingType::Uniform,
+                        BufferKind::Storage { read_only } => wgpu::BufferBindingType::Storage { read_only },
+                    },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            })
+            .collect();
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &entries,
+        });
+
+        PipelineBindings {
+            label: label.to_string(),
+            layout,
+            bindings: self.bindings,
+        }
+    }
+}
+
+// A bind group layout and the names of the buffers that go in it
+pub struct PipelineBindings {
+    label: String,
+    layout: wgpu::BindGroupLayout,
+    bindings: Vec<BufferBinding>,
+}
+
+impl PipelineBindings {
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    // Whether every binding has a buffer with its name, so a reloaded shader that uses a new buffer can be turned away
+    // before create_bind_group panics on it
+    pub fn check_buffers(&self, buffers: &HashMap<&str, &wgpu::Buffer>) -> Result<(), String> {
+        match self.bindings.iter().find(|binding| !buffers.contains_key(binding.name.as_str())) {
+            Some(binding) => Err(format!("{} uses {}, but there is no buffer with that name", self.label, binding.name)),
+            None => Ok(()),
+        }
+    }
+
+    // Looks up the buffer for each binding by name
+    pub fn create_bind_group(&self, device: &wgpu::Device, buffers: &HashMap<&str, &wgpu::Buffer>) -> wgpu::BindGroup {
+        let entries: Vec<wgpu::BindGroupEntry> = self.bindings
+            .iter()
+            .map(|binding| wgpu::BindGroupEntry {
+                binding: binding.binding,
+                resource: buffers
+                    .get(binding.name.as_str())
+                    .unwrap_or_else(|| panic!("{} uses {}, but there is no buffer with that name", self.label, binding.name))
+                    .as_entire_binding(),
+            })
+            .collect();
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&self.label),
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+}
+
+// Reads which buffers each entry point of a shader uses
+pub struct ShaderReflection {
+    module: naga::Module,
+    info: naga::valid::ModuleInfo,
+}
+
+impl ShaderReflection {
+
+    pub fn new(source: &str) -> Result<Self, String> {
+        let module = naga::front::wgsl::parse_str(source).map_err(|error| error.emit_to_string(source))?;
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .map_err(|error| format!("{:?}", error.into_inner()))?;
+        Ok(ShaderReflection { module, info })
+    }
+
+    // The buffers used by the entry points, including through the functions they call
+    pub fn bindings(&self, entry_points: &[&str]) -> Result<BindGroupLayoutBuilder, String> {
+        let mut builder = BindGroupLayoutBuilder::new();
+
+        for &name in entry_points {
+            let (index, entry_point) = self.module.entry_points
+                .iter()
+                .enumerate()
+                .find(|(_, entry_point)| entry_point.name == name)
+                .ok_or_else(|| format!("There is no entry point called {}", name))?;
+            let visibility = match entry_point.stage {
+                naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+            };
+            let usage = self.info.get_entry_point(index);
+
+            for (handle, variable) in self.module.global_variables.iter() {
+                let Some(resource_binding) = &variable.binding else {
+                    continue;
+                };
+                if usage[handle].is_empty() {
+                    continue;
+                }
+                let kind = match variable.space {
+                    naga::AddressSpace::Uniform => BufferKind::Uniform,
+                    naga::AddressSpace::Storage { access } => BufferKind::Storage {
+                        read_only: !access.contains(naga::StorageAccess::STORE),
+                    },
+                    _ => continue,
+                };
+                let variable_name = variable.name.as_deref().unwrap_or_default();
+                if resource_binding.group != 0 {
+                    return Err(format!("{} is in group {}, only group 0 is supported", variable_name, resource_binding.group));
+                }
+
+                builder = builder.buffer(variable_name, resource_binding.binding, kind, visibility);
+            }
+        }
+
+        Ok(builder)
+    }
+}
+
+// Gives each pipeline a bind group layout with only the buffers its entry points use
+// `pipelines` names each pipeline and the entry points it runs
+pub fn create_bind_group_layouts(
+    device: &wgpu::Device,
+    shader_source: &str,
+    pipelines: &[(&'static str, &[&str])],
+) -> Result<HashMap<&'static str, PipelineBindings>, String> {
+    let reflection = ShaderReflection::new(shader_source)?;
+    pipelines
+        .iter()
+        .map(|&(pipeline, entry_points)| {
+            let bindings = reflection.bindings(entry_points)?;
+            Ok((pipeline, bindings.build(device, &format!("{} Bind Group", pipeline))))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+        @group(0) @binding(0) var<storage, read_write> a: array<f32>;
+        @group(0) @binding(1) var<storage, read> b: array<f32>;
+        @group(0) @binding(2) var<uniform> c: vec4<f32>;
+
+        fn read_b(i: u32) -> f32 {
+            return b[i];
+        }
+
+        @compute @workgroup_size(1)
+        fn uses_a(@builtin(global_invocation_id) id: vec3<u32>) {
+            a[id.x] = 1.0;
+        }
+
+        @compute @workgroup_size(1)
+        fn uses_a_and_b(@builtin(global_invocation_id) id: vec3<u32>) {
+            a[id.x] = read_b(id.x);
+        }
+
+        @vertex
+        fn vs_main() -> @builtin(position) vec4<f32> {
+            return c;
+        }
+
+        @fragment
+        fn fs_main() -> @location(0) vec4<f32> {
+            return c * b[0];
+        }
+    ";
+
+    #[test]
+    fn reflection_only_includes_used_buffers() {
+        let reflection = ShaderReflection::new(SHADER).unwrap();
+        assert_eq!(
+            reflection.bindings(&["uses_a"]).unwrap(),
+            BindGroupLayoutBuilder::new().storage("a", 0, false, wgpu::ShaderStages::COMPUTE)
+        );
+        // b is only used through a function call
+        assert_eq!(
+            reflection.bindings(&["uses_a_and_b"]).unwrap(),
+            BindGroupLayoutBuilder::new()
+                .storage("a", 0, false, wgpu::ShaderStages::COMPUTE)
+                .storage("b", 1, true, wgpu::ShaderStages::COMPUTE)
+        );
+    }
+
+    #[test]
+    fn reflection_merges_stages() {
+        let reflection = ShaderReflection::new(SHADER).unwrap();
+        assert_eq!(
+            reflection.bindings(&["vs_main", "fs_main"]).unwrap(),
+            BindGroupLayoutBuilder::new()
+                .storage("b", 1, true, wgpu::ShaderStages::FRAGMENT)
+                .uniform("c", 2, wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+        );
+    }
+
+    #[test]
+    fn unknown_entry_points_are_errors() {
+        let reflection = ShaderReflection::new(SHADER).unwrap();
+        assert!(reflection.bindings(&["missing"]).is_err());
+        assert!(ShaderReflection::new("fn broken( {").is_err());
+    }
+}
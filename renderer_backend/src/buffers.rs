@@ -0,0 +1,49 @@
+use bytemuck::Pod;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+
+// A buffer holding `contents`, for the buffers whose size is known up front
+pub fn create_buffer<T: Pod>(device: &wgpu::Device, label: &str, contents: &[T], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+    device.create_buffer_init(&BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(contents),
+        usage,
+    })
+}
+
+// A buffer the CPU can map to read `size` bytes copied out of another buffer
+pub fn create_reader_buffer(device: &wgpu::Device, label: &str, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Copies `source` into `reader` and maps it, waiting for everything submitted so far
+// Empty when the mapping fails, the error is printed
+pub fn read_buffer<T: Pod>(device: &wgpu::Device, queue: &wgpu::Queue, source: &wgpu::Buffer, reader: &wgpu::Buffer) -> Vec<T> {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Copy Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(source, 0, reader, 0, reader.size());
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let buffer_slice = reader.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).unwrap();
+    });
+    device.poll(wgpu::Maintain::Wait);
+
+    if let Ok(Ok(())) = receiver.recv() {
+        let data = buffer_slice.get_mapped_range();
+        let values = bytemuck::cast_slice(&data).to_vec();
+        drop(data);
+        reader.unmap();
+        values
+    } else {
+        eprintln!("Error mapping buffer");
+        vec![]
+    }
+}
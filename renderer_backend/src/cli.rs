@@ -0,0 +1,183 @@
+// The command line handling shared by the simulations, each one keeps its own table of flags
+use crate::adapter::Backend;
+
+pub const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+#[derive(Debug, PartialEq)]
+pub enum Command<T> {
+    Run(Box<T>),
+    Help,
+}
+
+// The arguments after the program name with every --config file read in, so the flag tables never see --config
+pub fn expand_configs(args: &[String]) -> Result<Vec<String>, String> {
+    let mut expanded = vec![];
+    let mut config_end = 0;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            let path = value(&mut args, arg)?;
+            let text = std::fs::read_to_string(&path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+            // The config goes first so the command line overrides it
+            let config = parse_config(&text).map_err(|error| format!("{}: {}", path, error))?;
+            let length = config.len();
+            expanded.splice(config_end..config_end, config);
+            config_end += length;
+        } else {
+            expanded.push(arg.clone());
+        }
+    }
+    Ok(expanded)
+}
+
+// The lines of a config file as arguments, `name value` or `name = value`, with # starting a comment
+pub fn parse_config(text: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, value) = match line.split_once(|c: char| c == '=' || c.is_whitespace()) {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_start_matches('=').trim())),
+            None => (line, None),
+        };
+        if name == "config" {
+            return Err(format!("Line {} includes another config, which is not supported", number + 1));
+        }
+        args.push(format!("--{}", name));
+        args.extend(value.filter(|value| !value.is_empty()).map(str::to_string));
+    }
+    Ok(args)
+}
+
+// Hands each flag to `flag` with the arguments after it to take its value from, -h or --help anywhere asks for the help
+// `flag` rejects the flags it doesn't know
+pub fn parse_flags<'a, T: Default>(
+    args: &'a [String],
+    mut flag: impl FnMut(&mut T, &str, &mut std::slice::Iter<'a, String>) -> Result<(), String>,
+) -> Result<Command<T>, String> {
+    let mut options = T::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            name => flag(&mut options, name, &mut args)?,
+        }
+    }
+    Ok(Command::Run(Box::new(options)))
+}
+
+// The options to run with, None once the help has been printed, a bad option is printed and exits with status 2
+pub fn options_or_exit<T>(command: Result<Command<T>, String>, help: &str) -> Option<T> {
+    match command {
+        Ok(Command::Run(options)) => Some(*options),
+        Ok(Command::Help) => {
+            print!("{}", help);
+            None
+        }
+        Err(error) => {
+            eprintln!("{}\nRun with --help to see the options", error);
+            std::process::exit(2);
+        }
+    }
+}
+
+// Logs at --log-level when it was given, otherwise at RUST_LOG
+pub fn init_logging(level: Option<&str>) {
+    match level {
+        Some(level) => env_logger::Builder::new().parse_filters(level).init(),
+        None => env_logger::init(),
+    }
+}
+
+// The value following a flag
+pub fn value<'a>(args: &mut impl Iterator<Item = &'a String>, flag: &str) -> Result<String, String> {
+    args.next().cloned().ok_or_else(|| format!("{} needs a value", flag))
+}
+
+pub fn number<T: std::str::FromStr>(value: &str, flag: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} expects a whole number, not {:?}", flag, value))
+}
+
+// A size like 80x40, both sides above 0
+pub fn size(value: &str, flag: &str) -> Result<[u32; 2], String> {
+    let sides: Option<Vec<u32>> = value.split('x').map(|n| n.parse().ok()).collect();
+    match sides.as_deref() {
+        Some(&[x, y]) if x > 0 && y > 0 => Ok([x, y]),
+        _ => Err(format!("{} expects a size like 80x40, not {:?}", flag, value)),
+    }
+}
+
+pub fn backend(name: &str) -> Result<Backend, String> {
+    Backend::from_name(name).ok_or_else(|| format!("Unknown backend {:?}, expected gpu or cpu", name))
+}
+
+// One of LOG_LEVELS, for env_logger
+pub fn log_level(level: &str) -> Result<String, String> {
+    if !LOG_LEVELS.contains(&level) {
+        return Err(format!("Unknown log level {:?}, expected one of {}", level, LOG_LEVELS.join(", ")));
+    }
+    Ok(level.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    // A flag table with one flag that takes a value and one that doesn't
+    fn parse(args: &[String]) -> Result<Command<(Option<u32>, bool)>, String> {
+        parse_flags(args, |options: &mut (Option<u32>, bool), flag, args| {
+            match flag {
+                "--steps" => options.0 = Some(number(&value(args, flag)?, flag)?),
+                "--headless" => options.1 = true,
+                _ => return Err(format!("Unknown option {:?}", flag)),
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn flags_are_handed_to_the_table() {
+        assert_eq!(parse(&args("--steps 5 --headless")), Ok(Command::Run(Box::new((Some(5), true)))));
+        assert_eq!(parse(&args("")), Ok(Command::Run(Box::default())));
+        assert_eq!(parse(&args("--steps 5 --help --unknown")), Ok(Command::Help));
+        assert!(parse(&args("--steps")).is_err());
+        assert!(parse(&args("--unknown")).is_err());
+    }
+
+    #[test]
+    fn config_lines_become_flags() {
+        let config = parse_config("# A comment\nseed 7\nkernel = poly6\n\nheadless\nsteps=50 # Trailing comment\nmask masks/a b.png\n").unwrap();
+        assert_eq!(config, ["--seed", "7", "--kernel", "poly6", "--headless", "--steps", "50", "--mask", "masks/a b.png"]);
+        assert!(parse_config("config other.txt").is_err());
+    }
+
+    #[test]
+    fn config_files_go_before_the_command_line() {
+        let path = std::env::temp_dir().join(format!("renderer_backend_cli_{}.txt", std::process::id()));
+        std::fs::write(&path, "steps 7\nheadless\n").unwrap();
+        let expanded = expand_configs(&args(&format!("--steps 8 --config {}", path.display())));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(expanded.unwrap(), args("--steps 7 --headless --steps 8"));
+        assert!(expand_configs(&args("--config")).is_err());
+    }
+
+    #[test]
+    fn values_are_checked() {
+        assert_eq!(size("80x40", "--grid"), Ok([80, 40]));
+        for bad in ["80", "0x40", "80x", "80x40x2", "-1x40"] {
+            assert!(size(bad, "--grid").is_err(), "{:?} should be an error", bad);
+        }
+        assert_eq!(number::<u64>("42", "--seed"), Ok(42));
+        assert!(number::<u64>("-1", "--seed").is_err());
+        assert_eq!(backend("cpu"), Ok(Backend::Cpu));
+        assert!(backend("tpu").is_err());
+        assert_eq!(log_level("warn"), Ok("warn".to_string()));
+        assert!(log_level("loud").is_err());
+    }
+}
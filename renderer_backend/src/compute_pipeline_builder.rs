@@ -1,53 +1,46 @@
-use std::env::current_dir;
-use std::fs;
-
-use super::bind_group_layout_generator;
-
-pub struct ComputePipelineBuilder {
-    shader_filename: String,
+pub struct ComputePipelineBuilder<'a> {
+    shader_source: String,
     entry_point: String,
-    bind_group_layout: Option<wgpu::BindGroupLayout>
+    bind_group_layout: Option<&'a wgpu::BindGroupLayout>
+}
+
+impl Default for ComputePipelineBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl ComputePipelineBuilder {
+impl<'a> ComputePipelineBuilder<'a> {
 
     pub fn new() -> Self {
         ComputePipelineBuilder {
-            shader_filename: "dummy".to_string(),
+            shader_source: String::new(),
             entry_point: "dummy".to_string(),
             bind_group_layout: None
         }
     }
 
-    pub fn set_bind_group_layout(&mut self, bind_group_layout: wgpu::BindGroupLayout) {
+    pub fn set_bind_group_layout(&mut self, bind_group_layout: &'a wgpu::BindGroupLayout) {
         self.bind_group_layout = Some(bind_group_layout);
     }
 
-    pub fn set_shader_module(&mut self, shader_filename: &str, entry_point: &str) {
-        self.shader_filename = shader_filename.to_string();
+    pub fn set_shader_module(&mut self, shader_source: &str, entry_point: &str) {
+        self.shader_source = shader_source.to_string();
         self.entry_point = entry_point.to_string();
     }
 
     pub fn build_pipeline(&self, device: &wgpu::Device) -> wgpu::ComputePipeline {
-        
-        let mut filepath = current_dir().unwrap();
-        filepath.push("src/");
-        filepath.push(self.shader_filename.as_str());
-        let filepath = filepath.into_os_string().into_string().unwrap();
-        let source_code = fs::read_to_string(filepath).expect("Can't read the shader source file.");
-
         let shader_module_descriptor = wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(source_code.into()),
+            source: wgpu::ShaderSource::Wgsl(self.shader_source.as_str().into()),
         };
         let shader_module = device.create_shader_module(shader_module_descriptor);
 
-        // Create the bind group layout *before* building the pipeline
-        let bind_group_layout = bind_group_layout_generator::get_bind_group_layout(device);
+        let bind_group_layout = self.bind_group_layout.expect("The pipeline needs a bind group layout");
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[bind_group_layout],
             push_constant_ranges: &[],
         });
 
@@ -0,0 +1,123 @@
+// Summary statistics of a headless run, compared against reference data committed next to the simulation
+// Running the tests with UPDATE_GOLDEN=1 records the reference data again instead of comparing
+// Only built with the golden feature, the simulations turn it on for their tests
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Snapshot {
+    values: Vec<(String, f32)>,
+}
+
+impl Snapshot {
+
+    pub fn new() -> Self {
+        Snapshot {
+            values: Vec::new(),
+        }
+    }
+
+    pub fn value(&mut self, name: impl Into<String>, value: f32) {
+        self.values.push((name.into(), value));
+    }
+
+    // One `name value` pair per line
+    pub fn to_text(&self) -> String {
+        self.values
+            .iter()
+            .map(|(name, value)| format!("{} {:?}\n", name, value))
+            .collect()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut snapshot = Snapshot::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (name, value) = line
+                .rsplit_once(' ')
+                .ok_or_else(|| format!("Line {} should be a name and a value: {:?}", number + 1, line))?;
+            let value = value
+                .parse()
+                .map_err(|_| format!("Line {} has an invalid value: {:?}", number + 1, line))?;
+            snapshot.value(name, value);
+        }
+        Ok(snapshot)
+    }
+
+    // Values match when they are within the tolerance, relative to the reference once it is larger than 1
+    pub fn compare(&self, reference: &Snapshot, tolerance: f32) -> Result<(), String> {
+        let mut mismatches = Vec::new();
+        if self.values.len() != reference.values.len() {
+            mismatches.push(format!("{} values, the reference has {}", self.values.len(), reference.values.len()));
+        }
+        for ((name, value), (reference_name, reference_value)) in self.values.iter().zip(&reference.values) {
+            if name != reference_name {
+                mismatches.push(format!("{} is where the reference has {}", name, reference_name));
+            } else if value.is_nan() || (value - reference_value).abs() > tolerance * reference_value.abs().max(1.0) {
+                mismatches.push(format!("{} is {}, the reference is {}", name, value, reference_value));
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatches.join("\n"))
+        }
+    }
+
+    // Compares against <dir>/<name>.txt, or records it with UPDATE_GOLDEN=1
+    // `dir` is the golden directory of the crate running the test, as the manifest directory here is this crate's
+    pub fn check(&self, dir: &str, name: &str, tolerance: f32) {
+        let path = format!("{}/{}.txt", dir, name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::write(&path, self.to_text()).unwrap();
+            println!("Recorded {}", path);
+            return;
+        }
+
+        let text = std::fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("There is no reference data at {}, record it with UPDATE_GOLDEN=1", path));
+        let reference = Snapshot::parse(&text).unwrap_or_else(|error| panic!("{}: {}", path, error));
+        if let Err(mismatches) = self.compare(&reference, tolerance) {
+            panic!("The run no longer matches {}:\n{}", path, mismatches);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(values: &[(&str, f32)]) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        for &(name, value) in values {
+            snapshot.value(name, value);
+        }
+        snapshot
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let original = snapshot(&[("mean speed", 1.5), ("particle 3 x", -0.125), ("count", 625.0)]);
+        assert_eq!(Snapshot::parse(&original.to_text()).unwrap(), original);
+        assert!(Snapshot::parse("missing_value").is_err());
+        assert!(Snapshot::parse("speed fast").is_err());
+    }
+
+    #[test]
+    fn compare_uses_the_tolerance() {
+        let reference = snapshot(&[("small", 0.5), ("large", 1000.0)]);
+        assert!(snapshot(&[("small", 0.505), ("large", 1005.0)]).compare(&reference, 0.01).is_ok());
+        assert!(snapshot(&[("small", 0.52), ("large", 1000.0)]).compare(&reference, 0.01).is_err());
+        assert!(snapshot(&[("small", 0.5), ("large", 1020.0)]).compare(&reference, 0.01).is_err());
+        assert!(snapshot(&[("small", f32::NAN), ("large", 1000.0)]).compare(&reference, 0.01).is_err());
+    }
+
+    #[test]
+    fn compare_reports_different_values() {
+        let reference = snapshot(&[("a", 1.0), ("b", 2.0)]);
+        assert!(snapshot(&[("a", 1.0)]).compare(&reference, 0.01).is_err());
+        assert!(snapshot(&[("a", 1.0), ("c", 2.0)]).compare(&reference, 0.01).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+use egui_wgpu::ScreenDescriptor;
+use winit::{event::WindowEvent, window::Window};
+
+// An egui overlay painted at the end of the render pass, hidden until toggle() is called
+pub struct Gui {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    visible: bool,
+}
+
+// What the overlay drew this frame, uploaded to the GPU and ready to paint
+pub struct GuiFrame {
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    screen: ScreenDescriptor,
+    textures_to_free: Vec<egui::TextureId>,
+}
+
+impl Gui {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, window: &Window) -> Self {
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        Gui {
+            context,
+            state,
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1),
+            visible: false,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    // Gives a window event to the overlay, true when the overlay used it and the simulation should ignore it
+    pub fn handle_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        let response = self.state.on_window_event(window, event);
+        self.visible && response.consumed
+    }
+
+    // Runs the panel and uploads what it drew, None while the overlay is hidden
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        window: &Window,
+        run_ui: impl FnOnce(&egui::Context),
+    ) -> Option<GuiFrame> {
+        if !self.visible {
+            return None;
+        }
+
+        let input = self.state.take_egui_input(window);
+        let output = self.context.run(input, run_ui);
+        self.state.handle_platform_output(window, output.platform_output);
+
+        let paint_jobs = self.context.tessellate(output.shapes, output.pixels_per_point);
+        let size = window.inner_size();
+        let screen = ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point: output.pixels_per_point,
+        };
+        for (id, image_delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, image_delta);
+        }
+        // Only paint callbacks add command buffers, the panels don't use them
+        let callbacks = self.renderer.update_buffers(device, queue, encoder, &paint_jobs, &screen);
+        queue.submit(callbacks);
+
+        Some(GuiFrame {
+            paint_jobs,
+            screen,
+            textures_to_free: output.textures_delta.free,
+        })
+    }
+
+    pub fn paint<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>, frame: &'rp GuiFrame) {
+        self.renderer.render(render_pass, &frame.paint_jobs, &frame.screen);
+    }
+
+    // Frees the textures egui is done with, once the frame that used them has been submitted
+    pub fn finish(&mut self, frame: GuiFrame) {
+        for id in &frame.textures_to_free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
+
+// The latest values of something the panel plots, the oldest are dropped
+pub struct History {
+    values: VecDeque<[f64; 2]>,
+    length: usize,
+}
+
+impl History {
+    pub fn new(length: usize) -> Self {
+        History {
+            values: VecDeque::with_capacity(length),
+            length,
+        }
+    }
+
+    pub fn push(&mut self, x: f64, y: f64) {
+        if self.values.len() == self.length {
+            self.values.pop_front();
+        }
+        self.values.push_back([x, y]);
+    }
+
+    pub fn latest(&self) -> Option<f64> {
+        self.values.back().map(|value| value[1])
+    }
+
+    // A small line plot of the history
+    pub fn plot(&self, ui: &mut egui::Ui, name: &str) {
+        let points: egui_plot::PlotPoints = self.values.iter().copied().collect();
+        egui_plot::Plot::new(name)
+            .height(80.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .show(ui, |plot_ui| plot_ui.line(egui_plot::Line::new(points).name(name)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histories_drop_the_oldest_values() {
+        let mut history = History::new(3);
+        assert_eq!(history.latest(), None);
+        for i in 0..5 {
+            history.push(i as f64, 10.0 * i as f64);
+        }
+        assert_eq!(history.values, [[2.0, 20.0], [3.0, 30.0], [4.0, 40.0]]);
+        assert_eq!(history.latest(), Some(40.0));
+    }
+}
@@ -0,0 +1,17 @@
+// The wgpu and winit plumbing shared by the simulations: pipeline builders, shader tooling, buffers, the window and the overlays
+pub mod pipeline_builder;
+pub mod compute_pipeline_builder;
+pub mod bind_group_layout_builder;
+pub mod shader_watcher;
+pub mod preprocessor;
+pub mod shader_types;
+pub mod profiler;
+pub mod adapter;
+pub mod gui;
+pub mod text;
+pub mod buffers;
+pub mod window;
+pub mod app;
+pub mod cli;
+#[cfg(feature = "golden")]
+pub mod golden;
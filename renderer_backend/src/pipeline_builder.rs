@@ -1,36 +1,39 @@
-use std::env::current_dir;
-use std::fs;
-
-use super::bind_group_layout_generator;
-
-pub struct PipelineBuilder {
-    shader_filename: String,
+pub struct PipelineBuilder<'a> {
+    shader_source: String,
     vertex_entry: String,
     fragment_entry: String,
     pixel_format: wgpu::TextureFormat,
-    bind_group_layout: Option<wgpu::BindGroupLayout>,
+    blend_state: wgpu::BlendState,
+    bind_group_layout: Option<&'a wgpu::BindGroupLayout>,
+}
+
+impl Default for PipelineBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl PipelineBuilder {
+impl<'a> PipelineBuilder<'a> {
 
     pub fn new() -> Self {
         PipelineBuilder {
-            shader_filename: "dummy".to_string(),
+            shader_source: String::new(),
             vertex_entry: "dummy".to_string(),
             fragment_entry: "dummy".to_string(),
             pixel_format: wgpu::TextureFormat::Rgba8Unorm,
+            blend_state: wgpu::BlendState::REPLACE,
             bind_group_layout: None,
         }
     }
 
-    pub fn set_bind_group_layout(&mut self, bind_group_layout: wgpu::BindGroupLayout) {
+    pub fn set_bind_group_layout(&mut self, bind_group_layout: &'a wgpu::BindGroupLayout) {
         self.bind_group_layout = Some(bind_group_layout);
     }
 
     pub fn set_shader_module(&mut self, 
-        shader_filename: &str, vertex_entry: &str, fragment_entry: &str) {
+        shader_source: &str, vertex_entry: &str, fragment_entry: &str) {
 
-        self.shader_filename = shader_filename.to_string();
+        self.shader_source = shader_source.to_string();
         self.vertex_entry = vertex_entry.to_string();
         self.fragment_entry = fragment_entry.to_string();
     }
@@ -39,27 +42,23 @@ impl PipelineBuilder {
         self.pixel_format = pixel_format;
     }
 
-    pub fn build_pipeline(&self, device: &wgpu::Device) -> wgpu::RenderPipeline {
-        
-        let mut filepath = current_dir().unwrap();
-        filepath.push("src/");
-        filepath.push(self.shader_filename.as_str());
-        let filepath = filepath.into_os_string().into_string().unwrap();
-        let source_code = fs::read_to_string(filepath).expect("Can't read the shader source file.");
+    pub fn set_blend_state(&mut self, blend_state: wgpu::BlendState) {
+        self.blend_state = blend_state;
+    }
 
+    pub fn build_pipeline(&self, device: &wgpu::Device) -> wgpu::RenderPipeline {
         let shader_module_descriptor = wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(source_code.into()),
+            source: wgpu::ShaderSource::Wgsl(self.shader_source.as_str().into()),
         };
         let shader_module = device.create_shader_module(shader_module_descriptor);
 
-        // Create the bind group layout *before* building the pipeline
-        let bind_group_layout = bind_group_layout_generator::get_bind_group_layout(device);
-        
-        // Create the pipeline using the new bind group layout
+        let bind_group_layout = self.bind_group_layout.expect("The pipeline needs a bind group layout");
+
+        // Create the pipeline using the bind group layout
         let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[bind_group_layout],
             push_constant_ranges: &[],
         };
         
@@ -67,7 +66,7 @@ impl PipelineBuilder {
 
         let render_targets = [Some(wgpu::ColorTargetState {
             format: self.pixel_format,
-            blend: Some(wgpu::BlendState::REPLACE),
+            blend: Some(self.blend_state),
             write_mask: wgpu::ColorWrites::ALL,
         })];
 
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+// Expands `#include "name"` and `#define NAME value` lines in WGSL before it is compiled
+// Includes are registered from Rust instead of read from disk, so generated code can be included the same way as a file
+pub struct Preprocessor {
+    includes: HashMap<String, String>,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preprocessor {
+
+    pub fn new() -> Self {
+        Preprocessor {
+            includes: HashMap::new(),
+        }
+    }
+
+    pub fn include(mut self, name: &str, source: &str) -> Self {
+        self.includes.insert(name.to_string(), source.to_string());
+        self
+    }
+
+    pub fn process(&self, source: &str) -> Result<String, String> {
+        let mut defines = Vec::new();
+        let mut output = String::new();
+        self.expand(source, &mut Vec::new(), &mut defines, &mut output)?;
+        Ok(output)
+    }
+
+    fn expand(&self, source: &str, stack: &mut Vec<String>, defines: &mut Vec<(String, String)>, output: &mut String) -> Result<(), String> {
+        for (line_number, line) in source.lines().enumerate() {
+            let trimmed = line.trim();
+
+            if let Some(name) = trimmed.strip_prefix("#include") {
+                let name = name.trim().trim_matches('"');
+                if stack.iter().any(|included| included == name) {
+                    return Err(format!("line {}: \"{}\" includes itself", line_number + 1, name));
+                }
+                let Some(included) = self.includes.get(name) else {
+                    return Err(format!("line {}: unknown include \"{}\"", line_number + 1, name));
+                };
+
+                stack.push(name.to_string());
+                self.expand(included, stack, defines, output)?;
+                stack.pop();
+            } else if let Some(define) = trimmed.strip_prefix("#define") {
+                let mut parts = define.trim().splitn(2, char::is_whitespace);
+                let name = parts.next().unwrap_or_default();
+                if name.is_empty() {
+                    return Err(format!("line {}: #define without a name", line_number + 1));
+                }
+                defines.push((name.to_string(), parts.next().unwrap_or_default().trim().to_string()));
+            } else {
+                output.push_str(&substitute(line, defines));
+                output.push('\n');
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Replaces whole identifiers that have been defined
+fn substitute(line: &str, defines: &[(String, String)]) -> String {
+    if defines.is_empty() {
+        return line.to_string();
+    }
+
+    let mut output = String::with_capacity(line.len());
+    let mut identifier = String::new();
+    for c in line.chars().chain(std::iter::once('\n')) {
+        if c.is_alphanumeric() || c == '_' {
+            identifier.push(c);
+            continue;
+        }
+
+        if !identifier.is_empty() {
+            match defines.iter().rev().find(|(name, _)| *name == identifier) {
+                Some((_, value)) => output.push_str(value),
+                None => output.push_str(&identifier),
+            }
+            identifier.clear();
+        }
+        if c != '\n' {
+            output.push(c);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_are_expanded_in_place() {
+        let output = Preprocessor::new()
+            .include("a.wgsl", "const A: u32 = 1u;\n#include \"b.wgsl\"")
+            .include("b.wgsl", "const B: u32 = 2u;")
+            .process("#include \"a.wgsl\"\nconst C: u32 = A + B;")
+            .unwrap();
+        assert_eq!(output, "const A: u32 = 1u;\nconst B: u32 = 2u;\nconst C: u32 = A + B;\n");
+    }
+
+    #[test]
+    fn defines_replace_whole_identifiers() {
+        let output = Preprocessor::new()
+            .process("#define SIZE 16u\n#define HALF 8u\nlet a = SIZE + HALF + SIZE_2;")
+            .unwrap();
+        assert_eq!(output, "let a = 16u + 8u + SIZE_2;\n");
+    }
+
+    #[test]
+    fn bad_includes_are_errors() {
+        assert!(Preprocessor::new().process("#include \"missing.wgsl\"").is_err());
+        assert!(Preprocessor::new()
+            .include("loop.wgsl", "#include \"loop.wgsl\"")
+            .process("#include \"loop.wgsl\"")
+            .is_err());
+    }
+}
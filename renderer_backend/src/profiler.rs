@@ -0,0 +1,293 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Instant;
+
+const MAX_TIMED_PASSES: u32 = 256; // Passes after this many in a frame are not timed
+
+// Times each pass with timestamp queries when the adapter supports them, and on the CPU otherwise
+// The CPU timer waits for every pass to finish, so it includes the submission overhead and slows the frame down
+pub struct Profiler {
+    timer: Timer,
+    frame: RefCell<Frame>,
+    table: ProfileTable,
+}
+
+enum Timer {
+    Disabled,
+    Cpu,
+    Gpu(Box<GpuTimer>),
+}
+
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    period: f32, // Nanoseconds per timestamp tick
+}
+
+#[derive(Default)]
+struct Frame {
+    gpu_passes: Vec<&'static str>, // Pass i wrote timestamps 2i and 2i + 1
+    cpu_pass: Option<(&'static str, Instant)>,
+    durations: Vec<(&'static str, f64)>,
+}
+
+impl Profiler {
+
+    pub fn disabled() -> Self {
+        Profiler {
+            timer: Timer::Disabled,
+            frame: RefCell::new(Frame::default()),
+            table: ProfileTable::new(1),
+        }
+    }
+
+    // window is how many frames the table averages over
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, window: usize) -> Self {
+        let timer = if device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            let size = MAX_TIMED_PASSES as u64 * 2 * std::mem::size_of::<u64>() as u64;
+            Timer::Gpu(Box::new(GpuTimer {
+                query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                    label: Some("Profiler Query Set"),
+                    ty: wgpu::QueryType::Timestamp,
+                    count: MAX_TIMED_PASSES * 2,
+                }),
+                resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Resolve Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                read_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Read Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                period: queue.get_timestamp_period(),
+            }))
+        } else {
+            Timer::Cpu
+        };
+
+        Profiler {
+            timer,
+            frame: RefCell::new(Frame::default()),
+            table: ProfileTable::new(window),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self.timer, Timer::Disabled)
+    }
+
+    pub fn source(&self) -> &'static str {
+        match self.timer {
+            Timer::Disabled => "disabled",
+            Timer::Cpu => "CPU",
+            Timer::Gpu(_) => "GPU timestamps",
+        }
+    }
+
+    pub fn table(&self) -> &ProfileTable {
+        &self.table
+    }
+
+    // Call before beginning a compute pass, and end_pass after submitting it
+    pub fn compute_pass(&self, name: &'static str) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        let (query_set, start) = self.begin_pass(name)?;
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(start),
+            end_of_pass_write_index: Some(start + 1),
+        })
+    }
+
+    pub fn render_pass(&self, name: &'static str) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let (query_set, start) = self.begin_pass(name)?;
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(start),
+            end_of_pass_write_index: Some(start + 1),
+        })
+    }
+
+    // Returns the query set and the first timestamp index when the pass should write timestamps
+    fn begin_pass(&self, name: &'static str) -> Option<(&wgpu::QuerySet, u32)> {
+        let mut frame = self.frame.borrow_mut();
+        match &self.timer {
+            Timer::Disabled => None,
+            Timer::Cpu => {
+                frame.cpu_pass = Some((name, Instant::now()));
+                None
+            }
+            Timer::Gpu(gpu) => {
+                let index = frame.gpu_passes.len() as u32;
+                if index >= MAX_TIMED_PASSES {
+                    return None;
+                }
+                frame.gpu_passes.push(name);
+                Some((&gpu.query_set, index * 2))
+            }
+        }
+    }
+
+    pub fn end_pass(&self, device: &wgpu::Device) {
+        let mut frame = self.frame.borrow_mut();
+        if let Some((name, start)) = frame.cpu_pass.take() {
+            device.poll(wgpu::Maintain::Wait);
+            frame.durations.push((name, start.elapsed().as_secs_f64() * 1000.0));
+        }
+    }
+
+    // Reads back this frame's timestamps and adds the frame to the table
+    pub fn end_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut frame = self.frame.take();
+
+        if let Timer::Gpu(gpu) = &self.timer {
+            let GpuTimer { query_set, resolve_buffer, read_buffer, period } = gpu.as_ref();
+            if !frame.gpu_passes.is_empty() {
+                let query_count = frame.gpu_passes.len() as u32 * 2;
+                let size = query_count as u64 * std::mem::size_of::<u64>() as u64;
+
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Profiler Encoder"),
+                });
+                encoder.resolve_query_set(query_set, 0..query_count, resolve_buffer, 0);
+                encoder.copy_buffer_to_buffer(resolve_buffer, 0, read_buffer, 0, size);
+                queue.submit(std::iter::once(encoder.finish()));
+
+                let slice = read_buffer.slice(0..size);
+                let (sender, receiver) = std::sync::mpsc::channel();
+                slice.map_async(wgpu::MapMode::Read, move |result| {
+                    sender.send(result).ok();
+                });
+                device.poll(wgpu::Maintain::Wait);
+
+                if let Ok(Ok(())) = receiver.recv() {
+                    {
+                        let data = slice.get_mapped_range();
+                        let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                        for (i, name) in frame.gpu_passes.iter().enumerate() {
+                            let ticks = timestamps[i * 2 + 1].wrapping_sub(timestamps[i * 2]);
+                            frame.durations.push((name, ticks as f64 * *period as f64 / 1_000_000.0));
+                        }
+                    }
+                    read_buffer.unmap();
+                }
+            }
+        }
+
+        if self.is_enabled() {
+            self.table.record_frame(&frame.durations);
+        }
+    }
+}
+
+// The time spent in each pass over the last few frames, in milliseconds
+pub struct ProfileTable {
+    window: usize,
+    frames: u32,
+    passes: Vec<(&'static str, VecDeque<f64>)>, // In the order they first ran
+}
+
+impl ProfileTable {
+
+    pub fn new(window: usize) -> Self {
+        ProfileTable {
+            window: window.max(1),
+            frames: 0,
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    // Passes that run several times in a frame, like the sort passes, are added together
+    pub fn record_frame(&mut self, durations: &[(&'static str, f64)]) {
+        let mut totals: Vec<(&'static str, f64)> = Vec::new();
+        for &(name, duration) in durations {
+            match totals.iter_mut().find(|(total_name, _)| *total_name == name) {
+                Some((_, total)) => *total += duration,
+                None => totals.push((name, duration)),
+            }
+        }
+
+        for (name, total) in totals {
+            let samples = match self.passes.iter().position(|(pass, _)| *pass == name) {
+                Some(index) => &mut self.passes[index].1,
+                None => {
+                    self.passes.push((name, VecDeque::new()));
+                    &mut self.passes.last_mut().unwrap().1
+                }
+            };
+            samples.push_back(total);
+            if samples.len() > self.window {
+                samples.pop_front();
+            }
+        }
+
+        self.frames += 1;
+    }
+}
+
+impl fmt::Display for ProfileTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<20} {:>9} {:>9} {:>9}", "Pass", "avg ms", "min ms", "max ms")?;
+        let mut total = 0.0;
+        for (name, samples) in &self.passes {
+            let average = samples.iter().sum::<f64>() / samples.len() as f64;
+            let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = samples.iter().copied().fold(0.0, f64::max);
+            writeln!(f, "{:<20} {:>9.3} {:>9.3} {:>9.3}", name, average, min, max)?;
+            total += average;
+        }
+        write!(f, "{:<20} {:>9.3}", "total", total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The average column of a pass in the printed table
+    fn average(table: &ProfileTable, name: &str) -> Option<f64> {
+        let text = table.to_string();
+        let row = text.lines().find(|line| line.split_whitespace().next() == Some(name))?;
+        row.split_whitespace().nth(1)?.parse().ok()
+    }
+
+    #[test]
+    fn repeated_passes_are_added_together() {
+        let mut table = ProfileTable::new(4);
+        table.record_frame(&[("histogram", 1.0), ("scan", 0.5), ("histogram", 2.0)]);
+        assert_eq!(average(&table, "histogram"), Some(3.0));
+        assert_eq!(average(&table, "scan"), Some(0.5));
+        assert_eq!(average(&table, "render"), None);
+    }
+
+    #[test]
+    fn old_frames_leave_the_window() {
+        let mut table = ProfileTable::new(2);
+        for duration in [10.0, 1.0, 3.0] {
+            table.record_frame(&[("density", duration)]);
+        }
+        assert_eq!(average(&table, "density"), Some(2.0));
+        assert_eq!(table.frames(), 3);
+    }
+
+    #[test]
+    fn table_lists_passes_in_the_order_they_ran() {
+        let mut table = ProfileTable::new(8);
+        table.record_frame(&[("density", 1.0), ("forces", 2.0)]);
+        table.record_frame(&[("render", 0.5), ("density", 1.0)]);
+        let text = table.to_string();
+        let position = |name: &str| text.find(name).unwrap();
+        assert!(position("density") < position("forces"));
+        assert!(position("forces") < position("render"));
+        assert!(text.lines().last().unwrap().contains("3.500"));
+    }
+}
@@ -0,0 +1,152 @@
+// Generates WGSL for constants and structs defined in Rust, so the CPU and the shader can't disagree about them
+
+// A Rust type that has a WGSL type with the same size and alignment
+pub trait WgslType {
+    fn wgsl_type() -> String;
+}
+
+impl WgslType for f32 {
+    fn wgsl_type() -> String { "f32".to_string() }
+}
+
+impl WgslType for u32 {
+    fn wgsl_type() -> String { "u32".to_string() }
+}
+
+impl WgslType for i32 {
+    fn wgsl_type() -> String { "i32".to_string() }
+}
+
+impl WgslType for [f32; 2] {
+    fn wgsl_type() -> String { "vec2<f32>".to_string() }
+}
+
+impl WgslType for [u32; 2] {
+    fn wgsl_type() -> String { "vec2<u32>".to_string() }
+}
+
+impl WgslType for [i32; 2] {
+    fn wgsl_type() -> String { "vec2<i32>".to_string() }
+}
+
+impl WgslType for [f32; 4] {
+    fn wgsl_type() -> String { "vec4<f32>".to_string() }
+}
+
+// vec3 is aligned to 16 bytes, so three floats of padding are an array instead
+impl WgslType for [f32; 3] {
+    fn wgsl_type() -> String { "array<f32, 3>".to_string() }
+}
+
+impl<T: WgslStruct, const N: usize> WgslType for [T; N] {
+    fn wgsl_type() -> String { format!("array<{}, {}>", T::NAME, N) }
+}
+
+// A struct declared with wgsl_struct!
+pub trait WgslStruct {
+    const NAME: &'static str;
+
+    // The name, WGSL type, and Rust offset of each field
+    fn fields() -> Vec<(&'static str, String, usize)>;
+
+    fn wgsl_definition() -> String {
+        let mut definition = format!("struct {} {{\n", Self::NAME);
+        for (name, ty, _) in Self::fields() {
+            definition += &format!("    {}: {},\n", name, ty);
+        }
+        definition + "}\n"
+    }
+}
+
+// Declares a #[repr(C)] struct and its WGSL definition from the same field list
+#[macro_export]
+macro_rules! wgsl_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($field_vis $field: $ty),*
+        }
+
+        impl $crate::shader_types::WgslStruct for $name {
+            const NAME: &'static str = stringify!($name);
+
+            fn fields() -> Vec<(&'static str, String, usize)> {
+                vec![$((
+                    stringify!($field),
+                    <$ty as $crate::shader_types::WgslType>::wgsl_type(),
+                    std::mem::offset_of!($name, $field),
+                )),*]
+            }
+        }
+    };
+}
+
+// A Rust value written as a WGSL literal
+pub trait WgslValue {
+    fn wgsl_type(&self) -> String;
+    fn wgsl_value(&self) -> String;
+}
+
+impl WgslValue for f32 {
+    fn wgsl_type(&self) -> String { "f32".to_string() }
+    fn wgsl_value(&self) -> String { format!("{:?}", self) }
+}
+
+impl WgslValue for u32 {
+    fn wgsl_type(&self) -> String { "u32".to_string() }
+    fn wgsl_value(&self) -> String { format!("{}u", self) }
+}
+
+impl WgslValue for i32 {
+    fn wgsl_type(&self) -> String { "i32".to_string() }
+    fn wgsl_value(&self) -> String { format!("{}i", self) }
+}
+
+impl WgslValue for [f32; 2] {
+    fn wgsl_type(&self) -> String { "vec2<f32>".to_string() }
+    fn wgsl_value(&self) -> String { format!("vec2<f32>({:?}, {:?})", self[0], self[1]) }
+}
+
+impl WgslValue for [u32; 2] {
+    fn wgsl_type(&self) -> String { "vec2<u32>".to_string() }
+    fn wgsl_value(&self) -> String { format!("vec2<u32>({}u, {}u)", self[0], self[1]) }
+}
+
+// Collects the shared constants and structs into WGSL that the shader includes
+pub struct SharedDefinitions {
+    wgsl: String,
+}
+
+impl Default for SharedDefinitions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedDefinitions {
+
+    pub fn new() -> Self {
+        SharedDefinitions {
+            wgsl: String::new(),
+        }
+    }
+
+    pub fn constant(mut self, name: &str, value: impl WgslValue) -> Self {
+        self.wgsl += &format!("const {}: {} = {};\n", name, value.wgsl_type(), value.wgsl_value());
+        self
+    }
+
+    pub fn structure<T: WgslStruct>(mut self) -> Self {
+        self.wgsl += &T::wgsl_definition();
+        self
+    }
+
+    pub fn build(self) -> String {
+        self.wgsl
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// Watches a shader file on disk so the pipelines can be rebuilt while the simulation runs
+pub struct ShaderWatcher {
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+}
+
+impl ShaderWatcher {
+
+    pub fn new(path: &str) -> Self {
+        ShaderWatcher {
+            path: PathBuf::from(path),
+            last_modified: None,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap_or("shader")
+    }
+
+    // Returns the source if the file changed since the last poll, the first poll always returns it
+    pub fn poll(&mut self) -> Option<String> {
+        let modified = fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok()?;
+        if self.last_modified == Some(modified) {
+            return None;
+        }
+        self.last_modified = Some(modified);
+
+        match fs::read_to_string(&self.path) {
+            Ok(source) => Some(source),
+            Err(error) => {
+                eprintln!("Can't read {}: {}", self.path.display(), error);
+                None
+            }
+        }
+    }
+
+    // Rebuilds the pipelines when the file changes to something other than the `running` source once preprocessed
+    // `build` makes them from the new source next to the running ones, and they are only returned, to be swapped in,
+    // once they have built and validated, otherwise the error is printed and the running pipelines are kept
+    pub fn reload<T>(
+        &mut self,
+        device: &wgpu::Device,
+        running: &str,
+        preprocess: impl FnOnce(&str) -> Result<String, String>,
+        build: impl FnOnce(String) -> Result<T, String>,
+    ) -> Option<T> {
+        let source = self.poll()?;
+        let built = preprocess(&source).and_then(|source| {
+            if source == running {
+                return Ok(None);
+            }
+            validated(device, || build(source)).map(Some)
+        });
+        match built {
+            Ok(Some(pipelines)) => {
+                println!("Reloaded {}", self.path());
+                Some(pipelines)
+            }
+            Ok(None) => None,
+            Err(error) => {
+                eprintln!("Error in {}, keeping the previous shader:\n{}", self.path(), error);
+                None
+            }
+        }
+    }
+}
+
+// Runs `build` with the validation errors it causes caught, so pipelines that don't build are an error instead of a panic
+pub fn validated<T>(device: &wgpu::Device, build: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let built = build();
+    let error = pollster::block_on(device.pop_error_scope());
+    match error {
+        Some(error) => Err(error.to_string()),
+        None => built,
+    }
+}
@@ -0,0 +1,51 @@
+// Text drawn over the simulation, one instance per character, used by renderer_backend/text.rs
+
+// The glyph size and the Glyph struct, generated by text.rs
+#include "text_shared.wgsl"
+
+@group(0) @binding(0) var<storage, read> glyphs: array<Glyph>;
+@group(0) @binding(1) var<storage, read> font: array<u32>; // One row of bits per line of each glyph
+@group(0) @binding(2) var<uniform> screen: vec2<f32>; // The size of the surface in pixels
+
+struct GlyphOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) cell: vec2<f32>, // Where in the glyph this is, in font pixels
+    @location(1) @interpolate(flat) character: u32,
+    @location(2) color: vec4<f32>,
+};
+
+@vertex
+fn vs_text(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> GlyphOutput {
+    // Two counter clockwise triangles once y is flipped to point up
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0), // Top Left
+        vec2<f32>(0.0, 1.0), // Bottom Left
+        vec2<f32>(1.0, 0.0), // Top Right
+
+        vec2<f32>(1.0, 0.0), // Top Right
+        vec2<f32>(0.0, 1.0), // Bottom Left
+        vec2<f32>(1.0, 1.0) // Bottom Right
+    );
+
+    let glyph = glyphs[instance];
+    let cell = corners[vertex] * vec2<f32>(f32(GLYPH_WIDTH), f32(GLYPH_HEIGHT));
+    let pixel = glyph.position + cell * glyph.scale;
+
+    var out: GlyphOutput;
+    out.pos = vec4<f32>(pixel.x / screen.x * 2.0 - 1.0, 1.0 - pixel.y / screen.y * 2.0, 0.0, 1.0);
+    out.cell = cell;
+    out.character = glyph.character;
+    out.color = glyph.color;
+    return out;
+}
+
+@fragment
+fn fs_text(in: GlyphOutput) -> @location(0) vec4<f32> {
+    let column = min(u32(in.cell.x), GLYPH_WIDTH - 1u);
+    let row = min(u32(in.cell.y), GLYPH_HEIGHT - 1u);
+    let bits = font[in.character * GLYPH_HEIGHT + row];
+    if ((bits >> (GLYPH_WIDTH - 1u - column)) & 1u) == 0u {
+        discard;
+    }
+    return in.color;
+}
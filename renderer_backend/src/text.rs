@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use crate::wgsl_struct;
+use super::{
+    bind_group_layout_builder::{PipelineBindings, ShaderReflection}, pipeline_builder::PipelineBuilder,
+    preprocessor::Preprocessor, shader_types::SharedDefinitions,
+};
+
+const TEXT_SHADER: &str = include_str!("shaders/text.wgsl");
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const GLYPH_ADVANCE: [u32; 2] = [GLYPH_WIDTH + 1, GLYPH_HEIGHT + 2]; // One font pixel between characters and two between lines
+const FIRST_CHARACTER: char = ' '; // The font covers printable ASCII, from the space to the tilde
+const UNKNOWN_CHARACTER: char = '?'; // Drawn for characters the font doesn't have
+const MAX_GLYPHS: usize = 4096; // How many characters can be drawn each frame, shadows included
+const SHADOW_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.75];
+const HUD_SCALE: f32 = 2.0; // How many pixels each pixel of the HUD font covers
+const HUD_MARGIN: f32 = 10.0; // The gap between the HUD and the bottom left corner of the window
+
+// The font atlas, one row of bits per line of each glyph with the most significant bit on the left
+const FONT: [[u8; GLYPH_HEIGHT as usize]; 95] = [
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // ' '
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100], // '!'
+    [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000], // '"'
+    [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010], // '#'
+    [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100], // '$'
+    [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011], // '%'
+    [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101], // '&'
+    [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000], // '\''
+    [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010], // '('
+    [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000], // ')'
+    [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000], // '*'
+    [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000], // '+'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000], // ','
+    [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000], // '-'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100], // '.'
+    [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000], // '/'
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110], // '0'
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // '1'
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111], // '2'
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110], // '3'
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010], // '4'
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110], // '5'
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110], // '6'
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000], // '7'
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110], // '8'
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100], // '9'
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000], // ':'
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000], // ';'
+    [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010], // '<'
+    [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000], // '='
+    [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000], // '>'
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100], // '?'
+    [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110], // '@'
+    [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // 'A'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110], // 'B'
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110], // 'C'
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100], // 'D'
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111], // 'E'
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000], // 'F'
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111], // 'G'
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // 'H'
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 'I'
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // 'J'
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001], // 'K'
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111], // 'L'
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001], // 'M'
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001], // 'N'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // 'O'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000], // 'P'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101], // 'Q'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001], // 'R'
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110], // 'S'
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // 'T'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // 'U'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // 'V'
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010], // 'W'
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001], // 'X'
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100], // 'Y'
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111], // 'Z'
+    [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110], // '['
+    [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000], // '\\'
+    [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110], // ']'
+    [0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000], // '^'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111], // '_'
+    [0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000], // '`'
+    [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111], // 'a'
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110], // 'b'
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110], // 'c'
+    [0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111], // 'd'
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110], // 'e'
+    [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000], // 'f'
+    [0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // 'g'
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // 'h'
+    [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110], // 'i'
+    [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100], // 'j'
+    [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010], // 'k'
+    [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 'l'
+    [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001], // 'm'
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // 'n'
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110], // 'o'
+    [0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000], // 'p'
+    [0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001], // 'q'
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000], // 'r'
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110], // 's'
+    [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110], // 't'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101], // 'u'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // 'v'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010], // 'w'
+    [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001], // 'x'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // 'y'
+    [0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111], // 'z'
+    [0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010], // '{'
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // '|'
+    [0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000], // '}'
+    [0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000], // '~'
+];
+
+wgsl_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+    struct Glyph {
+        position: [f32; 2], // 8 bytes, the top left corner in pixels
+        character: u32, // 4 bytes, the index of the glyph in the font
+        scale: f32, // 4 bytes, how many screen pixels each font pixel covers
+        color: [f32; 4], // 16 bytes
+    }
+}
+
+// Draws text over the simulation in its own render pass, one instanced quad per character
+pub struct TextRenderer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    glyph_buffer: wgpu::Buffer,
+    screen_buffer: wgpu::Buffer,
+    glyphs: Vec<Glyph>, // The characters queued for the next draw
+}
+
+impl TextRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, size: [u32; 2]) -> Self {
+        // The shader is embedded and doesn't change, so an error in it is a bug
+        let shader_source = Preprocessor::new()
+            .include("text_shared.wgsl", &shared_wgsl())
+            .process(TEXT_SHADER)
+            .expect("The text shader could not be preprocessed");
+        let bindings: PipelineBindings = ShaderReflection::new(&shader_source)
+            .and_then(|reflection| reflection.bindings(&["vs_text", "fs_text"]))
+            .expect("The text shader is invalid")
+            .build(device, "Text Bind Group");
+
+        let glyph_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Text Glyph Buffer"),
+            size: (MAX_GLYPHS * std::mem::size_of::<Glyph>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let font: Vec<u32> = FONT.iter().flatten().map(|&row| row as u32).collect();
+        let font_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Text Font Buffer"),
+            contents: bytemuck::cast_slice(&font),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let screen_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Text Screen Buffer"),
+            contents: bytemuck::cast_slice(&[size[0] as f32, size[1] as f32]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = bindings.create_bind_group(
+            device,
+            &HashMap::from([("glyphs", &glyph_buffer), ("font", &font_buffer), ("screen", &screen_buffer)]),
+        );
+
+        let mut pipeline_builder = PipelineBuilder::new();
+        pipeline_builder.set_shader_module(&shader_source, "vs_text", "fs_text");
+        pipeline_builder.set_pixel_format(format);
+        pipeline_builder.set_blend_state(wgpu::BlendState::ALPHA_BLENDING);
+        pipeline_builder.set_bind_group_layout(bindings.layout());
+        let pipeline = pipeline_builder.build_pipeline(device);
+
+        TextRenderer {
+            pipeline,
+            bind_group,
+            glyph_buffer,
+            screen_buffer,
+            glyphs: Vec::new(),
+        }
+    }
+
+    // The text is laid out in pixels, so it has to know the size of the surface
+    pub fn resize(&self, queue: &wgpu::Queue, size: [u32; 2]) {
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[size[0] as f32, size[1] as f32]));
+    }
+
+    // Queues text with its top left corner at a position in pixels, with a shadow so it can be read on any background
+    // Queues the HUD of a simulation in the bottom left corner of a screen `height` pixels high
+    pub fn queue_hud(&mut self, hud: &str, height: f32, color: [f32; 4]) {
+        let hud_height = text_size(hud, HUD_SCALE)[1];
+        self.queue(hud, [HUD_MARGIN, height - hud_height - HUD_MARGIN], HUD_SCALE, color);
+    }
+
+    pub fn queue(&mut self, text: &str, position: [f32; 2], scale: f32, color: [f32; 4]) {
+        let shadow = [position[0] + scale, position[1] + scale];
+        self.glyphs.extend(layout(text, shadow, scale, SHADOW_COLOR));
+        self.glyphs.extend(layout(text, position, scale, color));
+    }
+
+    // Draws the queued text over the view, after everything else has been drawn, and clears the queue
+    pub fn draw(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        if self.glyphs.len() > MAX_GLYPHS {
+            log::warn!("{} characters were queued, only the first {} are drawn", self.glyphs.len(), MAX_GLYPHS);
+            self.glyphs.truncate(MAX_GLYPHS);
+        }
+        if self.glyphs.is_empty() {
+            return;
+        }
+        queue.write_buffer(&self.glyph_buffer, 0, bytemuck::cast_slice(&self.glyphs));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Text Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..self.glyphs.len() as u32);
+        drop(render_pass);
+
+        self.glyphs.clear();
+    }
+}
+
+// The size of text in pixels, the widest line by the number of lines
+pub fn text_size(text: &str, scale: f32) -> [f32; 2] {
+    let columns = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+    let lines = text.lines().count();
+    [
+        columns as f32 * GLYPH_ADVANCE[0] as f32 * scale,
+        lines as f32 * GLYPH_ADVANCE[1] as f32 * scale,
+    ]
+}
+
+// The index of a character in the font
+fn glyph_index(character: char) -> u32 {
+    let last = FIRST_CHARACTER as u32 + FONT.len() as u32 - 1;
+    match character as u32 {
+        code if (FIRST_CHARACTER as u32..=last).contains(&code) => code - FIRST_CHARACTER as u32,
+        _ => UNKNOWN_CHARACTER as u32 - FIRST_CHARACTER as u32,
+    }
+}
+
+// One glyph per character that draws something, spaces only move the next character along
+fn layout(text: &str, position: [f32; 2], scale: f32, color: [f32; 4]) -> Vec<Glyph> {
+    let mut glyphs = vec![];
+    for (line_number, line) in text.lines().enumerate() {
+        let y = position[1] + (line_number as u32 * GLYPH_ADVANCE[1]) as f32 * scale;
+        for (column, character) in line.chars().enumerate() {
+            if character == ' ' {
+                continue;
+            }
+            glyphs.push(Glyph {
+                position: [position[0] + (column as u32 * GLYPH_ADVANCE[0]) as f32 * scale, y],
+                character: glyph_index(character),
+                scale,
+                color,
+            });
+        }
+    }
+    glyphs
+}
+
+fn shared_wgsl() -> String {
+    SharedDefinitions::new()
+        .constant("GLYPH_WIDTH", GLYPH_WIDTH)
+        .constant("GLYPH_HEIGHT", GLYPH_HEIGHT)
+        .structure::<Glyph>()
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bind_group_layout_builder::BindGroupLayoutBuilder;
+
+    #[test]
+    fn the_font_fits_the_glyph_size() {
+        for (i, glyph) in FONT.iter().enumerate() {
+            assert!(glyph.iter().all(|&row| row < 1 << GLYPH_WIDTH), "Glyph {} is wider than the font", i);
+        }
+        assert!(FONT[glyph_index(' ') as usize].iter().all(|&row| row == 0));
+        assert!(FONT[glyph_index('~') as usize].iter().any(|&row| row != 0));
+    }
+
+    #[test]
+    fn the_shader_binds_the_glyphs_font_and_screen() {
+        let source = Preprocessor::new().include("text_shared.wgsl", &shared_wgsl()).process(TEXT_SHADER).unwrap();
+        let bindings = ShaderReflection::new(&source).unwrap().bindings(&["vs_text", "fs_text"]).unwrap();
+        assert_eq!(
+            bindings,
+            BindGroupLayoutBuilder::new()
+                .storage("glyphs", 0, true, wgpu::ShaderStages::VERTEX)
+                .storage("font", 1, true, wgpu::ShaderStages::FRAGMENT)
+                .uniform("screen", 2, wgpu::ShaderStages::VERTEX)
+        );
+    }
+
+    #[test]
+    fn text_is_laid_out_in_lines() {
+        let glyphs = layout("Hi 2\nx\u{e9}", [10.0, 20.0], 2.0, [1.0; 4]);
+        let positions: Vec<[f32; 2]> = glyphs.iter().map(|glyph| glyph.position).collect();
+        assert_eq!(positions, [[10.0, 20.0], [22.0, 20.0], [46.0, 20.0], [10.0, 38.0], [22.0, 38.0]]);
+        assert_eq!(glyphs[0].character, glyph_index('H'));
+        assert_eq!(glyphs[4].character, glyph_index(UNKNOWN_CHARACTER));
+        assert_eq!(text_size("Hi 2\nx", 2.0), [48.0, 36.0]);
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use winit::{
+    dpi::PhysicalSize,
+    event_loop::{EventLoop, EventLoopBuilder},
+    window::{Window, WindowBuilder},
+};
+use crate::adapter::{select_adapter, AdapterOptions};
+
+// Sent to the event loop every frame interval, the simulations redraw when it arrives
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTimer;
+
+// How long the frame timer waits between frames, shared with its thread so it can change while the window is open
+#[derive(Debug, Clone)]
+pub struct FrameInterval(Arc<AtomicU64>);
+
+impl FrameInterval {
+    pub fn new(interval: Duration) -> Self {
+        Self(Arc::new(AtomicU64::new(interval.as_micros() as u64)))
+    }
+
+    pub fn get(&self) -> Duration {
+        Duration::from_micros(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, interval: Duration) {
+        self.0.store(interval.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+// Opens a window of `size` and starts a thread that wakes its event loop every `interval`
+pub fn create_window(size: [u32; 2], interval: FrameInterval) -> (EventLoop<FrameTimer>, Window) {
+    let event_loop = EventLoopBuilder::<FrameTimer>::with_user_event()
+        .build()
+        .unwrap();
+    let window = WindowBuilder::new()
+        .with_inner_size(PhysicalSize::new(size[0], size[1]))
+        .build(&event_loop)
+        .unwrap();
+    let event_loop_proxy = event_loop.create_proxy();
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval.get());
+        if event_loop_proxy.send_event(FrameTimer).is_err() {
+            break; // The event loop has exited
+        }
+    });
+
+    (event_loop, window)
+}
+
+// The device, and the surface it draws to when there is a window
+pub struct GpuContext<'a> {
+    pub surface: Option<wgpu::Surface<'a>>, // None when running headless
+    pub device: Arc<wgpu::Device>, // Shared so a simulation can keep its own handle
+    pub queue: Arc<wgpu::Queue>,
+    pub config: wgpu::SurfaceConfiguration, // Without a window it still gives the render pipelines a format
+}
+
+impl<'a> GpuContext<'a> {
+    // `size` is the size of the window, or of the screen the render pipelines would draw without one
+    pub async fn new(
+        window: Option<&'a Window>,
+        size: PhysicalSize<u32>,
+        adapter: &AdapterOptions,
+        limits: wgpu::Limits,
+    ) -> Result<Self, String> {
+        let instance_descriptor = wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        };
+        let instance = wgpu::Instance::new(instance_descriptor);
+        let surface = window.map(|window| instance.create_surface(window).unwrap());
+
+        let adapter = select_adapter(&instance, surface.as_ref(), adapter).await?;
+        println!("{:?}", adapter.get_info());
+
+        let (device, queue) = request_device(&adapter, limits).await?;
+
+        let config = match &surface {
+            Some(surface) => {
+                let surface_capabilities = surface.get_capabilities(&adapter);
+                let surface_format = surface_capabilities
+                    .formats
+                    .iter()
+                    .copied()
+                    .find(|f| f.is_srgb())
+                    .unwrap_or(surface_capabilities.formats[0]);
+                let config = wgpu::SurfaceConfiguration {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    format: surface_format,
+                    width: size.width,
+                    height: size.height,
+                    present_mode: surface_capabilities.present_modes[0],
+                    alpha_mode: surface_capabilities.alpha_modes[0],
+                    view_formats: vec![],
+                    desired_maximum_frame_latency: 2,
+                };
+                surface.configure(&device, &config);
+                config
+            }
+            // The render pipeline still needs a format
+            None => wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                width: size.width,
+                height: size.height,
+                present_mode: wgpu::PresentMode::Fifo,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: vec![],
+                desired_maximum_frame_latency: 2,
+            },
+        };
+
+        Ok(GpuContext {
+            surface,
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+            config,
+        })
+    }
+}
+
+// Opens the device with the limits the caller's shaders need, and timestamp queries when the adapter has them
+pub async fn request_device(adapter: &wgpu::Adapter, limits: wgpu::Limits) -> Result<(wgpu::Device, wgpu::Queue), String> {
+    let device_descriptor = wgpu::DeviceDescriptor {
+        required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY, // Used by --profile when available
+        required_limits: limits,
+        label: Some("Device"),
+    };
+    adapter
+        .request_device(&device_descriptor, None)
+        .await
+        .map_err(|error| format!("Could not open the adapter: {}", error))
+}
@@ -0,0 +1,50 @@
+// Times a collision step without a window at a few particle counts and grid sizes, the sort on the CPU and the collisions on the GPU
+// `cargo bench` uses the default adapter, WGPU_ADAPTER_NAME picks another one, like llvmpipe for the software adapter
+// The shader is built for the particle count and grid, so every size builds a simulation of its own
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use renderer_backend::adapter::adapter_available;
+use rust_collisions::{AdapterOptions, Sizes, State, GRID_SIZE, PARTICLE_COUNT_X, PARTICLE_COUNT_Y};
+
+const PARTICLE_SCALES: [f32; 3] = [0.5, 1.0, 2.0]; // Multiplies the particles in each direction, so a quarter, the same and four times as many
+const GRID_SCALES: [f32; 3] = [0.5, 1.0, 2.0]; // Multiplies the default number of cells in each direction
+const SAMPLE_SIZE: usize = 20;
+const BENCH_SEED: u64 = 1;
+
+fn collisions(c: &mut Criterion) {
+    if !pollster::block_on(adapter_available(&AdapterOptions::default())) {
+        eprintln!("There is no adapter, skipping the collisions benchmark");
+        return;
+    }
+
+    for grid_scale in GRID_SCALES {
+        let grid = scale([GRID_SIZE.0, GRID_SIZE.1], grid_scale);
+
+        let mut group = c.benchmark_group(format!("collisions {}x{} grid", grid[0], grid[1]));
+        group.sample_size(SAMPLE_SIZE);
+
+        for particle_scale in PARTICLE_SCALES {
+            let particles = scale([PARTICLE_COUNT_X, PARTICLE_COUNT_Y], particle_scale);
+            let sizes = Sizes { particles, grid, ..Sizes::default() };
+            let mut state = pollster::block_on(State::headless_with(sizes, BENCH_SEED, &AdapterOptions::default())).unwrap();
+
+            let count = particles[0] * particles[1];
+            // Particles per second
+            group.throughput(Throughput::Elements(count as u64));
+            group.bench_with_input(BenchmarkId::new("step", count), &count, |b, _| {
+                b.iter(|| {
+                    state.step();
+                    state.wait();
+                })
+            });
+        }
+
+        group.finish();
+    }
+}
+
+fn scale(size: [u32; 2], scale: f32) -> [u32; 2] {
+    size.map(|cells| ((cells as f32 * scale) as u32).max(1))
+}
+
+criterion_group!(benches, collisions);
+criterion_main!(benches);
@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+use renderer_backend::cli::{self, backend, expand_configs, log_level, number, parse_flags, size, value};
+use crate::{AdapterOptions, Sizes};
+
+pub const HELP: &str = "\
+Elastic collisions between 2D particles
+
+Usage: rust-collisions [OPTIONS]
+
+Options:
+  --window WxH        The size of the window and of the box the particles bounce around in [default: 1200x600]
+  --particles XxY     How many particles there are in the x and y direction [default: 25x25]
+  --grid XxY          How many grid cells to divide the screen into [default: 20x10]
+  --config FILE       Read options from a file, one `name value` per line, the command line overrides it
+  --seed N            The seed of the initial velocities [default: picked at random]
+  --backend NAME      gpu, or cpu for the software adapter [default: gpu]
+  --adapter NAME      Run on the adapter whose name contains NAME, overrides --backend
+  --headless          Run --steps steps without a window
+  --steps N           How many steps to run with --headless
+  --output DIR        Where --headless writes particles.csv and snapshots are saved
+  --log-level LEVEL   off, error, warn, info, debug or trace [default: RUST_LOG]
+  --hot-reload        Rebuild the pipelines when the shader is saved
+  --profile           Print how long each pass takes
+  -h, --help          Print this help
+";
+
+const MAX_PARTICLES: u64 = 1 << 20; // Two storage buffers of this many vec2s fit in the default limits
+const MAX_CELLS: u64 = 1 << 22; // The lookup and count buffers hold an i32 per cell, 16 MiB each fits in the default limits
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    pub sizes: Sizes,
+    pub seed: Option<u64>,
+    pub adapter: AdapterOptions,
+    pub headless: bool,
+    pub steps: Option<u64>,
+    pub output: Option<PathBuf>,
+    pub log_level: Option<String>,
+    pub hot_reload: bool,
+    pub profile: bool,
+}
+
+pub type Command = cli::Command<Options>;
+
+// Reads the options from the arguments after the program name, --config files are read here too
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    parse_expanded(&expand_configs(args)?)
+}
+
+fn parse_expanded(args: &[String]) -> Result<Command, String> {
+    let command = parse_flags(args, |options: &mut Options, flag, args| {
+        match flag {
+            "--window" => options.sizes.screen = size(&value(args, flag)?, flag)?,
+            "--particles" => {
+                let particles = size(&value(args, flag)?, flag)?;
+                if particles[0] as u64 * particles[1] as u64 > MAX_PARTICLES {
+                    return Err(format!("{}x{} particles is too many, there can be at most {}", particles[0], particles[1], MAX_PARTICLES));
+                }
+                options.sizes.particles = particles;
+            }
+            "--grid" => {
+                let grid = size(&value(args, flag)?, flag)?;
+                if grid[0] as u64 * grid[1] as u64 > MAX_CELLS {
+                    return Err(format!("A {}x{} grid has too many cells, it can have at most {}", grid[0], grid[1], MAX_CELLS));
+                }
+                options.sizes.grid = grid;
+            }
+            "--seed" => options.seed = Some(number(&value(args, flag)?, flag)?),
+            "--backend" => options.adapter.backend = backend(&value(args, flag)?)?,
+            "--adapter" => options.adapter.name = Some(value(args, flag)?),
+            "--headless" => options.headless = true,
+            "--steps" => options.steps = Some(number(&value(args, flag)?, flag)?),
+            "--output" => options.output = Some(PathBuf::from(value(args, flag)?)),
+            "--log-level" => options.log_level = Some(log_level(&value(args, flag)?)?),
+            "--hot-reload" => options.hot_reload = true,
+            "--profile" => options.profile = true,
+            _ => return Err(format!("Unknown option {:?}", flag)),
+        }
+        Ok(())
+    })?;
+
+    // Options that only make sense together
+    if let Command::Run(options) = &command {
+        if options.headless != options.steps.is_some() {
+            return Err("--headless and --steps go together, the steps say when a run without a window stops".to_string());
+        }
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use renderer_backend::adapter::Backend;
+
+    fn run(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        match parse(&args)? {
+            Command::Run(options) => Ok(*options),
+            Command::Help => Err("help".to_string()),
+        }
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        assert_eq!(run("").unwrap(), Options::default());
+        let options = run("--window 800x400 --particles 40x30 --grid 16x8 --seed 42 --backend cpu --headless --steps 100 --output out").unwrap();
+        assert_eq!(options.sizes, Sizes { screen: [800, 400], particles: [40, 30], grid: [16, 8] });
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.adapter.backend, Backend::Cpu);
+        assert_eq!(options.steps, Some(100));
+        assert_eq!(run("--help --unknown"), Err("help".to_string()));
+    }
+
+    #[test]
+    fn invalid_options_are_errors() {
+        for args in [
+            "--unknown",
+            "--seed",
+            "--seed -1",
+            "--particles 25",
+            "--particles 0x25",
+            "--particles 2000x2000",
+            "--grid 20x",
+            "--grid 100000x100000",
+            "--backend tpu",
+            "--log-level loud",
+            "--headless",
+            "--steps 10",
+        ] {
+            assert!(run(args).is_err(), "{:?} should be an error", args);
+        }
+    }
+
+    #[test]
+    fn config_files_are_overridden_by_the_command_line() {
+        let mut args = cli::parse_config("particles = 10x10\nseed 7\nprofile\n").unwrap();
+
+        args.extend(["--seed".to_string(), "8".to_string()]);
+        let Command::Run(options) = parse_expanded(&args).unwrap() else { panic!() };
+        assert_eq!(options.sizes.particles, [10, 10]);
+        assert_eq!(options.seed, Some(8));
+        assert!(options.profile);
+    }
+}
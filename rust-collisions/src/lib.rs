@@ -0,0 +1,1058 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use renderer_backend::{
+    bind_group_layout_builder::{create_bind_group_layouts, PipelineBindings}, buffers::read_buffer, compute_pipeline_builder::ComputePipelineBuilder,
+    gui::Gui, pipeline_builder::PipelineBuilder, preprocessor::Preprocessor, profiler::Profiler, shader_watcher::ShaderWatcher,
+    shader_types::SharedDefinitions, text::TextRenderer, window::GpuContext, app::{self, hud_text, write_particles, Entry, Simulation},
+    cli::{init_logging, options_or_exit},
+};
+pub use renderer_backend::adapter::{AdapterOptions, Backend};
+mod cli;
+use cli::Command;
+mod panel;
+use panel::{Action, Panel};
+// use cgmath::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use wgpu::{
+    // core::device::global,
+    util::{BufferInitDescriptor, DeviceExt},
+    BufferUsages,
+};
+use winit::{
+    dpi::PhysicalSize,
+    event::*,
+    keyboard::{KeyCode, PhysicalKey},
+    window::Window,
+};
+
+const SHADER: &str = include_str!("shaders/shader.wgsl"); // Embedded so the binary runs from any directory
+const SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/shader.wgsl"); // Watched with --hot-reload
+
+pub const SCREEN_SIZE: (u32, u32) = (1200, 600);
+const TIME_BETWEEN_FRAMES: u64 = 10;
+const PROFILE_INTERVAL: u32 = 60; // How often the pass timings are printed with --profile, in frames
+const PROFILE_WINDOW: usize = 60; // How many frames the pass timings are averaged over
+const HUD_COLOR: [f32; 4] = [1.0, 0.8, 0.2, 1.0];
+pub const PARTICLE_COUNT_X: u32 = 25;
+pub const PARTICLE_COUNT_Y: u32 = 25;
+// const OFFSET: (f32, f32) = (10.0, 8.0); // How much to offset all the particle's starting positions
+const PADDING: f32 = 25.0;
+pub const GRID_SIZE: (u32, u32) = (20, 10); // How many grid cells to divide the screen into
+const PARTICLE_RADIUS: f32 = 6.0;
+
+const WORKGROUP_SIZE: u32 = 10;
+// The sizes picked on the command line, the shader is built for them so they can't change while it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sizes {
+    pub screen: [u32; 2], // The size of the box the particles bounce around in, the same as the window
+    pub particles: [u32; 2], // How many particles there are in the x and y direction
+    pub grid: [u32; 2], // How many grid cells to divide the screen into
+}
+
+impl Default for Sizes {
+    fn default() -> Self {
+        Sizes {
+            screen: [SCREEN_SIZE.0, SCREEN_SIZE.1],
+            particles: [PARTICLE_COUNT_X, PARTICLE_COUNT_Y],
+            grid: [GRID_SIZE.0, GRID_SIZE.1],
+        }
+    }
+}
+
+impl Sizes {
+    pub fn particle_count(&self) -> u32 {
+        self.particles[0] * self.particles[1]
+    }
+
+    fn cell_count(&self) -> usize {
+        self.grid[0] as usize * self.grid[1] as usize
+    }
+
+    // The command line keeps the sizes small, but other callers can ask for buffers the device can't bind
+    fn check_limits(&self, limits: &wgpu::Limits) -> Result<(), String> {
+        let largest = (self.cell_count() as u64 * std::mem::size_of::<i32>() as u64)
+            .max(self.particle_count() as u64 * std::mem::size_of::<[f32; 2]>() as u64);
+        if largest > limits.max_storage_buffer_binding_size as u64 {
+            return Err(format!(
+                "{}x{} particles in a {}x{} grid need a {} byte buffer, the device can bind at most {}",
+                self.particles[0], self.particles[1], self.grid[0], self.grid[1], largest, limits.max_storage_buffer_binding_size
+            ));
+        }
+        Ok(())
+    }
+
+    fn dispatch_size(&self) -> (u32, u32) {
+        (
+            self.particles[0].div_ceil(WORKGROUP_SIZE),
+            self.particles[1].div_ceil(WORKGROUP_SIZE),
+        )
+    }
+}
+
+// The physical parameters, the panel can change them while it runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Physics {
+    pub restitution: f32, // How much of the speed along the line between two particles is kept when they collide, 1 is elastic
+    pub wall_restitution: f32, // How much of their speed the particles keep when they bounce off the walls
+    pub gravity: f32, // Added to the downwards velocity every step
+    pub radius: f32, // The radius of every particle
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Physics {
+            restitution: 1.0,
+            wall_restitution: 1.0,
+            gravity: 0.0,
+            radius: PARTICLE_RADIUS,
+        }
+    }
+}
+
+// Particles on a grid with random velocities from the seed, returns their positions, velocities and radii
+fn initial_particles(sizes: &Sizes, seed: u64, radius: f32) -> (Vec<[f32; 2]>, Vec<[f32; 2]>, Vec<f32>) {
+    // Every random initial condition comes from the seed, so a run can be repeated
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    let mut particle_positions = vec![];
+    let mut particle_velocities = vec![];
+    let mut particle_radii = vec![];
+    let screen = sizes.screen;
+    for i in 0..sizes.particles[0] {
+        for j in 0..sizes.particles[1] {
+            // let x = SCREEN_SIZE.0 as f32 / (PARTICLE_COUNT_X + 1) as f32 * i as f32 + OFFSET.0;
+            // let y = SCREEN_SIZE.1 as f32 / (PARTICLE_COUNT_Y + 1) as f32 * j as f32 + OFFSET.1;
+
+            let x = (i as f32 + 0.5) * (screen[0] as f32 - 2.0 * PADDING)
+                / sizes.particles[0] as f32
+                + PADDING;
+            let y = (j as f32 + 0.5) * (screen[1] as f32 - 2.0 * PADDING)
+                / sizes.particles[1] as f32
+                + PADDING;
+
+            particle_positions.push([x, y]);
+
+            // particle_velocities.push([x / SCREEN_SIZE.0 as f32 * 2.0 - 1.0, y / SCREEN_SIZE.1 as f32 * 2.0 - 1.0]);
+            particle_velocities.push([
+                2.0 * (rng.gen::<f32>() * 2.0 - 1.0),
+                2.0 * (rng.gen::<f32>() * 2.0 - 1.0),
+            ]);
+            // particle_velocities.push([0.0, 0.0]);
+            particle_radii.push(radius);
+        }
+    }
+    (particle_positions, particle_velocities, particle_radii)
+}
+
+// The constants the shader shares with this file, included in the shader as "shared.wgsl"
+fn shared_wgsl(sizes: &Sizes) -> String {
+    SharedDefinitions::new()
+        .constant("SCREEN_SIZE", [sizes.screen[0] as f32, sizes.screen[1] as f32])
+        .constant("WORKGROUP_SIZE", WORKGROUP_SIZE)
+        .constant("PARTICLE_COUNT_X", sizes.particles[0])
+        .constant("PARTICLE_COUNT_Y", sizes.particles[1])
+        .constant("GRID_SIZE", [sizes.grid[0] as f32, sizes.grid[1] as f32])
+        .build()
+}
+
+fn preprocess_shader(source: &str, sizes: &Sizes) -> Result<String, String> {
+    Preprocessor::new()
+        .include("shared.wgsl", &shared_wgsl(sizes))
+        .process(source)
+}
+
+pub struct State<'a> {
+    window: Option<&'a Window>, // None when running headless
+    surface: Option<wgpu::Surface<'a>>, // None when running headless
+    gui: Option<Gui>, // The parameter panel, None when running headless
+    panel: Panel,
+    text: Option<TextRenderer>, // Draws the HUD, None when running headless
+    hud_visible: bool,
+    paused: bool,
+    step_requested: bool, // Runs one step while paused
+    seed: u64, // Reset starts the particles over from it
+    steps: u64, // How many steps have run
+    output: PathBuf, // Where snapshots are saved
+    physics: Physics,
+    restitution_buffer: wgpu::Buffer,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    config: wgpu::SurfaceConfiguration,
+    size: PhysicalSize<u32>,
+    sizes: Sizes,
+    shader_source: String, // The shader after preprocessing
+    shader_watcher: Option<ShaderWatcher>, // Only set with --hot-reload
+    profiler: Profiler, // Disabled unless --profile is passed
+    bind_group_layouts: HashMap<&'static str, PipelineBindings>, // One per pipeline, with only the buffers it uses
+    render_pipeline: wgpu::RenderPipeline,
+    compute_pipeline: wgpu::ComputePipeline,
+    render_bind_group: wgpu::BindGroup,
+    compute_bind_group: wgpu::BindGroup,
+    frame_count: u32,
+    frame_count_buffer: wgpu::Buffer,
+    particle_positions: Vec<[f32; 2]>,
+    particle_positions_buffer: wgpu::Buffer,
+    particle_radii: Vec<f32>,
+    particle_radii_buffer: wgpu::Buffer,
+    particle_velocities: Vec<[f32; 2]>,
+    particle_velocities_buffer: wgpu::Buffer,
+    particle_lookup: Vec<i32>,
+    particle_lookup_buffer: wgpu::Buffer,
+    particle_counts: Vec<i32>,
+    particle_counts_buffer: wgpu::Buffer,
+    position_reading_buffer: wgpu::Buffer,
+    velocity_reading_buffer: wgpu::Buffer,
+}
+
+impl<'a> State<'a> {
+    // fn pos_to_grid_index(&self, pos: [f32; 2]) -> i32 {
+    //     let (x, y) = self.pos_to_grid(pos);
+
+    //     x + y * GRID_SIZE.0
+    // }
+
+    fn pos_to_grid(&self, pos: [f32; 2]) -> (i32, i32) {
+        let (screen, grid) = (self.sizes.screen, self.sizes.grid);
+        let x = (pos[0] / screen[0] as f32 * grid[0] as f32)
+            .min(grid[0] as f32 - 1.0)
+            .max(0.0) as i32;
+        let y = (pos[1] / screen[1] as f32 * grid[1] as f32)
+            .min(grid[1] as f32 - 1.0)
+            .max(0.0) as i32;
+
+        (x, y)
+    }
+
+    fn update_position_from_buffer(&mut self) {
+        self.particle_positions = read_buffer(&self.device, &self.queue, &self.particle_positions_buffer, &self.position_reading_buffer);
+    }
+
+    // Also moves the particles on the CPU, bouncing them off the walls
+    fn update_velocities_from_buffer(&mut self) {
+        let velocities: Vec<[f32; 2]> = read_buffer(&self.device, &self.queue, &self.particle_velocities_buffer, &self.velocity_reading_buffer);
+        let screen = self.sizes.screen;
+        // Update the particle velocities
+        for (i, velocity) in velocities.iter().enumerate().take(self.particle_velocities.len()) {
+            self.particle_velocities[i] = [velocity[0], velocity[1] + self.physics.gravity];
+
+            // Move the particle
+            if self.particle_positions[i][0] < 0.0 {
+                self.particle_positions[i][0] = 0.0;
+                self.particle_velocities[i][0] = -self.particle_velocities[i][0] * self.physics.wall_restitution;
+            }
+            if self.particle_positions[i][0] > screen[0] as f32 {
+                self.particle_positions[i][0] = screen[0] as f32;
+                self.particle_velocities[i][0] = -self.particle_velocities[i][0] * self.physics.wall_restitution;
+            }
+
+            if self.particle_positions[i][1] < 0.0 {
+                self.particle_positions[i][1] = 0.0;
+                self.particle_velocities[i][1] = -self.particle_velocities[i][1] * self.physics.wall_restitution;
+            }
+            if self.particle_positions[i][1] > screen[1] as f32 {
+                self.particle_positions[i][1] = screen[1] as f32;
+                self.particle_velocities[i][1] = -self.particle_velocities[i][1] * self.physics.wall_restitution;
+            }
+            self.particle_positions[i][0] += self.particle_velocities[i][0];
+            self.particle_positions[i][1] += self.particle_velocities[i][1];
+        }
+    }
+
+    // NOTE: Add how many particles are in each grid cell to the particle_lookup buffer. Right now, it sometimes searches more in the shader than it needs to because it doesn't always know the end index
+    fn sort_particles(&mut self) {
+        // Update the particle positions and velocities from the buffers
+        self.update_position_from_buffer();
+        self.update_velocities_from_buffer();
+
+        // Map all particles to their grid cell
+        let grid_size = [self.sizes.grid[0] as i32, self.sizes.grid[1] as i32];
+        let mut index_map: Vec<Vec<Vec<i32>>> =
+            vec![vec![vec![]; grid_size[1] as usize]; grid_size[0] as usize];
+        for i in 0..self.particle_positions.len() {
+            let grid = self.pos_to_grid(self.particle_positions[i]);
+            index_map[grid.0 as usize][grid.1 as usize].push(i as i32);
+        }
+
+        // Create a new list of particles
+        let mut new_positions: Vec<[f32; 2]> = vec![];
+        let mut new_velocities: Vec<[f32; 2]> = vec![];
+        let mut new_radii: Vec<f32> = vec![];
+        let mut lookup_table = vec![-1; self.sizes.cell_count()];
+        let mut new_counts: Vec<i32> = vec![0; self.sizes.cell_count()];
+
+        // Iterate over all grid cells
+        for i in 0..grid_size[0] {
+            for j in 0..grid_size[1] {
+                let grid_index = i + j * grid_size[0];
+                let mut index = -1;
+
+                // Iterate over all particles in the grid cell
+                for &particle_index in &index_map[i as usize][j as usize] {
+                    let particle_index = particle_index as usize;
+                    new_positions.push(self.particle_positions[particle_index]);
+                    new_velocities.push(self.particle_velocities[particle_index]);
+                    new_radii.push(self.particle_radii[particle_index]);
+                    if index == -1 {
+                        index = new_positions.len() as i32 - 1;
+                    }
+                    new_counts[grid_index as usize] += 1;
+                }
+
+                lookup_table[grid_index as usize] = index;
+            }
+        }
+
+        self.particle_positions = new_positions;
+        self.particle_velocities = new_velocities;
+        self.particle_radii = new_radii;
+        self.particle_lookup = lookup_table;
+        self.particle_counts = new_counts;
+
+        self.queue.write_buffer(
+            &self.particle_positions_buffer,
+            0,
+            bytemuck::cast_slice(&self.particle_positions),
+        );
+
+        self.queue.write_buffer(
+            &self.particle_radii_buffer,
+            0,
+            bytemuck::cast_slice(&self.particle_radii),
+        );
+
+        self.queue.write_buffer(
+            &self.particle_velocities_buffer,
+            0,
+            bytemuck::cast_slice(&self.particle_velocities),
+        );
+
+        self.queue.write_buffer(
+            &self.particle_lookup_buffer,
+            0,
+            bytemuck::cast_slice(&self.particle_lookup),
+        );
+
+        self.queue.write_buffer(
+            &self.particle_counts_buffer,
+            0,
+            bytemuck::cast_slice(&self.particle_counts),
+        );
+    }
+
+    async fn new(window: &'a Window, sizes: Sizes, seed: u64, adapter: &AdapterOptions) -> Result<Self, String> {
+        Self::create(Some(window), sizes, seed, adapter).await
+    }
+
+    // A simulation without a window, for benchmarks and tests, it can step but not render
+    // None when there is no adapter to run it on
+    pub async fn headless_with(sizes: Sizes, seed: u64, adapter: &AdapterOptions) -> Result<Self, String> {
+        let mut state = Self::create(None, sizes, seed, adapter).await?;
+        state.create_bind_groups();
+        Ok(state)
+    }
+
+    async fn create(window: Option<&'a Window>, sizes: Sizes, seed: u64, adapter: &AdapterOptions) -> Result<Self, String> {
+        let size = window.map_or(PhysicalSize::new(sizes.screen[0], sizes.screen[1]), |window| window.inner_size());
+        let GpuContext { surface, device, queue, config } = GpuContext::new(window, size, adapter, wgpu::Limits::default()).await?;
+        sizes.check_limits(&device.limits())?;
+
+        let Pipelines { shader_source, bind_group_layouts, render_pipeline, compute_pipeline } =
+            build_pipelines(&device, preprocess_shader(SHADER, &sizes)?, config.format)?;
+
+        // Create temporary bind groups
+        let temp_render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Temporary Render Bind Group"),
+            layout: &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[],
+                label: Some("Temporary Render Bind Group Layout"),
+            }),
+            entries: &[],
+        });
+
+        let temp_compute_render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Temporary Compute Bind Group"),
+            layout: &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[],
+                label: Some("Temporary Compute Bind Group Layout"),
+            }),
+            entries: &[],
+        });
+
+        // Create particle data
+        let physics = Physics::default();
+        let (particle_positions, particle_velocities, particle_radii) = initial_particles(&sizes, seed, physics.radius);
+        let particle_lookup: Vec<i32> = vec![0; sizes.cell_count()];
+        let particle_counts: Vec<i32> = vec![0; sizes.cell_count()];
+
+        // Buffer for particles
+        let particle_positions_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Particle Positions Buffer Data"),
+            contents: bytemuck::cast_slice(&particle_positions),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        });
+        let position_reading_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Position Reading Buffer Data"),
+            contents: bytemuck::cast_slice(&particle_positions),
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        });
+        let particle_velocities_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Particle Velocities Buffer Data"),
+            contents: bytemuck::cast_slice(&particle_velocities),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+        });
+        let velocity_reading_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Velocity Reading Buffer Data"),
+            contents: bytemuck::cast_slice(&particle_velocities),
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        });
+        let particle_radii_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Particle Radii Buffer Data"),
+            contents: bytemuck::cast_slice(&particle_radii),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let particle_lookup_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Particle Lookup Buffer Data"),
+            contents: bytemuck::cast_slice(&particle_lookup),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+        let particle_counts_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Particle Counts Buffer Data"),
+            contents: bytemuck::cast_slice(&particle_counts),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+
+        // Write data to buffers
+        queue.write_buffer(
+            &particle_positions_buffer,
+            0,
+            bytemuck::cast_slice(&particle_positions),
+        );
+        queue.write_buffer(
+            &particle_radii_buffer,
+            0,
+            bytemuck::cast_slice(&particle_radii),
+        );
+        queue.write_buffer(
+            &particle_velocities_buffer,
+            0,
+            bytemuck::cast_slice(&particle_velocities),
+        );
+        queue.write_buffer(
+            &particle_lookup_buffer,
+            0,
+            bytemuck::cast_slice(&particle_lookup),
+        );
+        queue.write_buffer(
+            &particle_counts_buffer,
+            0,
+            bytemuck::cast_slice(&particle_counts),
+        );
+
+        let restitution_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Restitution Buffer"),
+            contents: bytemuck::cast_slice(&[physics.restitution]),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+
+        // Buffer for the frame count
+        let frame_count_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Frame Count Buffer"),
+            contents: bytemuck::cast_slice(&[0]),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+
+        Ok(Self {
+            window,
+            gui: window.map(|window| Gui::new(&device, config.format, window)),
+            panel: Panel::new(),
+            text: window.map(|_| TextRenderer::new(&device, config.format, [config.width, config.height])),
+            hud_visible: true,
+            paused: false,
+            step_requested: false,
+            seed,
+            steps: 0,
+            output: PathBuf::from("."),
+            physics,
+            restitution_buffer,
+            sizes,
+            shader_source,
+            shader_watcher: None,
+            profiler: Profiler::disabled(),
+            bind_group_layouts,
+            surface,
+            device,
+            queue,
+            config,
+            size,
+            render_pipeline,
+            compute_pipeline,
+            render_bind_group: temp_render_bind_group,
+            compute_bind_group: temp_compute_render_bind_group,
+            frame_count: 0,
+            frame_count_buffer,
+            particle_positions,
+            particle_positions_buffer,
+            particle_radii,
+            particle_radii_buffer,
+            particle_velocities,
+            particle_velocities_buffer,
+            particle_lookup,
+            particle_lookup_buffer,
+            particle_counts,
+            particle_counts_buffer,
+            position_reading_buffer,
+            velocity_reading_buffer,
+        })
+    }
+
+    // Writes the positions and velocities last read back to a CSV file
+    fn write_particles(&self, path: &Path) -> Result<(), String> {
+        let rows = self.particle_positions.iter().zip(&self.particle_velocities).map(|(position, velocity)| [position[0], position[1], velocity[0], velocity[1]]);
+        write_particles(path, ["x", "y", "vx", "vy"], rows)
+    }
+
+    // Swaps in pipelines from build_pipelines, the buffers are kept and bound to them
+    fn set_pipelines(&mut self, pipelines: Pipelines) {
+        self.shader_source = pipelines.shader_source;
+        self.bind_group_layouts = pipelines.bind_group_layouts;
+        self.render_pipeline = pipelines.render_pipeline;
+        self.compute_pipeline = pipelines.compute_pipeline;
+        self.create_bind_groups();
+    }
+
+    // The buffers behind the variables in the shader, bind groups find their buffers by these names
+    fn named_buffers(&self) -> HashMap<&'static str, &wgpu::Buffer> {
+        HashMap::from([
+            ("frame_count", &self.frame_count_buffer),
+            ("restitution", &self.restitution_buffer),
+            ("particle_positions", &self.particle_positions_buffer),
+            ("particle_radii", &self.particle_radii_buffer),
+            ("particle_velocities", &self.particle_velocities_buffer),
+            ("particle_lookup", &self.particle_lookup_buffer),
+            ("particle_counts", &self.particle_counts_buffer),
+        ])
+    }
+
+    fn create_bind_group(&self, pipeline: &str) -> wgpu::BindGroup {
+        self.bind_group_layouts[pipeline].create_bind_group(&self.device, &self.named_buffers())
+    }
+
+    fn create_bind_groups(&mut self) {
+        self.render_bind_group = self.create_bind_group("render");
+        self.compute_bind_group = self.create_bind_group("main");
+    }
+
+    // Rebuilds the pipelines when the shader file changes, a shader that doesn't compile is reported and the old one is kept
+    fn hot_reload_shaders(&mut self) {
+        // The watcher is put back once the new pipelines have been checked against the buffers
+        let Some(mut watcher) = self.shader_watcher.take() else {
+            return;
+        };
+        let reloaded = watcher.reload(&self.device, &self.shader_source, |source| preprocess_shader(source, &self.sizes), |source| {
+            let pipelines = build_pipelines(&self.device, source, self.config.format)?;
+            let buffers = self.named_buffers();
+            pipelines.bind_group_layouts.values().try_for_each(|bindings| bindings.check_buffers(&buffers))?;
+            Ok(pipelines)
+        });
+        self.shader_watcher = Some(watcher);
+        if let Some(pipelines) = reloaded {
+            self.set_pipelines(pipelines);
+        }
+    }
+
+    // Sorts the particles into the grid and moves them, without drawing them
+    pub fn step(&mut self) {
+        self.sort_particles();
+
+        // Update the frame count buffer before rendering
+        self.queue.write_buffer(
+            &self.frame_count_buffer,
+            0,
+            bytemuck::cast_slice(&[self.frame_count]),
+        );
+
+        // Dispatch the compute shader
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Compute Encoder"),
+            });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+                timestamp_writes: self.profiler.compute_pass("collisions"),
+            });
+            compute_pass.set_pipeline(&self.compute_pipeline); // Assuming you have a compute pipeline
+            compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+            let dispatch_size = self.sizes.dispatch_size();
+            compute_pass.dispatch_workgroups(dispatch_size.0, dispatch_size.1, 1);
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.profiler.end_pass(&self.device);
+        self.steps += 1;
+    }
+
+    fn set_physics(&mut self, physics: Physics) {
+        self.physics = physics;
+        self.particle_radii.fill(physics.radius);
+        self.queue.write_buffer(&self.particle_radii_buffer, 0, bytemuck::cast_slice(&self.particle_radii));
+        self.queue.write_buffer(&self.restitution_buffer, 0, bytemuck::cast_slice(&[physics.restitution]));
+    }
+
+    // Carries out what was asked for in the panel
+    fn apply_action(&mut self, action: Action) {
+        match action {
+            Action::SetPhysics(physics) => self.set_physics(physics),
+            Action::Pause(paused) => self.paused = paused,
+            Action::Step => self.step_requested = true,
+            Action::Reset => self.reset(),
+            Action::Preset(preset) => {
+                println!("Preset: {}", preset.name());
+                self.set_physics(preset.physics());
+                self.reset();
+            }
+            Action::SaveSnapshot => {
+                self.update_velocities_from_buffer();
+                self.update_position_from_buffer();
+                let path = self.output.join(format!("snapshot-{}.csv", self.steps));
+                match self.write_particles(&path) {
+                    Ok(()) => println!("Saved a snapshot to {}", path.display()),
+                    Err(error) => eprintln!("{}", error),
+                }
+            }
+        }
+    }
+
+    // The kinetic energy of the particles, with masses from their areas like in the shader
+    fn kinetic_energy(&self) -> f64 {
+        self.particle_velocities
+            .iter()
+            .zip(&self.particle_radii)
+            .map(|(velocity, radius)| 0.5 * (std::f32::consts::PI * radius * radius * (velocity[0].powi(2) + velocity[1].powi(2))) as f64)
+            .sum()
+    }
+
+    // Blocks until the GPU has finished everything submitted so far
+    pub fn wait(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+}
+
+impl<'a> Simulation<'a> for State<'a> {
+    fn init(window: &'a Window, args: &[String]) -> Result<Self, String> {
+        let options = match cli::parse(args)? {
+            Command::Run(options) => *options,
+            Command::Help => return Err(cli::HELP.to_string()),
+        };
+        if options.headless {
+            return Err("--headless runs without a window".to_string());
+        }
+        let sizes = options.sizes;
+        println!("Particles: {}x{}, grid: {}x{} cells", sizes.particles[0], sizes.particles[1], sizes.grid[0], sizes.grid[1]);
+        let seed = options.seed.unwrap_or_else(rand::random);
+        println!("Seed: {}", seed);
+
+        let _ = window.request_inner_size(PhysicalSize::new(sizes.screen[0], sizes.screen[1]));
+        let mut state = pollster::block_on(State::new(window, sizes, seed, &options.adapter))?;
+        if let Some(output) = &options.output {
+            state.output = output.clone();
+        }
+        if options.hot_reload {
+            println!("Watching {} for changes", SHADER_PATH);
+            state.shader_watcher = Some(ShaderWatcher::new(SHADER_PATH));
+        }
+        if options.profile {
+            state.profiler = Profiler::new(&state.device, &state.queue, PROFILE_WINDOW);
+            println!("Profiling with {}", state.profiler.source());
+        }
+
+        state.create_bind_groups();
+        Ok(state)
+    }
+
+    fn frame_interval(&self) -> Duration {
+        Duration::from_millis(TIME_BETWEEN_FRAMES)
+    }
+
+    fn step(&mut self) {
+        self.hot_reload_shaders();
+
+        // The inherent step, which runs whether or not the simulation is paused
+        if !self.paused || std::mem::take(&mut self.step_requested) {
+            State::step(self);
+        }
+    }
+
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let start_time = std::time::Instant::now();
+
+        let drawable = self.surface.as_ref().expect("Rendering needs a window").get_current_texture()?;
+        let image_view_descriptor = wgpu::TextureViewDescriptor::default();
+        let image_view = drawable.texture.create_view(&image_view_descriptor);
+
+        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        };
+        let mut command_encoder = self
+            .device
+            .create_command_encoder(&command_encoder_descriptor);
+
+        // The panel is drawn over the particles in the same render pass
+        let mut actions = vec![];
+        let gui_frame = match (&mut self.gui, self.window) {
+            (Some(gui), Some(window)) => gui.prepare(&self.device, &self.queue, &mut command_encoder, window, |context| {
+                actions = self.panel.show(context, &self.physics, self.paused);
+            }),
+            _ => None,
+        };
+
+        let color_attachment = wgpu::RenderPassColorAttachment {
+            view: &image_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.75,
+                    g: 0.5,
+                    b: 0.25,
+                    a: 1.0,
+                }),
+                store: wgpu::StoreOp::Store,
+            },
+        };
+
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(color_attachment)],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: self.profiler.render_pass("render"),
+        };
+
+        {
+            let mut render_pass = command_encoder.begin_render_pass(&render_pass_descriptor);
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &self.render_bind_group, &[]); // Access using self
+            render_pass.draw(0..3, 0..1); // Draw the first triangle
+            render_pass.draw(3..6, 0..1); // Draw the second triangle
+            if let (Some(gui), Some(frame)) = (&self.gui, &gui_frame) {
+                gui.paint(&mut render_pass, frame);
+            }
+        }
+
+        // The HUD goes on top in its own pass
+        let hud = self.hud_visible.then(|| hud_text(&self.diagnostics(), self.paused));
+        if let Some(text) = &mut self.text {
+            if let Some(hud) = hud {
+                text.queue_hud(&hud, self.config.height as f32, HUD_COLOR);
+            }
+            text.draw(&self.queue, &mut command_encoder, &image_view);
+        }
+
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+        self.profiler.end_pass(&self.device);
+
+        drawable.present();
+
+        if let (Some(gui), Some(frame)) = (&mut self.gui, gui_frame) {
+            gui.finish(frame);
+        }
+        for action in actions {
+            self.apply_action(action);
+        }
+        // The particles are read back every step for the sort, so the energy is cheap to plot
+        self.panel.record_frame(self.kinetic_energy());
+
+        self.profiler.end_frame(&self.device, &self.queue);
+        if self.profiler.is_enabled() && self.profiler.table().frames().is_multiple_of(PROFILE_INTERVAL) {
+            println!("Pass timings ({}):\n{}", self.profiler.source(), self.profiler.table());
+        }
+
+        if self.frame_count.is_multiple_of(10) {
+            let elapsed_time = start_time.elapsed();
+            // let mut total_kinetic_energy = 0.0;
+            // for i in 0..self.particle_velocities.len() {
+            //     total_kinetic_energy += (self.particle_velocities[i][0] * self.particle_velocities[i][0]
+            //         + self.particle_velocities[i][1] * self.particle_velocities[i][1]).powf(0.5);
+            // }
+            // println!(
+            //     "{}",
+            //     total_kinetic_energy
+            // );
+            println!(
+                "fps: {}",
+                1.0 / elapsed_time.as_micros() as f32 * 1000.0 * 1000.0
+            );
+            self.frame_count = 0;
+        }
+
+        self.frame_count += 1;
+
+        Ok(())
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            if let Some(text) = &self.text {
+                text.resize(&self.queue, [new_size.width, new_size.height]);
+            }
+        }
+    }
+
+    fn handle_input(&mut self, event: &WindowEvent) -> bool {
+        // The panel gets the events first, the ones it uses don't reach the simulation
+        if let (Some(gui), Some(window)) = (&mut self.gui, self.window) {
+            if gui.handle_event(window, event) {
+                return true;
+            }
+        }
+
+        match event {
+            // Show or hide the parameter panel
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F1),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(gui) = &mut self.gui {
+                    gui.toggle();
+                }
+            }
+
+            // Show or hide the HUD
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F2),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => self.hud_visible = !self.hud_visible,
+
+            _ => return false,
+        }
+        true
+    }
+
+    // Starts the particles over from the seed, with the current radius
+    fn reset(&mut self) {
+        (self.particle_positions, self.particle_velocities, self.particle_radii) = initial_particles(&self.sizes, self.seed, self.physics.radius);
+        self.queue.write_buffer(&self.particle_positions_buffer, 0, bytemuck::cast_slice(&self.particle_positions));
+        self.queue.write_buffer(&self.particle_velocities_buffer, 0, bytemuck::cast_slice(&self.particle_velocities));
+        self.steps = 0;
+    }
+
+    fn diagnostics(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("FPS", format!("{:.0}", self.panel.fps())),
+            ("Particles", self.sizes.particle_count().to_string()),
+            ("Step", self.steps.to_string()),
+        ]
+    }
+}
+
+pub async fn run() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(options) = options_or_exit(cli::parse(&args), cli::HELP) else {
+        return;
+    };
+    init_logging(options.log_level.as_deref());
+
+    let sizes = options.sizes;
+    if options.headless {
+        println!("Particles: {}x{}, grid: {}x{} cells", sizes.particles[0], sizes.particles[1], sizes.grid[0], sizes.grid[1]);
+        let seed = options.seed.unwrap_or_else(rand::random);
+        println!("Seed: {}", seed);
+        if let Err(error) = run_headless(sizes, seed, &options).await {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let entry = Entry { name: "Collisions", launch, args };
+    if let Err(error) = app::run(sizes.screen, &[entry], 0) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+// Starts the collisions in a window from their command line options, for `app::run`
+pub fn launch<'w>(window: &'w Window, args: &[String]) -> Result<Box<dyn Simulation<'w> + 'w>, String> {
+    Ok(Box::new(State::init(window, args)?))
+}
+
+// The help when the options ask for it, so a launcher can print it instead of opening a window
+pub fn help(args: &[String]) -> Option<&'static str> {
+    matches!(cli::parse(args), Ok(Command::Help)).then_some(cli::HELP)
+}
+
+// Runs `--steps` collision steps without a window, with `--output` the particles are written to particles.csv there
+async fn run_headless(sizes: Sizes, seed: u64, options: &cli::Options) -> Result<(), String> {
+    let mut state = State::headless_with(sizes, seed, &options.adapter).await?;
+    for _ in 0..options.steps.unwrap_or(0) {
+        state.step();
+    }
+    // Reading the velocities also moves the particles on the CPU, so the positions are read after them
+    state.update_velocities_from_buffer();
+    state.update_position_from_buffer();
+    println!("Ran {} steps", options.steps.unwrap_or(0));
+
+    if let Some(output) = &options.output {
+        let path = output.join("particles.csv");
+        state.write_particles(&path)?;
+        println!("Wrote the particles to {}", path.display());
+    }
+    Ok(())
+}
+
+// The layouts and pipelines built from one shader, swapped in together
+struct Pipelines {
+    shader_source: String,
+    bind_group_layouts: HashMap<&'static str, PipelineBindings>,
+    render_pipeline: wgpu::RenderPipeline,
+    compute_pipeline: wgpu::ComputePipeline,
+}
+
+// Builds every pipeline from a preprocessed shader, validation errors in them go to the caller's error scope
+fn build_pipelines(device: &wgpu::Device, shader_source: String, format: wgpu::TextureFormat) -> Result<Pipelines, String> {
+    let bind_group_layouts = create_bind_group_layouts(device, &shader_source, &PIPELINES)?;
+
+    // Pass bind group layout to render pipeline builder
+    let mut render_pipeline_builder = PipelineBuilder::new();
+    render_pipeline_builder.set_shader_module(&shader_source, "vs_main", "fs_main");
+    render_pipeline_builder.set_pixel_format(format);
+    render_pipeline_builder.set_bind_group_layout(bind_group_layouts["render"].layout());
+    let render_pipeline = render_pipeline_builder.build_pipeline(device);
+
+    // Pass bind group layout to compute pipeline builder
+    let mut compute_pipeline_builder = ComputePipelineBuilder::new();
+    compute_pipeline_builder.set_shader_module(&shader_source, "main");
+    compute_pipeline_builder.set_bind_group_layout(bind_group_layouts["main"].layout());
+    let compute_pipeline = compute_pipeline_builder.build_pipeline(device);
+
+    Ok(Pipelines { shader_source, bind_group_layouts, render_pipeline, compute_pipeline })
+}
+
+// Every pipeline and the entry points it runs
+const PIPELINES: [(&str, &[&str]); 2] = [
+    ("render", &["vs_main", "fs_main"]),
+    ("main", &["main"]),
+];
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use renderer_backend::adapter::adapter_available;
+    use renderer_backend::golden::Snapshot;
+
+    #[test]
+    fn sizes_past_the_device_limits_are_errors() {
+        let limits = wgpu::Limits::default();
+        assert!(Sizes::default().check_limits(&limits).is_ok());
+        assert!(Sizes { grid: [100_000, 100_000], ..Sizes::default() }.check_limits(&limits).is_err());
+        assert!(Sizes { particles: [10_000, 10_000], ..Sizes::default() }.check_limits(&limits).is_err());
+    }
+
+    #[test]
+    fn shaders_that_dont_build_are_not_hot_reloaded() {
+        if !pollster::block_on(adapter_available(&AdapterOptions::default())) {
+            eprintln!("There is no adapter, skipping the hot reload");
+            return;
+        }
+        let mut state = pollster::block_on(State::headless_with(Sizes::default(), 1, &AdapterOptions::default())).unwrap();
+        let path = std::env::temp_dir().join(format!("rust_collisions_hot_reload_{}.wgsl", std::process::id()));
+        state.shader_watcher = Some(ShaderWatcher::new(path.to_str().unwrap()));
+        let running = state.shader_source.clone();
+
+        for broken in [SHADER.replace("fn main(", "fn renamed("), SHADER.to_string() + "fn broken( {"] {
+            std::fs::write(&path, broken).unwrap();
+            state.hot_reload_shaders();
+            assert_eq!(state.shader_source, running);
+        }
+        std::fs::write(&path, SHADER.to_string() + "// Saved again\n").unwrap();
+        state.hot_reload_shaders();
+        std::fs::remove_file(&path).unwrap();
+        assert_ne!(state.shader_source, running);
+        state.step();
+    }
+
+    #[test]
+    fn shared_constants_reach_the_shader() {
+        let source = preprocess_shader(SHADER, &Sizes::default()).unwrap();
+        let module = naga::front::wgsl::parse_str(&source).unwrap_or_else(|error| panic!("{}", error.emit_to_string(&source)));
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .unwrap_or_else(|error| panic!("{:?}", error));
+
+        let constant = |name: &str| {
+            let (_, constant) = module.constants.iter()
+                .find(|(_, constant)| constant.name.as_deref() == Some(name))
+                .unwrap_or_else(|| panic!("{} is not in the shader", name));
+            module.const_expressions[constant.init].clone()
+        };
+        assert_eq!(constant("PARTICLE_COUNT_X"), naga::Expression::Literal(naga::Literal::U32(PARTICLE_COUNT_X)));
+        assert_eq!(constant("PARTICLE_COUNT_Y"), naga::Expression::Literal(naga::Literal::U32(PARTICLE_COUNT_Y)));
+        assert_eq!(constant("WORKGROUP_SIZE"), naga::Expression::Literal(naga::Literal::U32(WORKGROUP_SIZE)));
+    }
+
+    const GOLDEN_SEED: u64 = 1;
+    const GOLDEN_STEPS: u32 = 20;
+    const GOLDEN_SUBSAMPLE: usize = 25; // Every this many particles are compared one by one
+    const GOLDEN_TOLERANCE: f32 = 1e-3;
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/golden");
+
+    fn golden_snapshot(positions: &[[f32; 2]], velocities: &[[f32; 2]]) -> Snapshot {
+        let count = positions.len() as f32;
+        let speeds: Vec<f32> = velocities.iter().map(|velocity| (velocity[0].powi(2) + velocity[1].powi(2)).sqrt()).collect();
+
+        let mut snapshot = Snapshot::new();
+        snapshot.value("count", count);
+        snapshot.value("mean_x", positions.iter().map(|position| position[0]).sum::<f32>() / count);
+        snapshot.value("mean_y", positions.iter().map(|position| position[1]).sum::<f32>() / count);
+        snapshot.value("mean_speed", speeds.iter().sum::<f32>() / count);
+        snapshot.value("kinetic_energy", speeds.iter().map(|speed| 0.5 * speed * speed).sum::<f32>());
+        for i in (0..positions.len()).step_by(GOLDEN_SUBSAMPLE) {
+            snapshot.value(format!("particle_{}_x", i), positions[i][0]);
+            snapshot.value(format!("particle_{}_y", i), positions[i][1]);
+            snapshot.value(format!("particle_{}_vx", i), velocities[i][0]);
+            snapshot.value(format!("particle_{}_vy", i), velocities[i][1]);
+        }
+        snapshot
+    }
+
+    #[test]
+    fn golden_run_matches_reference() {
+        if !pollster::block_on(adapter_available(&AdapterOptions::default())) {
+            eprintln!("There is no adapter, skipping the golden run");
+            return;
+        }
+        let mut state = pollster::block_on(State::headless_with(Sizes::default(), GOLDEN_SEED, &AdapterOptions::default()))
+            .expect("The headless simulation should build");
+        for _ in 0..GOLDEN_STEPS {
+            state.step();
+        }
+        // Reading the velocities also moves the particles on the CPU, so the positions are read after them
+        state.update_velocities_from_buffer();
+        state.update_position_from_buffer();
+        golden_snapshot(&state.particle_positions, &state.particle_velocities).check(GOLDEN_DIR, "collisions", GOLDEN_TOLERANCE);
+    }
+}
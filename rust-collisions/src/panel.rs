@@ -0,0 +1,139 @@
+use renderer_backend::gui::History;
+use crate::Physics;
+
+const PLOT_LENGTH: usize = 300; // How many samples the plots show
+
+// Sets of physical parameters that can be picked in the panel, picking one starts the particles over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    Elastic, // Nothing is lost in the collisions, the default
+    Inelastic, // Collisions and the walls take energy away until the particles settle
+    Falling, // Gravity pulls the particles into a pile on the floor
+}
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::Elastic, Preset::Inelastic, Preset::Falling];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Elastic => "elastic",
+            Preset::Inelastic => "inelastic",
+            Preset::Falling => "falling",
+        }
+    }
+
+    pub fn physics(self) -> Physics {
+        match self {
+            Preset::Elastic => Physics::default(),
+            Preset::Inelastic => Physics { restitution: 0.5, wall_restitution: 0.5, ..Physics::default() },
+            Preset::Falling => Physics { restitution: 0.8, wall_restitution: 0.6, gravity: 0.05, ..Physics::default() },
+        }
+    }
+}
+
+// What the panel asks the simulation to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    SetPhysics(Physics),
+    Pause(bool),
+    Step, // Runs one step while paused
+    Reset, // Starts the particles over from the seed
+    Preset(Preset),
+    SaveSnapshot,
+}
+
+// The parameter panel, shown over the simulation with F1
+pub struct Panel {
+    fps: History,
+    energy: History,
+    frames: u64,
+    last_frame: std::time::Instant,
+}
+
+impl Panel {
+    pub fn new() -> Self {
+        Panel {
+            fps: History::new(PLOT_LENGTH),
+            energy: History::new(PLOT_LENGTH),
+            frames: 0,
+            last_frame: std::time::Instant::now(),
+        }
+    }
+
+    // Called once per frame with the energy, the frame rate is measured between the calls
+    pub fn record_frame(&mut self, energy: f64) {
+        let now = std::time::Instant::now();
+        let seconds = now.duration_since(self.last_frame).as_secs_f64();
+        self.last_frame = now;
+        self.frames += 1;
+        if seconds > 0.0 {
+            self.fps.push(self.frames as f64, 1.0 / seconds);
+        }
+        self.energy.push(self.frames as f64, energy);
+    }
+
+    // The latest frame rate
+    pub fn fps(&self) -> f64 {
+        self.fps.latest().unwrap_or(0.0)
+    }
+
+    pub fn show(&mut self, context: &egui::Context, physics: &Physics, paused: bool) -> Vec<Action> {
+        let mut actions = vec![];
+        egui::Window::new("Simulation").default_width(280.0).show(context, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                    actions.push(Action::Pause(!paused));
+                }
+                if ui.add_enabled(paused, egui::Button::new("Step")).clicked() {
+                    actions.push(Action::Step);
+                }
+                if ui.button("Reset").clicked() {
+                    actions.push(Action::Reset);
+                }
+                if ui.button("Save snapshot").clicked() {
+                    actions.push(Action::SaveSnapshot);
+                }
+            });
+
+            egui::ComboBox::from_label("Preset").selected_text("Start a preset").show_ui(ui, |ui| {
+                for preset in Preset::ALL {
+                    if ui.selectable_label(false, preset.name()).clicked() {
+                        actions.push(Action::Preset(preset));
+                    }
+                }
+            });
+
+            ui.separator();
+            let mut changed = *physics;
+            ui.add(egui::Slider::new(&mut changed.restitution, 0.0..=1.0).text("Restitution"));
+            ui.add(egui::Slider::new(&mut changed.wall_restitution, 0.0..=1.0).text("Wall restitution"));
+            ui.add(egui::Slider::new(&mut changed.gravity, -0.2..=0.2).text("Gravity"));
+            ui.add(egui::Slider::new(&mut changed.radius, 1.0..=20.0).text("Particle radius"));
+            if changed != *physics {
+                actions.push(Action::SetPhysics(changed));
+            }
+
+            ui.separator();
+            ui.label(format!("FPS: {:.0}", self.fps()));
+            self.fps.plot(ui, "FPS");
+            ui.label(format!("Kinetic energy: {:.1}", self.energy.latest().unwrap_or(0.0)));
+            self.energy.plot(ui, "Kinetic energy");
+        });
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_keep_the_particles_in_range() {
+        assert_eq!(Preset::Elastic.physics(), Physics::default());
+        for preset in Preset::ALL {
+            let physics = preset.physics();
+            assert!((0.0..=1.0).contains(&physics.restitution), "{} gains energy in collisions", preset.name());
+            assert!((0.0..=1.0).contains(&physics.wall_restitution), "{} gains energy at the walls", preset.name());
+        }
+    }
+}
@@ -1,3 +0,0 @@
-pub mod pipeline_builder;
-pub mod compute_pipeline_builder;
-pub mod bind_group_layout_generator;
\ No newline at end of file
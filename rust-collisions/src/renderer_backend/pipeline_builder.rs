@@ -1,111 +0,0 @@
-use std::env::current_dir;
-use std::fs;
-
-use super::bind_group_layout_generator;
-
-pub struct PipelineBuilder {
-    shader_filename: String,
-    vertex_entry: String,
-    fragment_entry: String,
-    pixel_format: wgpu::TextureFormat,
-    bind_group_layout: Option<wgpu::BindGroupLayout>,
-}
-
-impl PipelineBuilder {
-
-    pub fn new() -> Self {
-        PipelineBuilder {
-            shader_filename: "dummy".to_string(),
-            vertex_entry: "dummy".to_string(),
-            fragment_entry: "dummy".to_string(),
-            pixel_format: wgpu::TextureFormat::Rgba8Unorm,
-            bind_group_layout: None,
-        }
-    }
-
-    pub fn set_bind_group_layout(&mut self, bind_group_layout: wgpu::BindGroupLayout) {
-        self.bind_group_layout = Some(bind_group_layout);
-    }
-
-    pub fn set_shader_module(&mut self, 
-        shader_filename: &str, vertex_entry: &str, fragment_entry: &str) {
-
-        self.shader_filename = shader_filename.to_string();
-        self.vertex_entry = vertex_entry.to_string();
-        self.fragment_entry = fragment_entry.to_string();
-    }
-
-    pub fn set_pixel_format(&mut self, pixel_format: wgpu::TextureFormat) {
-        self.pixel_format = pixel_format;
-    }
-
-    pub fn build_pipeline(&self, device: &wgpu::Device) -> wgpu::RenderPipeline {
-        
-        let mut filepath = current_dir().unwrap();
-        filepath.push("src/");
-        filepath.push(self.shader_filename.as_str());
-        let filepath = filepath.into_os_string().into_string().unwrap();
-        let source_code = fs::read_to_string(filepath).expect("Can't read the shader source file.");
-
-        let shader_module_descriptor = wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(source_code.into()),
-        };
-        let shader_module = device.create_shader_module(shader_module_descriptor);
-
-        // Create the bind group layout *before* building the pipeline
-        let bind_group_layout = bind_group_layout_generator::get_bind_group_layout(device, false);
-        
-        // Create the pipeline using the new bind group layout
-        let pipeline_layout_descriptor = wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        };
-        
-        let render_pipeline_layout = device.create_pipeline_layout(&pipeline_layout_descriptor);
-
-        let render_targets = [Some(wgpu::ColorTargetState {
-            format: self.pixel_format,
-            blend: Some(wgpu::BlendState::REPLACE),
-            write_mask: wgpu::ColorWrites::ALL,
-        })];
-
-        let render_pipeline_descriptor = wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: &self.vertex_entry,
-                buffers: &[],
-            },
-
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: &self.fragment_entry,
-                targets: &render_targets,
-            }),
-
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        };
-
-        device.create_render_pipeline(&render_pipeline_descriptor)
-    }
-}
\ No newline at end of file
@@ -7,13 +7,10 @@ struct Grid {
     y: i32,
 }
 
-const WORKGROUP_SIZE: u32 = 10;
+// The constants shared with lib.rs, generated by shared_wgsl()
+#include "shared.wgsl"
 
-const SCREEN_SIZE: vec2<f32> = vec2<f32>(1200.0, 600.0); // Size of the screen
 const FOV: f32 = 60.0 * 3.14159 / 180.0; // Field of view in radians
-const PARTICLE_COUNT_X: u32 = 25;
-const PARTICLE_COUNT_Y: u32 = 25;
-const GRID_SIZE: vec2<f32> = vec2<f32>(20.0, 10.0);
 
 @group(0) @binding(0) var<storage, read> frame_count: u32;
 @group(0) @binding(1) var<storage, read_write> particle_positions: array<vec2<f32>, u32(PARTICLE_COUNT_X * PARTICLE_COUNT_Y)>;
@@ -21,6 +18,7 @@ const GRID_SIZE: vec2<f32> = vec2<f32>(20.0, 10.0);
 @group(0) @binding(3) var<storage, read_write> particle_velocities: array<vec2<f32>, u32(PARTICLE_COUNT_X * PARTICLE_COUNT_Y)>;
 @group(0) @binding(4) var<storage, read> particle_lookup: array<i32, u32(GRID_SIZE.x * GRID_SIZE.y)>;
 @group(0) @binding(5) var<storage, read> particle_counts: array<i32, u32(GRID_SIZE.x * GRID_SIZE.y)>;
+@group(0) @binding(6) var<storage, read> restitution: f32; // 1 is elastic
 
 @vertex
 fn vs_main(@builtin(vertex_index) i: u32) -> VertexOutput {
@@ -84,8 +82,8 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
                         continue;
                     }
 
-                    particle_velocities[index] -= 2.0 * other_mass / (mass + other_mass) * dot(vel - other_vel, normalize(pos - other_pos) * (radius + other_radius)) / (radius + other_radius) / (radius + other_radius) * normalize(pos - other_pos) * (radius + other_radius);
-                    particle_velocities[i] -= 2.0 * mass / (mass + other_mass) * dot(other_vel - vel, normalize(other_pos - pos) * (radius + other_radius)) / (radius + other_radius) / (radius + other_radius) * normalize(other_pos - pos) * (radius + other_radius);
+                    particle_velocities[index] -= (1.0 + restitution) * other_mass / (mass + other_mass) * dot(vel - other_vel, normalize(pos - other_pos) * (radius + other_radius)) / (radius + other_radius) / (radius + other_radius) * normalize(pos - other_pos) * (radius + other_radius);
+                    particle_velocities[i] -= (1.0 + restitution) * mass / (mass + other_mass) * dot(other_vel - vel, normalize(other_pos - pos) * (radius + other_radius)) / (radius + other_radius) / (radius + other_radius) * normalize(other_pos - pos) * (radius + other_radius);
 
                     // If the particles are overlapping, move them apart
                     if d < (radius + particle_radii[i]) * (radius + particle_radii[i]) {
@@ -0,0 +1,86 @@
+// Times the solver stages and a whole step without a window at a few particle counts and grid sizes
+// `cargo bench` uses the default adapter, WGPU_ADAPTER_NAME picks another one, like llvmpipe for the software adapter
+// The buffers are sized to each count plus the free pool, so every count reallocates them and rebuilds the bind groups
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup, BenchmarkId, Criterion, Throughput};
+use renderer_backend::adapter::adapter_available;
+use rust_fluid::{grid::GridConfig, AdapterOptions, FluidConfig, FluidSimulation, RADIUS_OF_INFLUENCE, SCREEN_SIZE, TOTAL_PARTICLES};
+
+const PARTICLE_COUNTS: [u32; 3] = [TOTAL_PARTICLES / 4, TOTAL_PARTICLES / 2, TOTAL_PARTICLES];
+const GRID_SCALES: [f32; 3] = [0.5, 1.0, 2.0]; // Multiplies the number of cells derived from the radius of influence
+const SAMPLE_SIZE: usize = 20;
+const BENCH_SEED: u64 = 1;
+
+fn solver(c: &mut Criterion) {
+    if !pollster::block_on(adapter_available(&AdapterOptions::default())) {
+        eprintln!("There is no adapter, skipping the solver benchmark");
+        return;
+    }
+
+    let screen_size = [SCREEN_SIZE.0 as f32, SCREEN_SIZE.1 as f32];
+    let derived = GridConfig::from_radius(screen_size, RADIUS_OF_INFLUENCE);
+
+    for scale in GRID_SCALES {
+        let size = derived.size.map(|cells| ((cells as f32 * scale) as u32).max(1));
+        let grid = GridConfig::with_size(screen_size, RADIUS_OF_INFLUENCE, size);
+        let config = FluidConfig { grid, seed: BENCH_SEED, ..FluidConfig::default() };
+        let mut sim = pollster::block_on(FluidSimulation::headless(config, &AdapterOptions::default())).unwrap();
+
+        let mut group = c.benchmark_group(format!("fluid {}x{} grid", size[0], size[1]));
+        group.sample_size(SAMPLE_SIZE);
+
+        for count in PARTICLE_COUNTS {
+            // Particles per second
+            group.throughput(Throughput::Elements(count as u64));
+
+            // The sort starts from a cleared lookup, like it does in a step
+            bench_stage(&mut group, "sort", &mut sim, count, |sim, encoder| {
+                sim.reset_lookup(encoder);
+                sim.sort_particles(encoder);
+            });
+            bench_stage(&mut group, "density", &mut sim, count, |sim, encoder| sim.compute_density(encoder));
+            bench_stage(&mut group, "forces", &mut sim, count, |sim, encoder| sim.compute_forces(encoder));
+            bench_stage(&mut group, "move", &mut sim, count, |sim, encoder| sim.compute_move(encoder));
+            bench_stage(&mut group, "step", &mut sim, count, |sim, encoder| sim.step(encoder));
+        }
+
+        group.finish();
+    }
+}
+
+// Times `record` on particles freshly reset to rest and sorted, so every iteration starts from the same state
+// instead of from whatever the iterations before it left behind, the reset itself isn't timed
+fn bench_stage(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    sim: &mut FluidSimulation,
+    count: u32,
+    record: impl Fn(&mut FluidSimulation, &mut wgpu::CommandEncoder),
+) {
+    group.bench_with_input(BenchmarkId::new(name, count), &count, |b, &count| {
+        b.iter_custom(|iterations| {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iterations {
+                sim.reset_particles(count);
+                sim.wait();
+
+                let start = Instant::now();
+                submit(sim, &record);
+                sim.wait();
+                elapsed += start.elapsed();
+            }
+            elapsed
+        })
+    });
+}
+
+// Records one stage with an encoder of its own, like the window does for each step
+fn submit(sim: &mut FluidSimulation, record: impl FnOnce(&mut FluidSimulation, &mut wgpu::CommandEncoder)) {
+    let mut encoder = sim.device().create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    record(sim, &mut encoder);
+    sim.queue().submit(std::iter::once(encoder.finish()));
+}
+
+criterion_group!(benches, solver);
+criterion_main!(benches);
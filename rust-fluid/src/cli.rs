@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+use renderer_backend::cli::{self, backend, expand_configs, log_level, number, parse_flags, size, value};
+use crate::kernels::Kernel;
+use crate::scenarios::Scenario;
+use crate::{AdapterOptions, BASE, DEFAULT_KERNEL, NUM_DIGITS, SCREEN_SIZE, SOLVER_EXPLICIT, SOLVER_POSITION_BASED, TOTAL_PARTICLES};
+
+pub const HELP: &str = "\
+A 2D fluid simulation
+
+Usage: rust-fluid [OPTIONS]
+
+Options:
+  --window WxH        The size of the window, the simulation is stretched over it [default: 1200x600]
+  --particles N       How many particles start active in the block filling the screen, the rest are the free pool
+  --grid XxY          The cells of the neighbour grid [default: derived from the radius of influence]
+  --scenario NAME     Start with a preset: dam-break, double-dam-break, droplet, fountain, cylinder or sloshing
+  --mask FILE         Start with the fluid and walls painted in a PNG image
+  --config FILE       Read options from a file, one `name value` per line, the command line overrides it
+  --seed N            The seed of the initial conditions [default: picked at random]
+  --solver NAME       explicit or pbf [default: explicit]
+  --kernel NAME       quadratic, poly6, spiky, cubic or wendland [default: quadratic]
+  --backend NAME      gpu, or cpu for the software adapter [default: gpu]
+  --adapter NAME      Run on the adapter whose name contains NAME, overrides --backend
+  --headless          Run without a window, for --steps steps or until the playback ends
+  --steps N           How many steps to run with --headless
+  --output DIR        Where --headless writes particles.csv, snapshots are saved and relative --record paths go
+  --log-level LEVEL   off, error, warn, info, debug or trace [default: RUST_LOG]
+  --record FILE       Record the inputs to a file
+  --playback FILE     Play back a recording
+  --hot-reload        Rebuild the pipelines when the shader is saved
+  --profile           Print how long each pass takes
+  -h, --help          Print this help
+";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub window_size: [u32; 2],
+    pub particles: Option<u32>, // None keeps the default block with a quarter of the particles in the free pool
+    pub grid: Option<[u32; 2]>,
+    pub scenario: Option<Scenario>,
+    pub mask: Option<String>,
+    pub seed: Option<u64>,
+    pub solver: u32,
+    pub kernel: Kernel,
+    pub adapter: AdapterOptions,
+    pub headless: bool,
+    pub steps: Option<u64>,
+    pub output: Option<PathBuf>,
+    pub log_level: Option<String>,
+    pub record: Option<String>,
+    pub playback: Option<String>,
+    pub hot_reload: bool,
+    pub profile: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            window_size: [SCREEN_SIZE.0, SCREEN_SIZE.1],
+            particles: None,
+            grid: None,
+            scenario: None,
+            mask: None,
+            seed: None,
+            solver: SOLVER_EXPLICIT,
+            kernel: DEFAULT_KERNEL,
+            adapter: AdapterOptions::default(),
+            headless: false,
+            steps: None,
+            output: None,
+            log_level: None,
+            record: None,
+            playback: None,
+            hot_reload: false,
+            profile: false,
+        }
+    }
+}
+
+impl Options {
+    // Where --record writes, relative paths go in the output directory
+    pub fn record_path(&self) -> Option<PathBuf> {
+        let path = PathBuf::from(self.record.as_ref()?);
+        Some(match &self.output {
+            Some(output) if path.is_relative() => output.join(path),
+            _ => path,
+        })
+    }
+}
+
+pub type Command = cli::Command<Options>;
+
+// Reads the options from the arguments after the program name, --config files are read here too
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    parse_expanded(&expand_configs(args)?)
+}
+
+fn parse_expanded(args: &[String]) -> Result<Command, String> {
+    let command = parse_flags(args, |options: &mut Options, flag, args| {
+        match flag {
+            "--window" => options.window_size = size(&value(args, flag)?, flag)?,
+            "--particles" => {
+                let count = number(&value(args, flag)?, flag)?;
+                if count == 0 || count > TOTAL_PARTICLES {
+                    return Err(format!("--particles has to be between 1 and {}", TOTAL_PARTICLES));
+                }
+                options.particles = Some(count);
+            }
+            "--grid" => {
+                let grid = size(&value(args, flag)?, flag)?;
+                // The sort keys have NUM_DIGITS digits, and the inactive particles use the key after the last cell
+                if grid[0] as u64 * grid[1] as u64 >= BASE.pow(NUM_DIGITS) as u64 {
+                    return Err(format!("A {}x{} grid has too many cells for the sort, it can have at most {}", grid[0], grid[1], BASE.pow(NUM_DIGITS) - 1));
+                }
+                options.grid = Some(grid);
+            }
+            "--scenario" => {
+                let name = value(args, flag)?;
+                let names: Vec<&str> = Scenario::ALL.iter().map(|scenario| scenario.name()).collect();
+                options.scenario = Some(Scenario::from_name(&name).ok_or_else(|| format!("Unknown scenario {:?}, expected one of {}", name, names.join(", ")))?);
+            }
+            "--mask" => options.mask = Some(value(args, flag)?),
+            "--seed" => options.seed = Some(number(&value(args, flag)?, flag)?),
+            "--solver" => {
+                options.solver = match value(args, flag)?.as_str() {
+                    "explicit" => SOLVER_EXPLICIT,
+                    "pbf" => SOLVER_POSITION_BASED,
+                    other => return Err(format!("Unknown solver {:?}, expected explicit or pbf", other)),
+                }
+            }
+            "--kernel" => {
+                let name = value(args, flag)?;
+                let names: Vec<&str> = Kernel::ALL.iter().map(|kernel| kernel.name()).collect();
+                options.kernel = Kernel::from_name(&name).ok_or_else(|| format!("Unknown kernel {:?}, expected one of {}", name, names.join(", ")))?;
+            }
+            "--backend" => options.adapter.backend = backend(&value(args, flag)?)?,
+            "--adapter" => options.adapter.name = Some(value(args, flag)?),
+            "--headless" => options.headless = true,
+            "--steps" => options.steps = Some(number(&value(args, flag)?, flag)?),
+            "--output" => options.output = Some(PathBuf::from(value(args, flag)?)),
+            "--log-level" => options.log_level = Some(log_level(&value(args, flag)?)?),
+            "--record" => options.record = Some(value(args, flag)?),
+            "--playback" => options.playback = Some(value(args, flag)?),
+            "--hot-reload" => options.hot_reload = true,
+            "--profile" => options.profile = true,
+            _ => return Err(format!("Unknown option {:?}", flag)),
+        }
+        Ok(())
+    })?;
+    let Command::Run(options) = &command else {
+        return Ok(command);
+    };
+
+    // Options that cannot be used together
+    if options.particles.is_some() && (options.scenario.is_some() || options.mask.is_some()) {
+        return Err("--particles sets up the block filling the screen, it cannot be used with --scenario or --mask".to_string());
+    }
+    if options.record.is_some() && options.playback.is_some() {
+        return Err("--record and --playback cannot be used together".to_string());
+    }
+    if options.steps.is_some() && !options.headless {
+        return Err("--steps only applies with --headless".to_string());
+    }
+    if options.headless && options.steps.is_none() && options.playback.is_none() {
+        return Err("--headless needs --steps or --playback to know when to stop".to_string());
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Backend;
+
+    fn run(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        match parse(&args)? {
+            Command::Run(options) => Ok(*options),
+            Command::Help => Err("help".to_string()),
+        }
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        assert_eq!(run("").unwrap(), Options::default());
+        let options = run("--window 800x400 --grid 80x40 --seed 42 --solver pbf --kernel spiky --backend cpu --adapter llvm --headless --steps 100 --output out --log-level warn").unwrap();
+        assert_eq!(options.window_size, [800, 400]);
+        assert_eq!(options.grid, Some([80, 40]));
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.solver, SOLVER_POSITION_BASED);
+        assert_eq!(options.kernel, Kernel::Spiky);
+        assert_eq!(options.adapter, AdapterOptions { backend: Backend::Cpu, name: Some("llvm".to_string()) });
+        assert_eq!(options.steps, Some(100));
+        assert_eq!(options.output, Some(PathBuf::from("out")));
+        assert_eq!(run("--help --unknown"), Err("help".to_string()));
+    }
+
+    #[test]
+    fn invalid_options_are_errors() {
+        for args in [
+            "--unknown",
+            "--seed",
+            "--seed soon",
+            "--window 800",
+            "--grid 0x40",
+            "--grid 1000x1000",
+            "--particles 0",
+            "--scenario flood",
+            "--kernel gaussian",
+            "--backend tpu",
+            "--log-level loud",
+            "--particles 100 --scenario droplet",
+            "--record a.txt --playback b.txt",
+            "--steps 10",
+            "--headless",
+        ] {
+            assert!(run(args).is_err(), "{:?} should be an error", args);
+        }
+    }
+
+    #[test]
+    fn config_files_are_overridden_by_the_command_line() {
+        let mut args = cli::parse_config("seed 7\nkernel = poly6\n").unwrap();
+        args.extend(["--seed".to_string(), "8".to_string()]);
+        let Command::Run(options) = parse_expanded(&args).unwrap() else { panic!() };
+        assert_eq!(options.seed, Some(8));
+        assert_eq!(options.kernel, Kernel::Poly6);
+    }
+
+    #[test]
+    fn recordings_go_in_the_output_directory() {
+        let options = run("--record run.txt --output out").unwrap();
+        assert_eq!(options.record_path(), Some(PathBuf::from("out/run.txt")));
+        let options = run("--record /tmp/run.txt --output out").unwrap();
+        assert_eq!(options.record_path(), Some(PathBuf::from("/tmp/run.txt")));
+    }
+}
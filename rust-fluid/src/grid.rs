@@ -0,0 +1,124 @@
+// The uniform grid the particles are sorted into for the neighbour search
+// Deriving it from the radius of influence makes every cell at least as wide as the radius, so only the 3x3 cells around a particle are searched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridConfig {
+    pub size: [u32; 2], // How many cells divide the screen in x and y
+    pub cell_size: [f32; 2],
+    pub grids_to_check: [i32; 2], // How many cells on each side of a particle's cell can hold its neighbours
+}
+
+impl GridConfig {
+    // The finest grid whose cells are no smaller than the radius of influence
+    pub fn from_radius(screen_size: [f32; 2], radius_of_influence: f32) -> Self {
+        let size = [
+            ((screen_size[0] / radius_of_influence).floor() as u32).max(1),
+            ((screen_size[1] / radius_of_influence).floor() as u32).max(1),
+        ];
+        Self::with_size(screen_size, radius_of_influence, size)
+    }
+
+    // A grid with a fixed number of cells, smaller cells search further
+    pub fn with_size(screen_size: [f32; 2], radius_of_influence: f32, size: [u32; 2]) -> Self {
+        assert!(size[0] > 0 && size[1] > 0, "The grid needs at least one cell in each direction");
+
+        let cell_size = [
+            screen_size[0] / size[0] as f32,
+            screen_size[1] / size[1] as f32,
+        ];
+        // The tolerance stops rounding errors from searching an extra cell when the cells are exactly the radius
+        let grids_to_check = [
+            (radius_of_influence / cell_size[0] - 0.0001).ceil().max(1.0) as i32,
+            (radius_of_influence / cell_size[1] - 0.0001).ceil().max(1.0) as i32,
+        ];
+
+        Self {
+            size,
+            cell_size,
+            grids_to_check,
+        }
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.size[0] as usize * self.size[1] as usize
+    }
+
+    // The same as pos_to_grid in the shader
+    pub fn cell(&self, position: [f32; 2]) -> [i32; 2] {
+        [
+            ((position[0] / self.cell_size[0]) as i32).clamp(0, self.size[0] as i32 - 1),
+            ((position[1] / self.cell_size[1]) as i32).clamp(0, self.size[1] as i32 - 1),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BASE, NUM_DIGITS, RADIUS_OF_INFLUENCE, SCREEN_SIZE};
+
+    const SCREEN: [f32; 2] = [SCREEN_SIZE.0 as f32, SCREEN_SIZE.1 as f32];
+
+    // A grid search range is correct if every pair of points closer than the radius is found
+    fn assert_finds_neighbours(grid: &GridConfig, radius: f32) {
+        let steps = 97;
+        for i in 0..steps {
+            for j in 0..steps {
+                let a = [SCREEN[0] * i as f32 / steps as f32, SCREEN[1] * j as f32 / steps as f32];
+                for angle in 0..16 {
+                    let angle = angle as f32 * std::f32::consts::PI / 8.0;
+                    let b = [a[0] + radius * 0.999 * angle.cos(), a[1] + radius * 0.999 * angle.sin()];
+                    let (cell_a, cell_b) = (grid.cell(a), grid.cell(b));
+                    assert!(
+                        (cell_a[0] - cell_b[0]).abs() <= grid.grids_to_check[0]
+                            && (cell_a[1] - cell_b[1]).abs() <= grid.grids_to_check[1],
+                        "{:?} and {:?} are neighbours but cells {:?} and {:?} are not searched",
+                        a, b, cell_a, cell_b
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn derived_grid_searches_three_by_three() {
+        for radius in [RADIUS_OF_INFLUENCE, 5.0, 10.0, 33.3, 100.0] {
+            let grid = GridConfig::from_radius(SCREEN, radius);
+            assert!(grid.cell_size[0] >= radius && grid.cell_size[1] >= radius, "{:?} has cells smaller than {}", grid, radius);
+            assert_eq!(grid.grids_to_check, [1, 1]);
+            assert_finds_neighbours(&grid, radius);
+        }
+    }
+
+    #[test]
+    fn derived_grid_is_as_fine_as_possible() {
+        for radius in [RADIUS_OF_INFLUENCE, 5.0, 10.0, 33.3, 100.0] {
+            let grid = GridConfig::from_radius(SCREEN, radius);
+            assert!(grid.cell_size[0] < 2.0 * radius && grid.cell_size[1] < 2.0 * radius, "{:?} could use more cells", grid);
+        }
+    }
+
+    #[test]
+    fn overridden_grid_searches_further() {
+        let grid = GridConfig::with_size(SCREEN, RADIUS_OF_INFLUENCE, [80, 40]);
+        assert_eq!(grid.grids_to_check, [2, 2]);
+        assert_finds_neighbours(&grid, RADIUS_OF_INFLUENCE);
+
+        let grid = GridConfig::with_size(SCREEN, RADIUS_OF_INFLUENCE, [8, 4]);
+        assert_eq!(grid.grids_to_check, [1, 1]);
+        assert_finds_neighbours(&grid, RADIUS_OF_INFLUENCE);
+    }
+
+    #[test]
+    fn cells_stay_on_the_grid() {
+        let grid = GridConfig::from_radius(SCREEN, RADIUS_OF_INFLUENCE);
+        assert_eq!(grid.cell([-10.0, -10.0]), [0, 0]);
+        assert_eq!(grid.cell([SCREEN[0] + 10.0, SCREEN[1] + 10.0]), [grid.size[0] as i32 - 1, grid.size[1] as i32 - 1]);
+    }
+
+    #[test]
+    fn sort_keys_fit_in_the_radix_sort() {
+        // The inactive particles use the key after the last cell
+        let grid = GridConfig::from_radius(SCREEN, RADIUS_OF_INFLUENCE);
+        assert!(grid.cell_count() < BASE.pow(NUM_DIGITS) as usize);
+    }
+}
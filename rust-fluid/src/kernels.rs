@@ -0,0 +1,283 @@
+use std::f32::consts::PI;
+
+// The SPH smoothing kernels in 2D, all of them are zero beyond the radius of influence h
+// The shader evaluates their shapes and multiplies them by the normalisation sent in the simulation parameters, the tests check the shapes against Rust copies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    Quadratic, // (h - r)^2, the kernel the simulation was tuned with
+    Poly6, // (h^2 - r^2)^3
+    Spiky, // (h - r)^3, the gradient does not vanish at the centre so close particles still repel
+    CubicSpline, // The piecewise cubic B-spline
+    WendlandC2, // (1 - q)^4 (1 + 4q)
+}
+
+impl Kernel {
+    pub const ALL: [Kernel; 5] = [
+        Kernel::Quadratic,
+        Kernel::Poly6,
+        Kernel::Spiky,
+        Kernel::CubicSpline,
+        Kernel::WendlandC2,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kernel| kernel.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kernel::Quadratic => "quadratic",
+            Kernel::Poly6 => "poly6",
+            Kernel::Spiky => "spiky",
+            Kernel::CubicSpline => "cubic",
+            Kernel::WendlandC2 => "wendland",
+        }
+    }
+
+    // Matches the KERNEL_ constants in the shader
+    pub fn id(self) -> u32 {
+        self as u32
+    }
+
+    // Scales the shape so it integrates to 1 over the disc of radius h
+    pub fn normalisation(self, h: f32) -> f32 {
+        match self {
+            Kernel::Quadratic => 6.0 / (PI * h.powi(4)),
+            Kernel::Poly6 => 4.0 / (PI * h.powi(8)),
+            Kernel::Spiky => 10.0 / (PI * h.powi(5)),
+            Kernel::CubicSpline => 40.0 / (7.0 * PI * h * h),
+            Kernel::WendlandC2 => 7.0 / (PI * h * h),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use renderer_backend::{
+        adapter::{select_adapter, AdapterOptions},
+        buffers::{create_buffer, create_reader_buffer, read_buffer},
+        window::request_device,
+    };
+    use crate::{preprocess_shader, RADIUS_OF_INFLUENCE, SHADER};
+
+    const RADII: [f32; 3] = [1.0, 75.0 / 4.0, 40.0];
+    const SAMPLES: u32 = 24; // The distances each kernel is sampled at on the GPU, reaching past the radius
+
+    // Runs kernel_shape and kernel_shape_derivative from the shader on each sample, a vec4 of the kernel, the distance and the two results
+    // The binding is past the ones the simulation uses so it is the only one in the pipeline's layout
+    const SAMPLE_KERNELS: &str = "
+@group(0) @binding(99) var<storage, read_write> kernel_samples: array<vec4<f32>>;
+
+@compute @workgroup_size(1)
+fn sample_kernels(@builtin(global_invocation_id) id: vec3<u32>) {
+    let sample = kernel_samples[id.x];
+    let kernel = u32(sample.x);
+    kernel_samples[id.x] = vec4<f32>(sample.x, sample.y, kernel_shape(kernel, sample.y), kernel_shape_derivative(kernel, sample.y));
+}
+";
+
+    // The kernel shapes written out again in Rust, the shader has to agree with them
+    impl Kernel {
+        // The kernel before it is normalised
+        fn shape(self, distance: f32, h: f32) -> f32 {
+            if distance >= h {
+                return 0.0;
+            }
+
+            let q = distance / h;
+            match self {
+                Kernel::Quadratic => (h - distance).powi(2),
+                Kernel::Poly6 => (h * h - distance * distance).powi(3),
+                Kernel::Spiky => (h - distance).powi(3),
+                Kernel::CubicSpline => {
+                    if q <= 0.5 {
+                        6.0 * (q * q * q - q * q) + 1.0
+                    } else {
+                        2.0 * (1.0 - q).powi(3)
+                    }
+                }
+                Kernel::WendlandC2 => (1.0 - q).powi(4) * (1.0 + 4.0 * q),
+            }
+        }
+
+        // The derivative of the shape with respect to the distance
+        fn shape_derivative(self, distance: f32, h: f32) -> f32 {
+            if distance >= h {
+                return 0.0;
+            }
+
+            let q = distance / h;
+            match self {
+                Kernel::Quadratic => -2.0 * (h - distance),
+                Kernel::Poly6 => -6.0 * distance * (h * h - distance * distance).powi(2),
+                Kernel::Spiky => -3.0 * (h - distance).powi(2),
+                Kernel::CubicSpline => {
+                    if q <= 0.5 {
+                        (18.0 * q * q - 12.0 * q) / h
+                    } else {
+                        -6.0 * (1.0 - q).powi(2) / h
+                    }
+                }
+                Kernel::WendlandC2 => -20.0 * q * (1.0 - q).powi(3) / h,
+            }
+        }
+
+        fn value(self, distance: f32, h: f32) -> f32 {
+            self.normalisation(h) * self.shape(distance, h)
+        }
+
+        fn derivative(self, distance: f32, h: f32) -> f32 {
+            self.normalisation(h) * self.shape_derivative(distance, h)
+        }
+    }
+
+    // Evaluates the shader's kernels at `samples`, each the kernel and the distance, and returns the shape and its derivative
+    // None when there is no adapter to run the shader on
+    fn shader_kernels(samples: &[[f32; 4]]) -> Option<Vec<[f32; 4]>> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let adapter = pollster::block_on(select_adapter(&instance, None, &AdapterOptions::default())).ok()?;
+        let (device, queue) = pollster::block_on(request_device(&adapter, wgpu::Limits::default())).unwrap();
+
+        let source = preprocess_shader(SHADER).unwrap() + SAMPLE_KERNELS;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Kernel Samples Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Kernel Samples Pipeline"),
+            layout: None,
+            module: &module,
+            entry_point: "sample_kernels",
+        });
+
+        let buffer = create_buffer(&device, "Kernel Samples Buffer", samples, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC);
+        let reader = create_reader_buffer(&device, "Kernel Samples Reader Buffer", buffer.size());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Kernel Samples Bind Group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 99,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Kernel Samples Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Kernel Samples Pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(samples.len() as u32, 1, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        Some(read_buffer(&device, &queue, &buffer, &reader))
+    }
+
+    // Integrates the kernel over the disc of radius h with Simpson's rule in polar coordinates
+    fn integrate_over_disc(kernel: Kernel, h: f32) -> f64 {
+        let steps = 20000;
+        let step = h as f64 / steps as f64;
+        let mut sum = 0.0;
+        for i in 0..=steps {
+            let r = i as f64 * step;
+            let weight = if i == 0 || i == steps { 1.0 } else if i % 2 == 1 { 4.0 } else { 2.0 };
+            sum += weight * kernel.value(r as f32, h) as f64 * 2.0 * std::f64::consts::PI * r;
+        }
+        sum * step / 3.0
+    }
+
+    #[test]
+    fn kernels_integrate_to_one() {
+        for kernel in Kernel::ALL {
+            for h in RADII {
+                let integral = integrate_over_disc(kernel, h);
+                assert!((integral - 1.0).abs() < 1e-3, "{:?} with h = {} integrates to {}", kernel, h, integral);
+            }
+        }
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        for kernel in Kernel::ALL {
+            for h in RADII {
+                let epsilon = h * 1e-3;
+                let largest = (0..=100)
+                    .map(|i| kernel.derivative(h * i as f32 / 100.0, h).abs())
+                    .fold(0.0, f32::max);
+
+                // Stay clear of the centre and the edge where the one-sided kernels are not differentiable
+                for i in 1..100 {
+                    let r = h * i as f32 / 100.0;
+                    let finite_difference = (kernel.value(r + epsilon, h) - kernel.value(r - epsilon, h)) / (2.0 * epsilon);
+                    let derivative = kernel.derivative(r, h);
+                    assert!(
+                        (finite_difference - derivative).abs() <= 1e-2 * largest,
+                        "{:?} with h = {} at r = {}: derivative {} but finite difference {}",
+                        kernel, h, r, derivative, finite_difference
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn kernels_vanish_outside_the_radius() {
+        for kernel in Kernel::ALL {
+            for h in RADII {
+                assert_eq!(kernel.value(h, h), 0.0);
+                assert_eq!(kernel.value(h * 1.5, h), 0.0);
+                assert_eq!(kernel.derivative(h * 1.5, h), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn shader_kernels_match_the_rust_kernels() {
+        let h = RADIUS_OF_INFLUENCE;
+        let samples: Vec<[f32; 4]> = Kernel::ALL
+            .iter()
+            .flat_map(|kernel| (0..SAMPLES).map(move |i| [kernel.id() as f32, h * 1.2 * i as f32 / SAMPLES as f32, 0.0, 0.0]))
+            .collect();
+        let Some(results) = shader_kernels(&samples) else {
+            eprintln!("There is no adapter, skipping the shader kernels");
+            return;
+        };
+        assert_eq!(results.len(), samples.len());
+
+        for kernel in Kernel::ALL {
+            // The shader multiplies its shapes by the normalisation sent in the simulation parameters
+            let normalisation = kernel.normalisation(h);
+            let largest = [kernel.value(0.0, h).abs(), kernel.derivative(h * 0.25, h).abs()].into_iter().fold(0.0, f32::max);
+            for result in results.iter().filter(|result| result[0] as u32 == kernel.id()) {
+                let distance = result[1];
+                let (value, derivative) = (normalisation * result[2], normalisation * result[3]);
+                assert!(
+                    (value - kernel.value(distance, h)).abs() <= 1e-4 * largest,
+                    "{:?} at r = {}: the shader gives {} but the Rust kernel {}",
+                    kernel, distance, value, kernel.value(distance, h)
+                );
+                assert!(
+                    (derivative - kernel.derivative(distance, h)).abs() <= 1e-4 * largest,
+                    "{:?} at r = {}: the shader's derivative is {} but the Rust kernel's {}",
+                    kernel, distance, derivative, kernel.derivative(distance, h)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for kernel in Kernel::ALL {
+            assert_eq!(Kernel::from_name(kernel.name()), Some(kernel));
+        }
+        assert_eq!(Kernel::from_name("gaussian"), None);
+    }
+}
@@ -0,0 +1,1538 @@
+use core::f32;
+use bytemuck::{Pod, Zeroable};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use std::path::{Path, PathBuf};
+use renderer_backend::{
+    bind_group_layout_builder::PipelineBindings, gui::Gui, pipeline_builder::PipelineBuilder, preprocessor::Preprocessor, profiler::Profiler, shader_watcher::ShaderWatcher,
+    shader_types::SharedDefinitions, text::TextRenderer, window::GpuContext, app::{self, hud_text, write_particles, Entry, Simulation},
+    cli::{init_logging, options_or_exit},
+    wgsl_struct,
+};
+pub use renderer_backend::adapter::{AdapterOptions, Backend};
+mod kernels;
+use kernels::Kernel;
+mod scenarios;
+use scenarios::Scenario;
+mod mask;
+use mask::ImageMask;
+mod recording;
+mod cli;
+use cli::{Command, Options};
+mod panel;
+mod touch;
+use touch::{TouchPhase, Touches};
+use panel::{Action, Panel};
+use recording::{Input, Playback, Recorder, Recording};
+pub mod grid;
+use grid::GridConfig;
+pub mod simulation;
+pub use simulation::{FluidConfig, FluidParams, FluidSimulation, Obstacle};
+use wgpu::BufferUsages;
+use winit::{
+    dpi::PhysicalSize,
+    event::*,
+    keyboard::{KeyCode, PhysicalKey},
+    window::Window,
+};
+
+const SHADER: &str = include_str!("shaders/shader.wgsl"); // Embedded so the binary runs from any directory
+const SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/shader.wgsl"); // Watched with --hot-reload
+
+pub const SCREEN_SIZE: (u32, u32) = (1200, 600);
+const TIME_BETWEEN_FRAMES: u64 = 2;
+const PROFILE_INTERVAL: u32 = 60; // How often the pass timings are printed with --profile, in frames
+const PROFILE_WINDOW: usize = 60; // How many frames the pass timings are averaged over
+const HUD_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+const PARTICLE_RADIUS: f32 = 1.25 / 4.0; // The radius of the particles
+const PARTICLE_AMOUNT_X: u32 = 192 * 4; // The number of particles in the x direction
+const PARTICLE_AMOUNT_Y: u32 = 96 * 4; // The number of particles in the y direction
+pub const TOTAL_PARTICLES: u32 = PARTICLE_AMOUNT_X * PARTICLE_AMOUNT_Y; // The number of particles on the lattice the block starts on
+pub const MAX_PARTICLES: u32 = 1_000_000; // The most particle slots, one sort workgroup per WORKGROUP_SIZE slots has to fit in a dispatch
+const PADDING: f32 = 50.0; // The padding around the screen
+const PARTICLE_SPACING: [f32; 2] = [
+    (SCREEN_SIZE.0 as f32 - 2.0 * PADDING) / PARTICLE_AMOUNT_X as f32,
+    (SCREEN_SIZE.1 as f32 - 2.0 * PADDING) / PARTICLE_AMOUNT_Y as f32,
+]; // The distance between the particles when they start on a lattice
+const FREE_POOL_ROWS: u32 = PARTICLE_AMOUNT_Y / 4; // The top rows of particles start inactive so there are particles to pour
+const FREE_POOL_SIZE: u32 = FREE_POOL_ROWS * PARTICLE_AMOUNT_X; // How many slots are kept inactive after the particles that are loaded
+const DEFAULT_ACTIVE_PARTICLES: u32 = TOTAL_PARTICLES - FREE_POOL_SIZE;
+const INACTIVE_POSITION: [f32; 2] = [-1000.0, -1000.0]; // Where inactive particles are kept, the same as in the shader
+pub const RADIUS_OF_INFLUENCE: f32 = 75.0 / 4.0; // The radius of the kernels, the same as in the shader
+const WALL_CELL_SIZE: f32 = 4.0; // The size of the cells of the walls painted in an image mask
+const WALL_GRID_SIZE: [u32; 2] = [
+    (SCREEN_SIZE.0 as f32 / WALL_CELL_SIZE) as u32,
+    (SCREEN_SIZE.1 as f32 / WALL_CELL_SIZE) as u32,
+];
+const TIME_STEP: f32 = 1.0 / 8.0; // The time step of the explicit solver
+const PBF_TIME_STEP: f32 = 1.0 / 2.0; // The time step of the position based solver
+const GRAVITY: f32 = 0.2; // The strength of gravity, the scenarios can point it elsewhere
+const TARGET_DENSITY: f32 = 0.2; // The target density of the fluid
+const PRESSURE_MULTIPLIER: f32 = 500.0; // The multiplier for the pressure force
+const VISCOSITY: f32 = 0.1; // The viscosity of the fluid
+const WALL_DAMPENING: f32 = 0.95; // How much to slow down particles when they collide with the walls
+const DEFAULT_KERNEL: Kernel = Kernel::Quadratic; // The kernel used for the density and pressure
+const VISCOSITY_KERNEL: Kernel = Kernel::Poly6; // The kernel used for the viscosity
+
+const BASE: u32 = 10;
+const NUM_DIGITS: u32 = 5;
+const BUCKET_SIZE: u32 = 32; // The amount of numbers in each bucket for the inclusive prefix sum
+
+const WORKGROUP_SIZE: u32 = 16;
+const PARTICLE_WORKGROUP_SIZE: u32 = WORKGROUP_SIZE * WORKGROUP_SIZE; // The passes over the particles are one dimensional
+
+const IPS_WORKGROUP_SIZE: u32 = 16;
+
+const MAX_RIGID_BODIES: u32 = 24; // The number of rigid body slots, obstacles take the first ones and spawned bodies the rest
+const MAX_OBSTACLES: u32 = 8; // The slots kept for obstacles, so adding one never replaces a spawned body
+const RIGID_BODY_BOX: u32 = 1;
+const RIGID_BODY_DISC: u32 = 2;
+const RIGID_BODY_DISPATCH_SIZE: u32 = MAX_RIGID_BODIES.div_ceil(WORKGROUP_SIZE);
+const LIGHT_RIGID_BODY_DENSITY: f32 = 0.1; // Less dense than the fluid, so it floats
+const HEAVY_RIGID_BODY_DENSITY: f32 = 0.6; // More dense than the fluid, so it sinks
+
+const MAX_HEAT_SOURCES: u32 = 8; // The number of heat source slots
+const REFERENCE_TEMPERATURE: f32 = 0.0; // The temperature with no buoyancy, also the starting temperature of the particles
+const HOT_TEMPERATURE: f32 = 1.0; // The temperature of heat sources and the heated wall
+const COLD_TEMPERATURE: f32 = -1.0; // The temperature of heat sinks and the cooled wall
+const BUOYANCY: f32 = 0.1; // The upwards acceleration per degree above the reference temperature
+const THERMAL_DIFFUSIVITY: f32 = 0.5; // How quickly heat spreads between particles
+const WALL_HEAT_TRANSFER: f32 = 0.2; // How quickly the heated and cooled walls change the temperature of the particles next to them
+const HEAT_SOURCE_RADIUS: f32 = 30.0; // The radius of the heat sources placed with the mouse
+
+const VORTICITY_STRENGTH: f32 = 0.1; // How strongly the vorticity confinement amplifies swirls
+const VORTICITY_STRENGTH_STEP: f32 = 0.05; // How much the vorticity strength changes with each key press
+
+const TOOL_PUSH: u32 = 0;
+pub const TOOL_NAMES: [&str; 6] = ["Push", "Drag", "Vortex", "Freeze", "Pour", "Erase"]; // In the order of the TOOL_ constants in the shader
+pub const MOUSE_LEFT: u32 = 1;
+pub const MOUSE_RIGHT: u32 = 2;
+const MOUSE_TOOL_RADIUS: f32 = RADIUS_OF_INFLUENCE; // The starting radius of the mouse tools
+const MIN_MOUSE_TOOL_RADIUS: f32 = 5.0;
+const MAX_MOUSE_TOOL_RADIUS: f32 = 200.0;
+const MOUSE_TOOL_RADIUS_STEP: f32 = 1.1; // How much one notch of the scroll wheel scales the tool radius
+const STRONG_MOUSE_TOOL: f32 = 4.0; // The strength of the mouse tools while shift is held
+const WEAK_MOUSE_TOOL: f32 = 0.25; // The strength of the mouse tools while control is held
+pub const MAX_TOUCHES: u32 = 10; // How many fingers and pens can use the tool at once
+pub const MAX_POINTERS: u32 = MAX_TOUCHES + 1; // The mouse is the first pointer, followed by the touches
+
+const SOLVER_EXPLICIT: u32 = 0; // Explicit SPH, pressure forces from the density
+const SOLVER_POSITION_BASED: u32 = 1; // Position Based Fluids, positions projected onto the density constraint
+const PBF_ITERATIONS: u32 = 4; // How many times the density constraint is projected each step
+
+wgsl_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Pod, Zeroable)]
+    pub struct Particle {
+        pub position: [f32; 2], // 8 bytes
+        pub velocity: [f32; 2], // 8 bytes
+        pub radius: f32, // 4 bytes, 0 while the slot is in the free pool
+        pub density: f32, // 4 bytes
+        pub temperature: f32, // 4 bytes
+        pub temperature_rate: f32, // 4 bytes
+        pub forces: [f32; 4], // 16 bytes
+        pub vorticity: f32, // 4 bytes
+        pub lambda: f32, // 4 bytes, the density constraint multiplier of the position based solver
+        pub previous_position: [f32; 2], // 8 bytes, the position before the position based solver predicted a new one
+    }
+}
+
+impl Particle {
+    fn new(position: [f32; 2], velocity: [f32; 2], radius: f32) -> Self {
+        Self {
+            position,
+            velocity,
+            radius,
+            density: 0.0,
+            temperature: REFERENCE_TEMPERATURE,
+            temperature_rate: 0.0,
+            forces: [0.0, 0.0, 0.0, 0.0],
+            vorticity: 0.0,
+            lambda: 0.0,
+            previous_position: position,
+        }
+    }
+
+    // A slot in the free pool, a radius of 0 marks it as inactive until the pour tool activates it
+    fn inactive() -> Self {
+        Self::new(INACTIVE_POSITION, [0.0, 0.0], 0.0)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.radius > 0.0
+    }
+}
+
+wgsl_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Pod, Zeroable)]
+    struct ParticlePool {
+        first_inactive: u32, // 4 bytes, the sort moves the inactive particles after the active ones
+        seed: u32, // 4 bytes, changes every step so poured particles land in different places
+    }
+}
+
+wgsl_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+    struct Capacity {
+        particles: u32, // 4 bytes, how many particle slots the buffers have
+        buckets: u32, // 4 bytes, how many buckets of BUCKET_SIZE slots the sort splits them into
+        _padding: [u32; 2], // Padding, 8 bytes, a uniform is a multiple of 16 bytes
+    }
+}
+
+impl Capacity {
+    fn new(particles: u32) -> Self {
+        Self {
+            particles,
+            buckets: particles.div_ceil(BUCKET_SIZE),
+            _padding: [0; 2],
+        }
+    }
+
+    // Workgroups for the passes over every particle
+    fn particle_workgroups(&self) -> u32 {
+        self.particles.div_ceil(PARTICLE_WORKGROUP_SIZE)
+    }
+
+    // Workgroups for the sort and pool passes, which run one thread per slot in smaller workgroups
+    fn sort_workgroups(&self) -> u32 {
+        self.particles.div_ceil(WORKGROUP_SIZE)
+    }
+
+    // Workgroups for each digit of the inclusive prefix sum
+    fn scan_workgroups(&self) -> u32 {
+        self.buckets.div_ceil(IPS_WORKGROUP_SIZE)
+    }
+
+    // How many steps the Hillis Steele scan needs to cover every bucket
+    fn scan_stages(&self) -> u32 {
+        (self.buckets as f32).log2().ceil() as u32
+    }
+}
+
+// The buffers sized by the capacity, they are replaced with new ones when it changes
+struct CapacityBuffers {
+    particles: wgpu::Buffer,
+    particle_reader: wgpu::Buffer,
+    sorted_data: wgpu::Buffer,
+    histogram: wgpu::Buffer,
+    inclusive_prefix_sum: wgpu::Buffer,
+    scanned_inclusive_prefix_sum: wgpu::Buffer,
+}
+
+impl CapacityBuffers {
+    fn new(device: &wgpu::Device, capacity: &Capacity) -> Self {
+        let particles_size = (capacity.particles as usize * std::mem::size_of::<Particle>()) as u64;
+        let sort_size = (capacity.buckets as usize * BASE as usize * std::mem::size_of::<u32>()) as u64;
+        let buffer = |label: &str, size: u64, usage: BufferUsages| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let storage = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+        CapacityBuffers {
+            particles: buffer("Particle Buffer Data", particles_size, storage),
+            particle_reader: buffer("Particle Reader Buffer", particles_size, BufferUsages::MAP_READ | BufferUsages::COPY_DST),
+            sorted_data: buffer("Sorted Data Buffer", particles_size, storage),
+            histogram: buffer("Histogram Buffer", sort_size, storage),
+            inclusive_prefix_sum: buffer("Inclusive Prefix Sum Buffer", sort_size, storage),
+            scanned_inclusive_prefix_sum: buffer("Scanned Inclusive Prefix Sum Buffer", sort_size, storage),
+        }
+    }
+}
+
+wgsl_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Pod, Zeroable)]
+    struct RigidBody {
+        position: [f32; 2], // 8 bytes
+        velocity: [f32; 2], // 8 bytes
+        size: [f32; 2], // 8 bytes, half extents for boxes and the radius in x for discs
+        angle: f32, // 4 bytes
+        angular_velocity: f32, // 4 bytes
+        density: f32, // 4 bytes
+        shape: u32, // 4 bytes, 0-Inactive; 1-Box; 2-Disc
+        fixed: u32, // 4 bytes, 1 for obstacles that the fluid cannot move
+        _padding: u32, // Padding, 4 bytes
+    }
+}
+
+impl RigidBody {
+    fn new_box(position: [f32; 2], half_size: [f32; 2], density: f32) -> Self {
+        Self {
+            position,
+            velocity: [0.0, 0.0],
+            size: half_size,
+            angle: 0.0,
+            angular_velocity: 0.0,
+            density,
+            shape: RIGID_BODY_BOX,
+            fixed: 0,
+            _padding: 0,
+        }
+    }
+
+    fn new_disc(position: [f32; 2], radius: f32, density: f32) -> Self {
+        Self {
+            position,
+            velocity: [0.0, 0.0],
+            size: [radius, radius],
+            angle: 0.0,
+            angular_velocity: 0.0,
+            density,
+            shape: RIGID_BODY_DISC,
+            fixed: 0,
+            _padding: 0,
+        }
+    }
+
+    // Holds the body in place, the forces from the fluid are ignored
+    fn fixed(self) -> Self {
+        Self {
+            fixed: 1,
+            ..self
+        }
+    }
+}
+
+wgsl_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Pod, Zeroable)]
+    struct HeatSource {
+        position: [f32; 2], // 8 bytes
+        radius: f32, // 4 bytes, 0 is inactive
+        temperature: f32, // 4 bytes
+    }
+}
+
+// What the mouse is doing, sent to the GPU as the first pointer
+#[derive(Debug, Clone, Copy)]
+struct MouseTool {
+    position: [f32; 2],
+    movement: [f32; 2], // How far the cursor moved since the last step
+    radius: f32,
+    strength: f32, // Multiplies the strength of the tool
+    tool: u32, // 0-Push; 1-Drag; 2-Vortex; 3-Freeze; 4-Pour; 5-Erase
+    buttons: u32, // 1-Left; 2-Right
+}
+
+impl MouseTool {
+    fn new() -> Self {
+        Self {
+            position: [0.0, 0.0],
+            movement: [0.0, 0.0],
+            radius: MOUSE_TOOL_RADIUS,
+            strength: 1.0,
+            tool: TOOL_PUSH,
+            buttons: 0,
+        }
+    }
+
+    fn set_button(&mut self, button: u32, pressed: bool) {
+        if pressed {
+            self.buttons |= button;
+        } else {
+            self.buttons &= !button;
+        }
+    }
+
+    fn next_tool(&mut self) {
+        self.tool = (self.tool + 1) % TOOL_NAMES.len() as u32;
+    }
+}
+
+wgsl_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+    pub struct Pointer {
+        pub position: [f32; 2], // 8 bytes
+        pub movement: [f32; 2], // 8 bytes, how far it moved since the last step
+        pub pressure: f32, // 4 bytes, multiplies the strength of the tool, 1 for the mouse
+        pub buttons: u32, // 4 bytes, the MOUSE_ buttons it acts as, 0 while it is up
+        pub radius: f32, // 4 bytes, how far its tool reaches
+        pub tool: u32, // 4 bytes, the index of its tool in TOOL_NAMES
+        pub strength: f32, // 4 bytes, multiplies the strength of the tool, set with shift and control
+        _padding: f32, // Padding, 4 bytes
+    }
+}
+
+impl Pointer {
+    // A pointer holding the left button down at `position` with the normal strength, it hasn't moved
+    pub fn new(position: [f32; 2], tool: u32, radius: f32) -> Self {
+        Self {
+            position,
+            movement: [0.0, 0.0],
+            pressure: 1.0,
+            buttons: MOUSE_LEFT,
+            radius,
+            tool,
+            strength: 1.0,
+            _padding: 0.0,
+        }
+    }
+}
+
+wgsl_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Pod, Zeroable)]
+    struct SimParams {
+        wall_temperatures: [f32; 4], // 16 bytes, left, right, top, bottom
+        wall_heat_transfer: [f32; 4], // 16 bytes, 0 is insulated
+        reference_temperature: f32, // 4 bytes
+        buoyancy: f32, // 4 bytes
+        thermal_diffusivity: f32, // 4 bytes
+        render_mode: u32, // 4 bytes, 0-Speed and density; 1-Temperature
+        heat_sources: [HeatSource; MAX_HEAT_SOURCES as usize], // 128 bytes
+        vorticity_strength: f32, // 4 bytes
+        solver_mode: u32, // 4 bytes, 0-Explicit SPH; 1-Position Based Fluids
+        kernel: u32, // 4 bytes, the kernel used for the density and pressure
+        kernel_normalisation: f32, // 4 bytes
+        viscosity_kernel_normalisation: f32, // 4 bytes
+        _padding: f32, // Padding, 4 bytes
+        grid_size: [u32; 2], // 8 bytes
+        cell_size: [f32; 2], // 8 bytes
+        grids_to_check: [i32; 2], // 8 bytes
+        gravity: [f32; 2], // 8 bytes
+        emitter_position: [f32; 2], // 8 bytes
+        emitter_velocity: [f32; 2], // 8 bytes
+        emitter_radius: f32, // 4 bytes
+        emitter_rate: u32, // 4 bytes, how many particles the emitter takes from the free pool each step, 0 is off
+        drain_position: [f32; 2], // 8 bytes
+        drain_radius: f32, // 4 bytes, 0 is no drain
+        _padding_2: f32, // Padding, 4 bytes
+        view_scale: [f32; 2], // 8 bytes, the size of the simulation over the size of the window
+        target_density: f32, // 4 bytes
+        pressure_multiplier: f32, // 4 bytes
+        viscosity: f32, // 4 bytes
+        wall_dampening: f32, // 4 bytes
+        _padding_3: [f32; 2], // Padding, 8 bytes
+    }
+}
+
+impl SimParams {
+    fn new(grid: &GridConfig) -> Self {
+        Self {
+            wall_temperatures: [REFERENCE_TEMPERATURE, REFERENCE_TEMPERATURE, COLD_TEMPERATURE, HOT_TEMPERATURE],
+            wall_heat_transfer: [0.0; 4],
+            reference_temperature: REFERENCE_TEMPERATURE,
+            buoyancy: BUOYANCY,
+            thermal_diffusivity: THERMAL_DIFFUSIVITY,
+            render_mode: 0,
+            heat_sources: [HeatSource::zeroed(); MAX_HEAT_SOURCES as usize],
+            vorticity_strength: VORTICITY_STRENGTH,
+            solver_mode: SOLVER_EXPLICIT,
+            kernel: DEFAULT_KERNEL.id(),
+            kernel_normalisation: DEFAULT_KERNEL.normalisation(RADIUS_OF_INFLUENCE),
+            viscosity_kernel_normalisation: VISCOSITY_KERNEL.normalisation(RADIUS_OF_INFLUENCE),
+            _padding: 0.0,
+            grid_size: grid.size,
+            cell_size: grid.cell_size,
+            grids_to_check: grid.grids_to_check,
+            gravity: [0.0, GRAVITY],
+            emitter_position: [0.0, 0.0],
+            emitter_velocity: [0.0, 0.0],
+            emitter_radius: 0.0,
+            emitter_rate: 0,
+            drain_position: [0.0, 0.0],
+            drain_radius: 0.0,
+            _padding_2: 0.0,
+            view_scale: [1.0, 1.0],
+            target_density: TARGET_DENSITY,
+            pressure_multiplier: PRESSURE_MULTIPLIER,
+            viscosity: VISCOSITY,
+            wall_dampening: WALL_DAMPENING,
+            _padding_3: [0.0, 0.0],
+        }
+    }
+
+    fn set_kernel(&mut self, kernel: Kernel) {
+        self.kernel = kernel.id();
+        self.kernel_normalisation = kernel.normalisation(RADIUS_OF_INFLUENCE);
+    }
+
+    // Stretches the simulation over a window of this size
+    fn set_window_size(&mut self, size: PhysicalSize<u32>) {
+        self.view_scale = [SCREEN_SIZE.0 as f32 / size.width as f32, SCREEN_SIZE.1 as f32 / size.height as f32];
+    }
+
+    // Points gravity down and turns the emitter and drain off
+    fn clear_scenario(&mut self) {
+        self.gravity = [0.0, GRAVITY];
+        self.emitter_rate = 0;
+        self.drain_radius = 0.0;
+    }
+
+    // Heats the bottom wall and cools the top wall, or insulates them again
+    fn toggle_wall_heating(&mut self) {
+        let transfer = if self.wall_heat_transfer[3] == 0.0 { WALL_HEAT_TRANSFER } else { 0.0 };
+        self.wall_heat_transfer[2] = transfer;
+        self.wall_heat_transfer[3] = transfer;
+    }
+}
+
+// Particles on a grid filling the screen, only the bottom `active` of them start active, followed by the free pool
+fn initial_particles(active: u32) -> Vec<Particle> {
+    let mut particles = vec![];
+    for i in 0..PARTICLE_AMOUNT_X {
+        for j in 0..PARTICLE_AMOUNT_Y {
+            let x = (i as f32 + 0.5) * (SCREEN_SIZE.0 as f32 - 2.0 * PADDING)
+                / PARTICLE_AMOUNT_X as f32
+                + PADDING;
+            let y = (j as f32 + 0.5) * (SCREEN_SIZE.1 as f32 - 2.0 * PADDING)
+                / PARTICLE_AMOUNT_Y as f32
+                + PADDING;
+
+            if (PARTICLE_AMOUNT_Y - 1 - j) * PARTICLE_AMOUNT_X + i < active {
+                particles.push(Particle::new([x, y], [0.0, 0.0], PARTICLE_RADIUS));
+            }
+        }
+    }
+    with_free_pool(particles)
+}
+
+// Adds the free pool after the particles, the buffers are sized to fit both
+fn with_free_pool(mut particles: Vec<Particle>) -> Vec<Particle> {
+    let loaded = (MAX_PARTICLES - FREE_POOL_SIZE) as usize;
+    if particles.len() > loaded {
+        eprintln!("There is room for {} particles, only the first {} of {} are used", loaded, loaded, particles.len());
+        particles.truncate(loaded);
+    }
+    particles.resize(particles.len() + FREE_POOL_SIZE as usize, Particle::inactive());
+    particles
+}
+
+// The particles painted in an image mask on the same lattice spacing, followed by the free pool
+fn mask_particles(mask: &ImageMask) -> Vec<Particle> {
+    let particles = mask
+        .particles(PARTICLE_SPACING)
+        .into_iter()
+        .map(|(position, temperature)| Particle {
+            temperature,
+            ..Particle::new(position, [0.0, 0.0], PARTICLE_RADIUS)
+        })
+        .collect();
+    with_free_pool(particles)
+}
+
+// The particles of a scenario on the same lattice spacing, followed by the free pool
+fn scenario_particles(scenario: Scenario) -> Vec<Particle> {
+    let particles = scenario
+        .particle_positions(PARTICLE_SPACING)
+        .into_iter()
+        .map(|position| Particle::new(position, [0.0, 0.0], PARTICLE_RADIUS))
+        .collect();
+    with_free_pool(particles)
+}
+
+// The constants and structs the shader shares with this file, included in the shader as "shared.wgsl"
+fn shared_wgsl() -> String {
+    SharedDefinitions::new()
+        .constant("SCREEN_SIZE", [SCREEN_SIZE.0 as f32, SCREEN_SIZE.1 as f32])
+        .constant("WORKGROUP_SIZE", WORKGROUP_SIZE)
+        .constant("IPS_WORKGROUP_SIZE", IPS_WORKGROUP_SIZE)
+        .constant("PARTICLE_WORKGROUP_SIZE", PARTICLE_WORKGROUP_SIZE)
+        .constant("PARTICLE_RADIUS", PARTICLE_RADIUS)
+        .constant("INACTIVE_POSITION", INACTIVE_POSITION)
+        .constant("RADIUS_OF_INFLUENCE", RADIUS_OF_INFLUENCE)
+        .constant("dt", TIME_STEP)
+        .constant("PBF_DT", PBF_TIME_STEP)
+        .constant("BASE", BASE as i32)
+        .constant("NUM_DIGITS", NUM_DIGITS)
+        .constant("BUCKET_SIZE", BUCKET_SIZE)
+        .constant("MAX_RIGID_BODIES", MAX_RIGID_BODIES)
+        .constant("RIGID_BODY_BOX", RIGID_BODY_BOX)
+        .constant("RIGID_BODY_DISC", RIGID_BODY_DISC)
+        .constant("MAX_HEAT_SOURCES", MAX_HEAT_SOURCES)
+        .constant("WALL_CELL_SIZE", WALL_CELL_SIZE)
+        .constant("WALL_GRID_SIZE", WALL_GRID_SIZE)
+        .constant("SOLVER_EXPLICIT", SOLVER_EXPLICIT)
+        .constant("SOLVER_POSITION_BASED", SOLVER_POSITION_BASED)
+        .constant("MOUSE_LEFT", MOUSE_LEFT)
+        .constant("MOUSE_RIGHT", MOUSE_RIGHT)
+        .constant("MAX_POINTERS", MAX_POINTERS)
+        .structure::<Particle>()
+        .structure::<ParticlePool>()
+        .structure::<Capacity>()
+        .structure::<RigidBody>()
+        .structure::<HeatSource>()
+        .structure::<Pointer>()
+        .structure::<SimParams>()
+        .build()
+}
+
+fn preprocess_shader(source: &str) -> Result<String, String> {
+    Preprocessor::new()
+        .include("shared.wgsl", &shared_wgsl())
+        .process(source)
+}
+
+struct State<'a> {
+    window: Option<&'a Window>, // None when running headless
+    surface: Option<wgpu::Surface<'a>>, // None when running headless
+    gui: Option<Gui>, // The parameter panel, None when running headless
+    panel: Panel,
+    text: Option<TextRenderer>, // Draws the HUD, None when running headless
+    hud_visible: bool,
+    live_particles: u32, // Read back every few frames for the HUD
+    paused: bool,
+    step_requested: bool, // Runs one step while paused
+    setup: Input, // The block, scenario or mask the simulation started with, reset starts it over
+    output: PathBuf, // Where snapshots are saved
+    device: Arc<wgpu::Device>, // Shared with the simulation
+    queue: Arc<wgpu::Queue>,
+    config: wgpu::SurfaceConfiguration,
+    size: PhysicalSize<u32>,
+    shader_watcher: Option<ShaderWatcher>, // Only set with --hot-reload
+    render_pipeline: wgpu::RenderPipeline,
+    sim: FluidSimulation,
+    frame_count: u32,
+    particles: Vec<Particle>, // The last particles read back, for the energy plot and the snapshots
+    mouse: MouseTool,
+    last_mouse_position: [f32; 2], // Where the cursor was last step, to find how far it moved
+    touches: Touches,
+    shift_pressed: bool,
+    steps: u64, // How many steps have run, inputs are recorded and played back by step
+    recorder: Option<Recorder>, // Only set with --record
+    playback: Option<Playback>, // Only set with --playback
+}
+
+impl<'a> State<'a> {
+    async fn new(window: &'a Window, grid: GridConfig, seed: u64, adapter: &AdapterOptions) -> Result<Self, String> {
+        Self::create(Some(window), grid, seed, adapter).await
+    }
+
+    // The app without a window, it can step but not render
+    async fn headless(grid: GridConfig, seed: u64, adapter: &AdapterOptions) -> Result<Self, String> {
+        Self::create(None, grid, seed, adapter).await
+    }
+
+    async fn create(window: Option<&'a Window>, grid: GridConfig, seed: u64, adapter: &AdapterOptions) -> Result<Self, String> {
+        let size = window.map_or(PhysicalSize::new(SCREEN_SIZE.0, SCREEN_SIZE.1), |window| window.inner_size());
+
+        let GpuContext { surface, device, queue, config } = GpuContext::new(window, size, adapter, simulation::device_limits()).await?;
+
+        let mut sim = FluidSimulation::new(device.clone(), queue.clone(), FluidConfig { grid, seed, particles: DEFAULT_ACTIVE_PARTICLES })?;
+        sim.params.set_window_size(size);
+        let render_pipeline = create_render_pipeline(&device, &sim.shader_source, &sim.bind_group_layouts, config.format);
+
+        Ok(Self {
+            window,
+            gui: window.map(|window| Gui::new(&device, config.format, window)),
+            panel: Panel::new(),
+            text: window.map(|_| TextRenderer::new(&device, config.format, [config.width, config.height])),
+            hud_visible: true,
+            live_particles: 0,
+            paused: false,
+            step_requested: false,
+            setup: Input::Particles(DEFAULT_ACTIVE_PARTICLES),
+            output: PathBuf::from("."),
+            shader_watcher: None,
+            surface,
+            device,
+            queue,
+            config,
+            size,
+            render_pipeline,
+            sim,
+            frame_count: 0,
+            particles: vec![],
+            mouse: MouseTool::new(),
+            last_mouse_position: [0.0, 0.0],
+            touches: Touches::new(),
+            shift_pressed: false,
+            steps: 0,
+            recorder: None,
+            playback: None,
+        })
+    }
+
+    // Rebuilds the pipelines when the shader file changes, a shader that doesn't compile is reported and the old one is kept
+    fn hot_reload_shaders(&mut self) {
+        let Some(watcher) = self.shader_watcher.as_mut() else {
+            return;
+        };
+        let (device, format) = (&self.device, self.config.format);
+        let reloaded = watcher.reload(device, &self.sim.shader_source, preprocess_shader, |source| {
+            let pipelines = self.sim.build_pipelines(source)?;
+            let render_pipeline = create_render_pipeline(device, &pipelines.shader_source, &pipelines.bind_group_layouts, format);
+            Ok((pipelines, render_pipeline))
+        });
+        if let Some((pipelines, render_pipeline)) = reloaded {
+            self.sim.set_pipelines(pipelines);
+            self.render_pipeline = render_pipeline;
+        }
+    }
+
+    // Applies the inputs due before this step, sends them to the GPU and runs the step
+    pub fn update(&mut self) {
+        let due = self.playback.as_mut().map_or(vec![], |playback| playback.due(self.steps));
+        for input in &due {
+            self.apply_input(input);
+        }
+        if self.playback.as_ref().is_some_and(|playback| playback.finished(self.steps)) {
+            println!("The playback has finished, the window has control again");
+            self.playback = None;
+        }
+
+        // The mouse and the touches use the tool together
+        self.mouse.movement = [
+            self.mouse.position[0] - self.last_mouse_position[0],
+            self.mouse.position[1] - self.last_mouse_position[1],
+        ];
+        self.last_mouse_position = self.mouse.position;
+        let mut pointers = vec![Pointer {
+            position: self.mouse.position,
+            movement: self.mouse.movement,
+            pressure: 1.0,
+            buttons: self.mouse.buttons,
+            radius: self.mouse.radius,
+            tool: self.mouse.tool,
+            strength: self.mouse.strength,
+            _padding: 0.0,
+        }];
+        pointers.extend(self.touches.pointers());
+        self.sim.set_pointers(&pointers);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Step Encoder"),
+        });
+        self.sim.step(&mut encoder);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.steps += 1;
+    }
+
+    // Records and applies an input from the window, the window is ignored while a recording plays back
+    fn input(&mut self, input: Input) {
+        if self.playback.is_some() {
+            return;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.steps, &input);
+        }
+        self.apply_input(&input);
+    }
+
+    fn apply_input(&mut self, input: &Input) {
+        match input {
+            Input::MouseMoved(position) => self.mouse.position = *position,
+            Input::MouseButton(button, pressed) => self.mouse.set_button(*button, *pressed),
+            // A touch keeps the tool, radius and strength the mouse had when it went down
+            Input::Touch { id, phase, position, pressure } => {
+                self.touches.update(*id, *phase, *position, *pressure, &self.mouse)
+            }
+            // Scrolling changes the radius of the mouse tool
+            Input::Scroll(notches) => {
+                self.mouse.radius = (self.mouse.radius * MOUSE_TOOL_RADIUS_STEP.powf(*notches))
+                    .clamp(MIN_MOUSE_TOOL_RADIUS, MAX_MOUSE_TOOL_RADIUS);
+            }
+            // Shift makes the mouse tool stronger and control makes it weaker
+            Input::Modifiers { shift, control } => {
+                self.shift_pressed = *shift;
+                self.mouse.strength = if *shift {
+                    STRONG_MOUSE_TOOL
+                } else if *control {
+                    WEAK_MOUSE_TOOL
+                } else {
+                    1.0
+                };
+            }
+            Input::NextTool => {
+                self.mouse.next_tool();
+                println!("Mouse tool: {}", TOOL_NAMES[self.mouse.tool as usize]);
+            }
+            // Holding shift makes the rigid bodies sink instead of float
+            Input::SpawnBox | Input::SpawnDisc => {
+                let position = self.mouse.position;
+                let density = if self.shift_pressed { HEAVY_RIGID_BODY_DENSITY } else { LIGHT_RIGID_BODY_DENSITY };
+                let rigid_body = if *input == Input::SpawnBox {
+                    RigidBody::new_box(position, [30.0, 20.0], density)
+                } else {
+                    RigidBody::new_disc(position, 25.0, density)
+                };
+                self.sim.spawn_rigid_body(rigid_body);
+            }
+            Input::ToggleRenderMode => self.sim.params.render_mode = 1 - self.sim.params.render_mode,
+            // A heat sink instead if shift is held
+            Input::PlaceHeatSource => {
+                let temperature = if self.shift_pressed { COLD_TEMPERATURE } else { HOT_TEMPERATURE };
+                self.sim.place_heat_source(self.mouse.position, temperature);
+            }
+            Input::ClearHeatSources => self.sim.clear_heat_sources(),
+            Input::ToggleWallHeating => self.sim.params.toggle_wall_heating(),
+            Input::Vorticity(stronger) => {
+                let step = if *stronger { VORTICITY_STRENGTH_STEP } else { -VORTICITY_STRENGTH_STEP };
+                self.sim.params.vorticity_strength = (self.sim.params.vorticity_strength + step).max(0.0);
+                println!("Vorticity strength: {}", self.sim.params.vorticity_strength);
+            }
+            Input::Particles(count) => {
+                self.setup = input.clone();
+                self.sim.load_block(*count);
+            }
+            Input::Scenario(scenario) => {
+                self.setup = input.clone();
+                self.sim.load_scenario(*scenario);
+            }
+            Input::Mask(path) => match ImageMask::load(path) {
+                Ok(mask) => {
+                    println!("Mask: {}", path);
+                    self.setup = input.clone();
+                    self.sim.load_mask(&mask);
+                }
+                Err(error) => eprintln!("{}, ignoring the mask", error),
+            },
+            Input::Solver(solver_mode) => self.sim.params.solver_mode = *solver_mode,
+            Input::Kernel(kernel) => self.sim.params.set_kernel(*kernel),
+            Input::Parameter(parameter, value) => parameter.set(&mut self.sim.params, *value),
+        }
+    }
+
+    // Carries out what was asked for in the panel
+    fn apply_action(&mut self, action: Action) {
+        match action {
+            Action::Input(input) => self.input(input),
+            Action::Pause(paused) => self.paused = paused,
+            Action::Step => self.step_requested = true,
+            Action::Reset => self.input(self.setup.clone()),
+            Action::SaveSnapshot => {
+                self.particles = self.sim.read_particles();
+                let path = self.output.join(format!("snapshot-{}.csv", self.steps));
+                match write_active_particles(&path, &self.particles) {
+                    Ok(()) => println!("Saved a snapshot to {}", path.display()),
+                    Err(error) => eprintln!("{}", error),
+                }
+            }
+        }
+    }
+
+    // The kinetic energy of the active particles, each with a mass of 1
+    fn kinetic_energy(&self) -> f64 {
+        self.particles
+            .iter()
+            .filter(|particle| particle.is_active())
+            .map(|particle| 0.5 * (particle.velocity[0].powi(2) + particle.velocity[1].powi(2)) as f64)
+            .sum()
+    }
+}
+
+impl<'a> Simulation<'a> for State<'a> {
+    fn init(window: &'a Window, args: &[String]) -> Result<Self, String> {
+        let options = match cli::parse(args)? {
+            Command::Run(options) => *options,
+            Command::Help => return Err(cli::HELP.to_string()),
+        };
+        if options.headless {
+            return Err("--headless runs without a window".to_string());
+        }
+        let (grid, seed, recording) = prepare(&options)?;
+
+        let _ = window.request_inner_size(PhysicalSize::new(options.window_size[0], options.window_size[1]));
+        let mut state = pollster::block_on(State::new(window, grid, seed, &options.adapter))?;
+        if let Some(output) = &options.output {
+            state.output = output.clone();
+        }
+        if options.hot_reload {
+            println!("Watching {} for changes", SHADER_PATH);
+            state.shader_watcher = Some(ShaderWatcher::new(SHADER_PATH));
+        }
+        if options.profile {
+            state.sim.set_profiler(Profiler::new(&state.device, &state.queue, PROFILE_WINDOW));
+            println!("Profiling with {}", state.sim.profiler.source());
+        }
+
+        match recording {
+            Some(recording) => state.playback = Some(Playback::new(recording)),
+            None => start(&mut state, &options, seed),
+        }
+        Ok(state)
+    }
+
+    fn frame_interval(&self) -> Duration {
+        Duration::from_millis(TIME_BETWEEN_FRAMES)
+    }
+
+    fn step(&mut self) {
+        self.hot_reload_shaders();
+
+        if !self.paused || std::mem::take(&mut self.step_requested) {
+            self.update();
+        }
+    }
+
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let start_time = std::time::Instant::now();
+
+        // Render the particles
+        let drawable = self.surface.as_ref().expect("Rendering needs a window").get_current_texture()?;
+        let image_view_descriptor = wgpu::TextureViewDescriptor::default();
+        let image_view = drawable.texture.create_view(&image_view_descriptor);
+
+        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        };
+        let mut command_encoder = self
+            .device
+            .create_command_encoder(&command_encoder_descriptor);
+
+        // The panel is drawn over the particles in the same render pass
+        let mut actions = vec![];
+        let gui_frame = match (&mut self.gui, self.window) {
+            (Some(gui), Some(window)) => gui.prepare(&self.device, &self.queue, &mut command_encoder, window, |context| {
+                actions = self.panel.show(context, &self.sim.params, self.paused);
+            }),
+            _ => None,
+        };
+
+        let color_attachment = wgpu::RenderPassColorAttachment {
+            view: &image_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.75,
+                    g: 0.5,
+                    b: 0.25,
+                    a: 1.0,
+                }),
+                store: wgpu::StoreOp::Store,
+            },
+        };
+
+        let render_pass_descriptor = wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(color_attachment)],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: self.sim.profiler.render_pass("render"),
+        };
+
+        {
+            let mut render_pass = command_encoder.begin_render_pass(&render_pass_descriptor);
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, self.sim.render_bind_group(), &[]);
+            render_pass.draw(0..3, 0..1); // Draw the first triangle
+            render_pass.draw(3..6, 0..1); // Draw the second triangle
+            if let (Some(gui), Some(frame)) = (&self.gui, &gui_frame) {
+                gui.paint(&mut render_pass, frame);
+            }
+        }
+
+        // The HUD goes on top in its own pass
+        let hud = self.hud_visible.then(|| hud_text(&self.diagnostics(), self.paused));
+        if let Some(text) = &mut self.text {
+            if let Some(hud) = hud {
+                text.queue_hud(&hud, self.config.height as f32, HUD_COLOR);
+            }
+            text.draw(&self.queue, &mut command_encoder, &image_view);
+        }
+
+        self.queue.submit(std::iter::once(command_encoder.finish()));
+        self.sim.profiler.end_pass(&self.device);
+
+        drawable.present();
+
+        if let (Some(gui), Some(frame)) = (&mut self.gui, gui_frame) {
+            gui.finish(frame);
+        }
+        for action in actions {
+            self.apply_action(action);
+        }
+        self.panel.record_frame();
+
+        let profiler = &mut self.sim.profiler;
+        profiler.end_frame(&self.device, &self.queue);
+        if profiler.is_enabled() && profiler.table().frames().is_multiple_of(PROFILE_INTERVAL) {
+            println!("Pass timings ({}):\n{}", profiler.source(), profiler.table());
+        }
+
+        if self.frame_count.is_multiple_of(10) {
+            let elapsed_time = start_time.elapsed();
+            println!(
+                "fps: {}",
+                1.0 / elapsed_time.as_micros() as f32 * 1000.0 * 1000.0
+            );
+            self.live_particles = self.sim.live_particles();
+            println!("Particles: {}", self.live_particles);
+            // Reading the particles back is slow, so the energy is only plotted while the panel is open
+            if self.gui.as_ref().is_some_and(Gui::is_visible) {
+                self.particles = self.sim.read_particles();
+                self.panel.record_energy(self.kinetic_energy());
+            }
+            // println!("Compute shaders and rendering time: {} ms", (density_elapsed_time.as_micros() as f32 + forces_elapsed_time.as_micros() as f32 + render_elapsed_time.as_micros() as f32) / 1000.0);
+            self.frame_count = 0;
+        }
+
+        self.frame_count += 1;
+
+        Ok(())
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.sim.params.set_window_size(new_size);
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            if let Some(text) = &self.text {
+                text.resize(&self.queue, [new_size.width, new_size.height]);
+            }
+        }
+    }
+
+    fn handle_input(&mut self, event: &WindowEvent) -> bool {
+        // The panel gets the events first, the ones it uses don't reach the simulation
+        if let (Some(gui), Some(window)) = (&mut self.gui, self.window) {
+            if gui.handle_event(window, event) {
+                return true;
+            }
+        }
+
+        match event {
+            // Show or hide the parameter panel
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F1),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(gui) = &mut self.gui {
+                    gui.toggle();
+                }
+            }
+
+            // Show or hide the HUD
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F2),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => self.hud_visible = !self.hud_visible,
+
+            // The mouse is recorded in simulation coordinates, so a recording plays back the same in any window size
+            WindowEvent::CursorMoved { position, .. } => {
+                let scale = self.sim.params.view_scale;
+                self.input(Input::MouseMoved([position.x as f32 * scale[0], position.y as f32 * scale[1]]));
+                // println!("Mouse position: {:?}", self.mouse.position);
+            }
+            // Every finger and pen is its own pointer, a pen without pressure sensing presses fully
+            WindowEvent::Touch(touch) => {
+                let scale = self.sim.params.view_scale;
+                let phase = match touch.phase {
+                    winit::event::TouchPhase::Started => TouchPhase::Down,
+                    winit::event::TouchPhase::Moved => TouchPhase::Move,
+                    winit::event::TouchPhase::Ended | winit::event::TouchPhase::Cancelled => TouchPhase::Up,
+                };
+                self.input(Input::Touch {
+                    id: touch.id,
+                    phase,
+                    position: [touch.location.x as f32 * scale[0], touch.location.y as f32 * scale[1]],
+                    pressure: touch.force.map_or(1.0, |force| force.normalized() as f32),
+                });
+            }
+            WindowEvent::MouseInput { state: element_state, button, .. } => {
+                let pressed = *element_state == ElementState::Pressed;
+                if *button == MouseButton::Left {
+                    self.input(Input::MouseButton(MOUSE_LEFT, pressed));
+                }
+                if *button == MouseButton::Right {
+                    self.input(Input::MouseButton(MOUSE_RIGHT, pressed));
+                }
+            }
+            // Scrolling changes the radius of the mouse tool
+            WindowEvent::MouseWheel { delta, .. } => {
+                let notches = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+                self.input(Input::Scroll(notches));
+            }
+            // Shift makes the mouse tool stronger and control makes it weaker
+            WindowEvent::ModifiersChanged(modifiers) => self.input(Input::Modifiers {
+                shift: modifiers.state().shift_key(),
+                control: modifiers.state().control_key(),
+            }),
+
+            // Switch to the next mouse tool
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyT),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => self.input(Input::NextTool),
+
+            // Spawn rigid bodies at the mouse, holding shift makes them sink instead of float
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::KeyB | KeyCode::KeyC)),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => self.input(if *key == KeyCode::KeyB { Input::SpawnBox } else { Input::SpawnDisc }),
+
+            // Temperature controls
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::KeyV | KeyCode::KeyH | KeyCode::KeyJ | KeyCode::KeyG)),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => self.input(match key {
+                // Switch between showing speed and density or temperature
+                KeyCode::KeyV => Input::ToggleRenderMode,
+                // Place a heat source at the mouse, or a heat sink if shift is held
+                KeyCode::KeyH => Input::PlaceHeatSource,
+                // Remove all heat sources
+                KeyCode::KeyJ => Input::ClearHeatSources,
+                // Heat the floor and cool the ceiling
+                _ => Input::ToggleWallHeating,
+            }),
+
+            // Start one of the scenarios over
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::Digit1 | KeyCode::Digit2 | KeyCode::Digit3 | KeyCode::Digit4 | KeyCode::Digit5 | KeyCode::Digit6)),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let scenario = match key {
+                    KeyCode::Digit1 => Scenario::DamBreak,
+                    KeyCode::Digit2 => Scenario::DoubleDamBreak,
+                    KeyCode::Digit3 => Scenario::Droplet,
+                    KeyCode::Digit4 => Scenario::Fountain,
+                    KeyCode::Digit5 => Scenario::Cylinder,
+                    _ => Scenario::SloshingTank,
+                };
+                println!("Scenario: {}", scenario.name());
+                self.input(Input::Scenario(scenario));
+            }
+
+            // Change the strength of the vorticity confinement
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::BracketLeft | KeyCode::BracketRight)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.input(Input::Vorticity(*key == KeyCode::BracketRight)),
+
+            _ => return false,
+        }
+        true
+    }
+
+    fn reset(&mut self) {
+        self.input(self.setup.clone());
+    }
+
+    fn diagnostics(&self) -> Vec<(&'static str, String)> {
+        let mut diagnostics = vec![
+            ("FPS", format!("{:.0}", self.panel.fps())),
+            ("Particles", self.live_particles.to_string()),
+            ("Time", format!("{:.1}", self.sim.time())),
+            ("Tool", TOOL_NAMES[self.mouse.tool as usize].to_string()),
+        ];
+        let touches = self.touches.count();
+        if touches > 0 {
+            diagnostics.push(("Touches", touches.to_string()));
+        }
+        diagnostics
+    }
+
+    // A recording is only complete once it knows how many steps ran
+    fn close(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.finish(self.steps);
+        }
+    }
+}
+
+pub async fn run() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(options) = options_or_exit(cli::parse(&args), cli::HELP) else {
+        return;
+    };
+    init_logging(options.log_level.as_deref());
+
+    if options.headless {
+        let result = match prepare(&options) {
+            Ok((grid, seed, recording)) => run_headless(grid, seed, &options, recording).await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let entry = Entry { name: "Fluid", launch, args };
+    if let Err(error) = app::run(options.window_size, &[entry], 0) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+// Starts the fluid in a window from its command line options, for `app::run`
+pub fn launch<'w>(window: &'w Window, args: &[String]) -> Result<Box<dyn Simulation<'w> + 'w>, String> {
+    Ok(Box::new(State::init(window, args)?))
+}
+
+// The help when the options ask for it, so a launcher can print it instead of opening a window
+pub fn help(args: &[String]) -> Option<&'static str> {
+    matches!(cli::parse(args), Ok(Command::Help)).then_some(cli::HELP)
+}
+
+// Creates the output directory and picks the grid and the seed, a recording brings its own seed
+fn prepare(options: &Options) -> Result<(GridConfig, u64, Option<Recording>), String> {
+    // Recordings can go in the output directory, so it has to exist before anything starts
+    if let Some(output) = &options.output {
+        std::fs::create_dir_all(output).map_err(|error| format!("Could not create {}: {}", output.display(), error))?;
+    }
+
+    let grid = grid_config(options.grid);
+    println!("Grid: {}x{} cells, checking {}x{}", grid.size[0], grid.size[1], grid.grids_to_check[0] * 2 + 1, grid.grids_to_check[1] * 2 + 1);
+
+    let recording = match &options.playback {
+        Some(path) => {
+            let recording = Recording::load(path)?;
+            println!("Playing back {} ({} steps)", path, recording.length());
+            Some(recording)
+        }
+        None => None,
+    };
+    let seed = recording.as_ref().map(|recording| recording.seed).or(options.seed).unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    Ok((grid, seed, recording))
+}
+
+// Every pipeline and the entry points it runs
+const PIPELINES: [(&str, &[&str]); 17] = [
+    ("render", &["vs_main", "fs_main"]),
+    ("main_density", &["main_density"]),
+    ("main_vorticity", &["main_vorticity"]),
+    ("main_forces", &["main_forces"]),
+    ("main_move", &["main_move"]),
+    ("main_rigid_bodies", &["main_rigid_bodies"]),
+    ("pbf_predict", &["pbf_predict"]),
+    ("pbf_lambda", &["pbf_lambda"]),
+    ("pbf_delta", &["pbf_delta"]),
+    ("pbf_apply", &["pbf_apply"]),
+    ("pbf_viscosity", &["pbf_viscosity"]),
+    ("pbf_apply_viscosity", &["pbf_apply_viscosity"]),
+    ("update_histogram", &["update_histogram"]),
+    ("update_inclusive_prefix_sum", &["update_inclusive_prefix_sum"]),
+    ("update_indices", &["update_indices"]),
+    ("update_lookup", &["update_lookup"]),
+    ("update_pool", &["update_pool"]),
+];
+
+
+// Draws the particles of the simulation into the window, it has to be rebuilt with the compute pipelines
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    shader_source: &str,
+    bind_group_layouts: &HashMap<&'static str, PipelineBindings>,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let mut render_pipeline_builder = PipelineBuilder::new();
+    render_pipeline_builder.set_shader_module(shader_source, "vs_main", "fs_main");
+    render_pipeline_builder.set_pixel_format(format);
+    render_pipeline_builder.set_bind_group_layout(bind_group_layouts["render"].layout());
+    render_pipeline_builder.build_pipeline(device)
+}
+
+// Derives the grid from the radius of influence, or uses the cell counts from `--grid 80x40` for experiments
+fn grid_config(size: Option<[u32; 2]>) -> GridConfig {
+    let screen_size = [SCREEN_SIZE.0 as f32, SCREEN_SIZE.1 as f32];
+    match size {
+        Some(size) => GridConfig::with_size(screen_size, RADIUS_OF_INFLUENCE, size),
+        None => GridConfig::from_radius(screen_size, RADIUS_OF_INFLUENCE),
+    }
+}
+
+// Starts recording if asked to and applies the options, they are inputs at the first step so they are recorded too
+fn start(state: &mut State, options: &Options, seed: u64) {
+    if let Some(path) = options.record_path() {
+        let path = path.to_string_lossy();
+        match Recorder::create(&path, seed) {
+            Ok(recorder) => {
+                println!("Recording the inputs to {}", path);
+                state.recorder = Some(recorder);
+            }
+            Err(error) => eprintln!("{}, not recording", error),
+        }
+    }
+
+    println!("Solver: {}", if options.solver == SOLVER_POSITION_BASED { "Position Based Fluids" } else { "Explicit SPH" });
+    state.input(Input::Solver(options.solver));
+    println!("Kernel: {}", options.kernel.name());
+    state.input(Input::Kernel(options.kernel));
+    if let Some(count) = options.particles {
+        println!("Particles: {}", count);
+        state.input(Input::Particles(count));
+    }
+    if let Some(scenario) = options.scenario {
+        println!("Scenario: {}", scenario.name());
+        state.input(Input::Scenario(scenario));
+    }
+    if let Some(path) = &options.mask {
+        state.input(Input::Mask(path.clone()));
+    }
+}
+
+// Runs without a window for `--steps` steps, or until the playback ends, and prints where it ended up
+// With `--output` the active particles are written to particles.csv there
+async fn run_headless(grid: GridConfig, seed: u64, options: &Options, recording: Option<Recording>) -> Result<(), String> {
+    let mut state = State::headless(grid, seed, &options.adapter).await?;
+
+    let length = match recording {
+        Some(recording) => {
+            let length = options.steps.unwrap_or(recording.length());
+            state.playback = Some(Playback::new(recording));
+            length
+        }
+        None => {
+            start(&mut state, options, seed);
+            options.steps.unwrap_or(0)
+        }
+    };
+    while state.steps < length {
+        state.update();
+    }
+    if let Some(recorder) = &mut state.recorder {
+        recorder.finish(state.steps);
+    }
+
+    let particles = state.sim.read_particles();
+    let active = particles.iter().filter(|particle| particle.is_active()).count();
+    println!("Ran {} steps, {} particles are active", state.steps, active);
+
+    if let Some(output) = &options.output {
+        let path = output.join("particles.csv");
+        write_active_particles(&path, &particles)?;
+        println!("Wrote the particles to {}", path.display());
+    }
+    Ok(())
+}
+
+// Writes the active particles to a CSV file
+fn write_active_particles(path: &Path, particles: &[Particle]) -> Result<(), String> {
+    let rows = particles
+        .iter()
+        .filter(|particle| particle.is_active())
+        .map(|particle| [particle.position[0], particle.position[1], particle.velocity[0], particle.velocity[1], particle.temperature]);
+    write_particles(path, ["x", "y", "vx", "vy", "temperature"], rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use renderer_backend::adapter::adapter_available;
+    use renderer_backend::bind_group_layout_builder::ShaderReflection;
+    use renderer_backend::golden::Snapshot;
+    use renderer_backend::shader_types::WgslStruct;
+
+    fn parse_shader() -> naga::Module {
+        let source = preprocess_shader(SHADER).unwrap();
+        let module = naga::front::wgsl::parse_str(&source).unwrap_or_else(|error| panic!("{}", error.emit_to_string(&source)));
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .unwrap_or_else(|error| panic!("{:?}", error));
+        module
+    }
+
+    // Compares the layout naga gives the WGSL struct with the layout of the Rust struct
+    fn assert_layout_matches<T: WgslStruct>(module: &naga::Module) {
+        let (members, span) = module.types.iter()
+            .find_map(|(_, ty)| match &ty.inner {
+                naga::TypeInner::Struct { members, span } if ty.name.as_deref() == Some(T::NAME) => Some((members, *span)),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("{} is not in the shader", T::NAME));
+
+        assert_eq!(span as usize, std::mem::size_of::<T>(), "{} has a different size in the shader", T::NAME);
+        let fields = T::fields();
+        assert_eq!(members.len(), fields.len(), "{} has a different number of fields in the shader", T::NAME);
+        for (member, (name, _, offset)) in members.iter().zip(fields) {
+            assert_eq!(member.name.as_deref(), Some(name), "{} has different fields in the shader", T::NAME);
+            assert_eq!(member.offset as usize, offset, "{}.{} has a different offset in the shader", T::NAME, name);
+        }
+    }
+
+    #[test]
+    fn shader_layouts_match() {
+        let module = parse_shader();
+        assert_layout_matches::<Particle>(&module);
+        assert_layout_matches::<ParticlePool>(&module);
+        assert_layout_matches::<Capacity>(&module);
+        assert_layout_matches::<RigidBody>(&module);
+        assert_layout_matches::<HeatSource>(&module);
+        assert_layout_matches::<Pointer>(&module);
+        assert_layout_matches::<SimParams>(&module);
+    }
+
+    #[test]
+    fn pipelines_fit_in_the_default_limits() {
+        let reflection = ShaderReflection::new(&preprocess_shader(SHADER).unwrap()).unwrap();
+        let limit = wgpu::Limits::default().max_storage_buffers_per_shader_stage as usize;
+        for (pipeline, entry_points) in PIPELINES {
+            let bindings = reflection.bindings(entry_points).unwrap();
+            for stage in [wgpu::ShaderStages::VERTEX, wgpu::ShaderStages::FRAGMENT, wgpu::ShaderStages::COMPUTE] {
+                let count = bindings.storage_buffer_count(stage);
+                assert!(count <= limit, "{} uses {} storage buffers in {:?}, the limit is {}", pipeline, count, stage, limit);
+            }
+        }
+    }
+
+    #[test]
+    fn shared_constants_reach_the_shader() {
+        let module = parse_shader();
+        let constant = |name: &str| {
+            let (_, constant) = module.constants.iter()
+                .find(|(_, constant)| constant.name.as_deref() == Some(name))
+                .unwrap_or_else(|| panic!("{} is not in the shader", name));
+            match module.const_expressions[constant.init] {
+                naga::Expression::Literal(naga::Literal::U32(value)) => value as i64,
+                naga::Expression::Literal(naga::Literal::I32(value)) => value as i64,
+                ref expression => panic!("{} is {:?}", name, expression),
+            }
+        };
+        assert_eq!(constant("PARTICLE_WORKGROUP_SIZE"), PARTICLE_WORKGROUP_SIZE as i64);
+        assert_eq!(constant("WORKGROUP_SIZE"), WORKGROUP_SIZE as i64);
+        assert_eq!(constant("BUCKET_SIZE"), BUCKET_SIZE as i64);
+        assert_eq!(constant("NUM_DIGITS"), NUM_DIGITS as i64);
+    }
+
+    #[test]
+    fn the_capacity_covers_every_slot() {
+        for particles in [1, BUCKET_SIZE, TOTAL_PARTICLES, MAX_PARTICLES] {
+            let capacity = Capacity::new(particles);
+            assert!(capacity.buckets * BUCKET_SIZE >= particles);
+            assert!(capacity.particle_workgroups() * PARTICLE_WORKGROUP_SIZE >= particles);
+            assert!(capacity.sort_workgroups() * WORKGROUP_SIZE >= particles);
+            assert!(capacity.sort_workgroups() <= 65535, "{} particles need too many workgroups", particles);
+            assert!(capacity.scan_workgroups() * IPS_WORKGROUP_SIZE >= capacity.buckets);
+            assert!(1 << capacity.scan_stages() >= capacity.buckets);
+        }
+        assert_eq!(initial_particles(DEFAULT_ACTIVE_PARTICLES).len(), TOTAL_PARTICLES as usize);
+        assert_eq!(initial_particles(10).len(), 10 + FREE_POOL_SIZE as usize);
+    }
+
+    const GOLDEN_SEED: u64 = 1;
+    // The position based solver falls smoothly for about 14 steps before the block reaches the floor,
+    // the explicit solver splashes from the first step so the lattice it starts on is no reference
+    const GOLDEN_STEPS: u32 = 12;
+    const GOLDEN_PARTICLES: u32 = 8192; // A small block keeps the run quick on the software adapter
+    const GOLDEN_QUANTILES: [f32; 3] = [0.1, 0.5, 0.9];
+    // On the software adapter, nudging every particle by up to 1e-3 pixels, more than 16 times the rounding of a position,
+    // moves no value by more than 1e-5, so rounding differences between GPUs stay well inside this
+    const GOLDEN_TOLERANCE: f32 = 1e-4;
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/golden");
+    const SEED_PARTICLES: u32 = 4096; // Enough to pour onto without slowing the software adapter down
+
+    // The quantiles of the values, the nearest one for each fraction
+    fn quantiles(mut values: Vec<f32>) -> [f32; GOLDEN_QUANTILES.len()] {
+        values.sort_by(f32::total_cmp);
+        GOLDEN_QUANTILES.map(|fraction| values[((values.len() - 1) as f32 * fraction).round() as usize])
+    }
+
+    // Only the active particles count, and as the sort moves them between slots every step they are compared through
+    // the spread of their positions and densities rather than slot by slot
+    fn golden_snapshot(particles: &[Particle]) -> Snapshot {
+        let active: Vec<&Particle> = particles.iter().filter(|particle| particle.is_active()).collect();
+        let count = active.len() as f32;
+        let mean = |value: fn(&Particle) -> f32| active.iter().map(|particle| value(particle)).sum::<f32>() / count;
+
+        let mut snapshot = Snapshot::new();
+        snapshot.value("active", count);
+        snapshot.value("centre_x", mean(|particle| particle.position[0]));
+        snapshot.value("centre_y", mean(|particle| particle.position[1]));
+        snapshot.value("kinetic_energy", mean(|particle| 0.5 * (particle.velocity[0].powi(2) + particle.velocity[1].powi(2))));
+        snapshot.value("mean_density", mean(|particle| particle.density));
+        let spreads = [
+            ("x", active.iter().map(|particle| particle.position[0]).collect()),
+            ("y", active.iter().map(|particle| particle.position[1]).collect()),
+            ("density", active.iter().map(|particle| particle.density).collect()),
+        ];
+        for (name, values) in spreads {
+            for (fraction, quantile) in GOLDEN_QUANTILES.iter().zip(quantiles(values)) {
+                snapshot.value(format!("{}_{}", name, (fraction * 100.0) as u32), quantile);
+            }
+        }
+        snapshot
+    }
+
+    #[test]
+    fn golden_run_matches_reference() {
+        if !pollster::block_on(adapter_available(&AdapterOptions::default())) {
+            eprintln!("There is no adapter, skipping the golden run");
+            return;
+        }
+        let grid = GridConfig::from_radius([SCREEN_SIZE.0 as f32, SCREEN_SIZE.1 as f32], RADIUS_OF_INFLUENCE);
+        let config = FluidConfig { grid, seed: GOLDEN_SEED, particles: GOLDEN_PARTICLES };
+        let mut sim = pollster::block_on(FluidSimulation::headless(config, &AdapterOptions::default())).expect("The headless simulation should build");
+        sim.params.solver_mode = SOLVER_POSITION_BASED;
+        for _ in 0..GOLDEN_STEPS {
+            let mut encoder = sim.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            sim.step(&mut encoder);
+            sim.queue.submit(std::iter::once(encoder.finish()));
+        }
+        golden_snapshot(&sim.read_particles()).check(GOLDEN_DIR, "fluid", GOLDEN_TOLERANCE);
+    }
+
+    // One step of pouring into a small block, where the poured particles land is what the seed decides
+    fn poured_positions(seed: u64) -> Vec<[f32; 2]> {
+        let config = FluidConfig { seed, particles: SEED_PARTICLES, ..FluidConfig::default() };
+        let mut sim = pollster::block_on(FluidSimulation::headless(config, &AdapterOptions::default())).expect("The headless simulation should build");
+        let pour = TOOL_NAMES.iter().position(|&name| name == "Pour").unwrap() as u32;
+        sim.set_pointers(&[Pointer::new([SCREEN_SIZE.0 as f32 / 2.0, SCREEN_SIZE.1 as f32 / 2.0], pour, MOUSE_TOOL_RADIUS)]);
+
+        let mut encoder = sim.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        sim.step(&mut encoder);
+        sim.queue.submit(std::iter::once(encoder.finish()));
+        sim.read_particles().iter().map(|particle| particle.position).collect()
+    }
+
+    #[test]
+    fn seeds_repeat_the_initial_conditions() {
+        if !pollster::block_on(adapter_available(&AdapterOptions::default())) {
+            eprintln!("There is no adapter, skipping the seeded runs");
+            return;
+        }
+        let first = poured_positions(GOLDEN_SEED);
+        assert_eq!(poured_positions(GOLDEN_SEED), first);
+        assert_ne!(poured_positions(GOLDEN_SEED + 1), first);
+    }
+}
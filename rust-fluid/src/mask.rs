@@ -0,0 +1,169 @@
+use std::io::Read;
+use crate::scenarios::Region;
+use crate::{COLD_TEMPERATURE, HOT_TEMPERATURE, REFERENCE_TEMPERATURE, SCREEN_SIZE, WALL_CELL_SIZE, WALL_GRID_SIZE};
+
+// The colours of an image mask, stretched over the screen, anything else is left empty
+const WALL_COLOUR: [u8; 3] = [0, 0, 0]; // Black
+const FLUID_COLOURS: [([u8; 3], f32); 3] = [
+    ([0, 0, 255], REFERENCE_TEMPERATURE), // Blue
+    ([255, 0, 0], HOT_TEMPERATURE), // Red
+    ([0, 255, 255], COLD_TEMPERATURE), // Cyan
+]; // Each fluid colour starts at a different temperature
+const COLOUR_TOLERANCE: i32 = 64; // How far each channel can be from a colour, so antialiased edges still count
+const OPAQUE: u8 = 128; // Pixels more transparent than this are empty
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaskCell {
+    Empty,
+    Wall,
+    Fluid(f32), // The starting temperature
+}
+
+// Initial conditions painted in an image
+pub struct ImageMask {
+    width: u32,
+    height: u32,
+    cells: Vec<MaskCell>,
+}
+
+// The fluid colour or wall closest to a pixel, if it is close enough
+fn classify(pixel: [u8; 4]) -> MaskCell {
+    if pixel[3] < OPAQUE {
+        return MaskCell::Empty;
+    }
+
+    let distance = |colour: [u8; 3]| (0..3).map(|c| (pixel[c] as i32 - colour[c] as i32).abs()).max().unwrap();
+    let candidates = FLUID_COLOURS
+        .iter()
+        .map(|&(colour, temperature)| (distance(colour), MaskCell::Fluid(temperature)))
+        .chain(std::iter::once((distance(WALL_COLOUR), MaskCell::Wall)));
+    match candidates.min_by_key(|&(distance, _)| distance) {
+        Some((distance, cell)) if distance <= COLOUR_TOLERANCE => cell,
+        _ => MaskCell::Empty,
+    }
+}
+
+impl ImageMask {
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = std::fs::File::open(path).map_err(|error| format!("Could not open {}: {}", path, error))?;
+        Self::decode(file).map_err(|error| format!("Could not read {}: {}", path, error))
+    }
+
+    // Reads a PNG of any colour type and bit depth
+    pub fn decode(reader: impl Read) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(|error| error.to_string())?;
+        let bytes = &buffer[..info.buffer_size()];
+
+        let rgba: Vec<u8> = match info.color_type {
+            png::ColorType::Rgba => bytes.to_vec(),
+            png::ColorType::Rgb => bytes.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => bytes.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => bytes.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+            png::ColorType::Indexed => return Err("The palette was not expanded".to_string()),
+        };
+        Ok(Self::from_rgba(info.width, info.height, &rgba))
+    }
+
+    pub fn from_rgba(width: u32, height: u32, rgba: &[u8]) -> Self {
+        assert_eq!(rgba.len(), (width * height * 4) as usize, "A {}x{} image needs 4 bytes per pixel", width, height);
+        ImageMask {
+            width,
+            height,
+            cells: rgba.chunks(4).map(|p| classify([p[0], p[1], p[2], p[3]])).collect(),
+        }
+    }
+
+    // The cell under a point on the screen, the image is stretched to fit the screen
+    pub fn sample(&self, point: [f32; 2]) -> MaskCell {
+        let x = (point[0] / SCREEN_SIZE.0 as f32 * self.width as f32) as i64;
+        let y = (point[1] / SCREEN_SIZE.1 as f32 * self.height as f32) as i64;
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return MaskCell::Empty;
+        }
+        self.cells[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    // The starting positions and temperatures of the particles on a lattice with this spacing
+    pub fn particles(&self, spacing: [f32; 2]) -> Vec<([f32; 2], f32)> {
+        let screen = Region::Rect { min: [0.0, 0.0], max: [SCREEN_SIZE.0 as f32, SCREEN_SIZE.1 as f32] };
+        screen
+            .fill(spacing)
+            .into_iter()
+            .filter_map(|point| match self.sample(point) {
+                MaskCell::Fluid(temperature) => Some((point, temperature)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // One value per wall cell, row by row, 1 where the mask is solid
+    pub fn walls(&self) -> Vec<u32> {
+        let mut walls = Vec::with_capacity((WALL_GRID_SIZE[0] * WALL_GRID_SIZE[1]) as usize);
+        for y in 0..WALL_GRID_SIZE[1] {
+            for x in 0..WALL_GRID_SIZE[0] {
+                let centre = [(x as f32 + 0.5) * WALL_CELL_SIZE, (y as f32 + 0.5) * WALL_CELL_SIZE];
+                walls.push((self.sample(centre) == MaskCell::Wall) as u32);
+            }
+        }
+        walls
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PARTICLE_SPACING;
+
+    #[test]
+    fn colours_are_classified_with_a_tolerance() {
+        assert_eq!(classify([0, 0, 255, 255]), MaskCell::Fluid(REFERENCE_TEMPERATURE));
+        assert_eq!(classify([230, 20, 10, 255]), MaskCell::Fluid(HOT_TEMPERATURE));
+        assert_eq!(classify([10, 240, 250, 255]), MaskCell::Fluid(COLD_TEMPERATURE));
+        assert_eq!(classify([20, 20, 20, 255]), MaskCell::Wall);
+        assert_eq!(classify([255, 255, 255, 255]), MaskCell::Empty);
+        assert_eq!(classify([128, 128, 255, 255]), MaskCell::Empty);
+        assert_eq!(classify([0, 0, 255, 0]), MaskCell::Empty);
+    }
+
+    // A 2x1 image with fluid on the left and a wall on the right
+    fn half_and_half() -> Vec<u8> {
+        vec![0, 0, 255, 255, 0, 0, 0, 255]
+    }
+
+    #[test]
+    fn the_image_is_stretched_over_the_screen() {
+        let mask = ImageMask::from_rgba(2, 1, &half_and_half());
+        let middle = SCREEN_SIZE.0 as f32 / 2.0;
+
+        let particles = mask.particles(PARTICLE_SPACING);
+        assert!(!particles.is_empty());
+        assert!(particles.iter().all(|&(point, _)| point[0] < middle));
+
+        let walls = mask.walls();
+        assert_eq!(walls.len(), (WALL_GRID_SIZE[0] * WALL_GRID_SIZE[1]) as usize);
+        assert_eq!(walls.iter().sum::<u32>(), WALL_GRID_SIZE[0] / 2 * WALL_GRID_SIZE[1]);
+        assert_eq!(walls[0], 0);
+        assert_eq!(walls[WALL_GRID_SIZE[0] as usize - 1], 1);
+    }
+
+    #[test]
+    fn png_files_decode() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[0, 0, 255, 0, 0, 0]).unwrap();
+        }
+
+        let mask = ImageMask::decode(bytes.as_slice()).unwrap();
+        assert_eq!(mask.cells, [MaskCell::Fluid(REFERENCE_TEMPERATURE), MaskCell::Wall]);
+        assert!(ImageMask::decode(&b"not a png"[..]).is_err());
+    }
+}
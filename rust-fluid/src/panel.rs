@@ -0,0 +1,239 @@
+use std::ops::RangeInclusive;
+use crate::kernels::Kernel;
+use crate::recording::Input;
+use renderer_backend::gui::History;
+use crate::scenarios::Scenario;
+use crate::{SimParams, SOLVER_EXPLICIT, SOLVER_POSITION_BASED};
+
+const PLOT_LENGTH: usize = 300; // How many samples the plots show
+
+// The physical parameters the panel has sliders for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parameter {
+    GravityX,
+    GravityY,
+    TargetDensity,
+    PressureMultiplier,
+    Viscosity,
+    WallDampening,
+    VorticityStrength,
+    Buoyancy,
+    ThermalDiffusivity,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 9] = [
+        Parameter::GravityX,
+        Parameter::GravityY,
+        Parameter::TargetDensity,
+        Parameter::PressureMultiplier,
+        Parameter::Viscosity,
+        Parameter::WallDampening,
+        Parameter::VorticityStrength,
+        Parameter::Buoyancy,
+        Parameter::ThermalDiffusivity,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|parameter| parameter.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Parameter::GravityX => "gravity-x",
+            Parameter::GravityY => "gravity-y",
+            Parameter::TargetDensity => "target-density",
+            Parameter::PressureMultiplier => "pressure",
+            Parameter::Viscosity => "viscosity",
+            Parameter::WallDampening => "wall-dampening",
+            Parameter::VorticityStrength => "vorticity",
+            Parameter::Buoyancy => "buoyancy",
+            Parameter::ThermalDiffusivity => "thermal-diffusivity",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Parameter::GravityX => "Gravity x",
+            Parameter::GravityY => "Gravity y",
+            Parameter::TargetDensity => "Target density",
+            Parameter::PressureMultiplier => "Pressure multiplier",
+            Parameter::Viscosity => "Viscosity",
+            Parameter::WallDampening => "Wall dampening",
+            Parameter::VorticityStrength => "Vorticity confinement",
+            Parameter::Buoyancy => "Buoyancy",
+            Parameter::ThermalDiffusivity => "Thermal diffusivity",
+        }
+    }
+
+    // The slider range, wide enough to break the simulation at either end
+    fn range(self) -> RangeInclusive<f32> {
+        match self {
+            Parameter::GravityX | Parameter::GravityY => -1.0..=1.0,
+            Parameter::TargetDensity => 0.05..=1.0,
+            Parameter::PressureMultiplier => 50.0..=2000.0,
+            Parameter::Viscosity => 0.0..=1.0,
+            Parameter::WallDampening => 0.0..=1.0,
+            Parameter::VorticityStrength => 0.0..=1.0,
+            Parameter::Buoyancy => 0.0..=0.5,
+            Parameter::ThermalDiffusivity => 0.0..=2.0,
+        }
+    }
+
+    pub(crate) fn get(self, params: &SimParams) -> f32 {
+        match self {
+            Parameter::GravityX => params.gravity[0],
+            Parameter::GravityY => params.gravity[1],
+            Parameter::TargetDensity => params.target_density,
+            Parameter::PressureMultiplier => params.pressure_multiplier,
+            Parameter::Viscosity => params.viscosity,
+            Parameter::WallDampening => params.wall_dampening,
+            Parameter::VorticityStrength => params.vorticity_strength,
+            Parameter::Buoyancy => params.buoyancy,
+            Parameter::ThermalDiffusivity => params.thermal_diffusivity,
+        }
+    }
+
+    pub(crate) fn set(self, params: &mut SimParams, value: f32) {
+        let field = match self {
+            Parameter::GravityX => &mut params.gravity[0],
+            Parameter::GravityY => &mut params.gravity[1],
+            Parameter::TargetDensity => &mut params.target_density,
+            Parameter::PressureMultiplier => &mut params.pressure_multiplier,
+            Parameter::Viscosity => &mut params.viscosity,
+            Parameter::WallDampening => &mut params.wall_dampening,
+            Parameter::VorticityStrength => &mut params.vorticity_strength,
+            Parameter::Buoyancy => &mut params.buoyancy,
+            Parameter::ThermalDiffusivity => &mut params.thermal_diffusivity,
+        };
+        *field = value;
+    }
+}
+
+// What the panel asks the simulation to do, the changes to the simulation are inputs so they are recorded
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Input(Input),
+    Pause(bool),
+    Step, // Runs one step while paused
+    Reset, // Starts the current block, scenario or mask over
+    SaveSnapshot,
+}
+
+// The parameter panel, shown over the simulation with F1
+pub struct Panel {
+    fps: History,
+    energy: History,
+    frames: u64,
+    last_frame: std::time::Instant,
+}
+
+impl Panel {
+    pub fn new() -> Self {
+        Panel {
+            fps: History::new(PLOT_LENGTH),
+            energy: History::new(PLOT_LENGTH),
+            frames: 0,
+            last_frame: std::time::Instant::now(),
+        }
+    }
+
+    // Called once per frame, the frame rate is measured between the calls
+    pub fn record_frame(&mut self) {
+        let now = std::time::Instant::now();
+        let seconds = now.duration_since(self.last_frame).as_secs_f64();
+        self.last_frame = now;
+        self.frames += 1;
+        if seconds > 0.0 {
+            self.fps.push(self.frames as f64, 1.0 / seconds);
+        }
+    }
+
+    // The latest frame rate
+    pub fn fps(&self) -> f64 {
+        self.fps.latest().unwrap_or(0.0)
+    }
+
+    pub fn record_energy(&mut self, energy: f64) {
+        self.energy.push(self.frames as f64, energy);
+    }
+
+    pub(crate) fn show(&mut self, context: &egui::Context, params: &SimParams, paused: bool) -> Vec<Action> {
+        let mut actions = vec![];
+        egui::Window::new("Simulation").default_width(280.0).show(context, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                    actions.push(Action::Pause(!paused));
+                }
+                if ui.add_enabled(paused, egui::Button::new("Step")).clicked() {
+                    actions.push(Action::Step);
+                }
+                if ui.button("Reset").clicked() {
+                    actions.push(Action::Reset);
+                }
+                if ui.button("Save snapshot").clicked() {
+                    actions.push(Action::SaveSnapshot);
+                }
+            });
+
+            egui::ComboBox::from_label("Preset").selected_text("Start a preset").show_ui(ui, |ui| {
+                for scenario in Scenario::ALL {
+                    if ui.selectable_label(false, scenario.name()).clicked() {
+                        actions.push(Action::Input(Input::Scenario(scenario)));
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                for (solver, name) in [(SOLVER_EXPLICIT, "Explicit SPH"), (SOLVER_POSITION_BASED, "Position Based Fluids")] {
+                    if ui.radio(params.solver_mode == solver, name).clicked() {
+                        actions.push(Action::Input(Input::Solver(solver)));
+                    }
+                }
+            });
+            let current = Kernel::ALL.into_iter().find(|kernel| kernel.id() == params.kernel);
+            egui::ComboBox::from_label("Kernel").selected_text(current.map_or("", Kernel::name)).show_ui(ui, |ui| {
+                for kernel in Kernel::ALL {
+                    if ui.selectable_label(Some(kernel) == current, kernel.name()).clicked() {
+                        actions.push(Action::Input(Input::Kernel(kernel)));
+                    }
+                }
+            });
+
+            ui.separator();
+            for parameter in Parameter::ALL {
+                let mut value = parameter.get(params);
+                if ui.add(egui::Slider::new(&mut value, parameter.range()).text(parameter.label())).changed() {
+                    actions.push(Action::Input(Input::Parameter(parameter, value)));
+                }
+            }
+
+            ui.separator();
+            ui.label(format!("FPS: {:.0}", self.fps()));
+            self.fps.plot(ui, "FPS");
+            ui.label(format!("Kinetic energy: {:.1}", self.energy.latest().unwrap_or(0.0)));
+            self.energy.plot(ui, "Kinetic energy");
+        });
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::GridConfig;
+    use crate::{RADIUS_OF_INFLUENCE, SCREEN_SIZE};
+
+    #[test]
+    fn parameters_round_trip() {
+        let mut params = SimParams::new(&GridConfig::from_radius([SCREEN_SIZE.0 as f32, SCREEN_SIZE.1 as f32], RADIUS_OF_INFLUENCE));
+        for (i, parameter) in Parameter::ALL.into_iter().enumerate() {
+            assert_eq!(Parameter::from_name(parameter.name()), Some(parameter));
+            assert!(parameter.range().contains(&parameter.get(&params)), "{} starts outside its slider", parameter.name());
+            parameter.set(&mut params, i as f32 * 0.01);
+        }
+        for (i, parameter) in Parameter::ALL.into_iter().enumerate() {
+            assert_eq!(parameter.get(&params), i as f32 * 0.01);
+        }
+    }
+}
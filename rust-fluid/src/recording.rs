@@ -0,0 +1,295 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use crate::kernels::Kernel;
+use crate::panel::Parameter;
+use crate::scenarios::Scenario;
+use crate::touch::TouchPhase;
+
+// Everything that can change the simulation while it runs, recorded with the step it was applied before
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    MouseMoved([f32; 2]),
+    MouseButton(u32, bool), // The MOUSE_ button and whether it was pressed
+    Touch { id: u64, phase: TouchPhase, position: [f32; 2], pressure: f32 }, // A finger or pen
+    Scroll(f32), // Notches of the scroll wheel
+    Modifiers { shift: bool, control: bool },
+    NextTool,
+    SpawnBox,
+    SpawnDisc,
+    ToggleRenderMode,
+    PlaceHeatSource,
+    ClearHeatSources,
+    ToggleWallHeating,
+    Vorticity(bool), // true to strengthen the vorticity confinement
+    Particles(u32), // Starts over with this many particles in the block filling the screen
+    Scenario(Scenario),
+    Mask(String), // The path of the image
+    Solver(u32),
+    Kernel(Kernel),
+    Parameter(Parameter, f32), // A slider in the panel
+}
+
+impl Input {
+
+    // The name and arguments written to a recording
+    pub fn to_text(&self) -> String {
+        match self {
+            Input::MouseMoved(position) => format!("mouse {:?} {:?}", position[0], position[1]),
+            Input::MouseButton(button, pressed) => format!("button {} {}", button, if *pressed { "down" } else { "up" }),
+            Input::Touch { id, phase, position, pressure } => {
+                format!("touch {} {} {:?} {:?} {:?}", id, phase.name(), position[0], position[1], pressure)
+            }
+            Input::Scroll(notches) => format!("scroll {:?}", notches),
+            Input::Modifiers { shift, control } => format!("modifiers {} {}", *shift as u32, *control as u32),
+            Input::NextTool => "tool".to_string(),
+            Input::SpawnBox => "box".to_string(),
+            Input::SpawnDisc => "disc".to_string(),
+            Input::ToggleRenderMode => "render-mode".to_string(),
+            Input::PlaceHeatSource => "heat".to_string(),
+            Input::ClearHeatSources => "clear-heat".to_string(),
+            Input::ToggleWallHeating => "wall-heating".to_string(),
+            Input::Vorticity(stronger) => format!("vorticity {}", if *stronger { "+" } else { "-" }),
+            Input::Particles(count) => format!("particles {}", count),
+            Input::Scenario(scenario) => format!("scenario {}", scenario.name()),
+            Input::Mask(path) => format!("mask {}", path),
+            Input::Solver(solver) => format!("solver {}", solver),
+            Input::Kernel(kernel) => format!("kernel {}", kernel.name()),
+            Input::Parameter(parameter, value) => format!("parameter {} {:?}", parameter.name(), value),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (name, arguments) = text.split_once(' ').unwrap_or((text, ""));
+        let words: Vec<&str> = arguments.split_whitespace().collect();
+        let number = |i: usize| -> Result<f32, String> {
+            words.get(i).and_then(|word| word.parse().ok()).ok_or_else(|| format!("{:?} is missing a number", text))
+        };
+        let flag = |i: usize| -> Result<bool, String> {
+            match words.get(i) {
+                Some(&"1") | Some(&"down") | Some(&"+") => Ok(true),
+                Some(&"0") | Some(&"up") | Some(&"-") => Ok(false),
+                _ => Err(format!("{:?} is missing an on or off value", text)),
+            }
+        };
+
+        Ok(match name {
+            "mouse" => Input::MouseMoved([number(0)?, number(1)?]),
+            "button" => Input::MouseButton(number(0)? as u32, flag(1)?),
+            "touch" => Input::Touch {
+                id: words.first().and_then(|id| id.parse().ok()).ok_or_else(|| format!("{:?} is missing a touch id", text))?,
+                phase: words.get(1).and_then(|phase| TouchPhase::from_name(phase)).ok_or_else(|| format!("Unknown touch phase in {:?}", text))?,
+                position: [number(2)?, number(3)?],
+                pressure: number(4)?,
+            },
+            "scroll" => Input::Scroll(number(0)?),
+            "modifiers" => Input::Modifiers { shift: flag(0)?, control: flag(1)? },
+            "tool" => Input::NextTool,
+            "box" => Input::SpawnBox,
+            "disc" => Input::SpawnDisc,
+            "render-mode" => Input::ToggleRenderMode,
+            "heat" => Input::PlaceHeatSource,
+            "clear-heat" => Input::ClearHeatSources,
+            "wall-heating" => Input::ToggleWallHeating,
+            "vorticity" => Input::Vorticity(flag(0)?),
+            "particles" => Input::Particles(number(0)? as u32),
+            "scenario" => Input::Scenario(Scenario::from_name(arguments).ok_or_else(|| format!("Unknown scenario in {:?}", text))?),
+            "mask" if !arguments.is_empty() => Input::Mask(arguments.to_string()),
+            "solver" => Input::Solver(number(0)? as u32),
+            "kernel" => Input::Kernel(Kernel::from_name(arguments).ok_or_else(|| format!("Unknown kernel in {:?}", text))?),
+            "parameter" => Input::Parameter(
+                words.first().and_then(|name| Parameter::from_name(name)).ok_or_else(|| format!("Unknown parameter in {:?}", text))?,
+                number(1)?,
+            ),
+            _ => return Err(format!("Unknown input {:?}", text)),
+        })
+    }
+}
+
+// A run that can be played back: the seed, then one `step input` line per input, then the step it ended on
+#[derive(Debug, Default, PartialEq)]
+pub struct Recording {
+    pub seed: u64,
+    pub inputs: Vec<(u64, Input)>,
+    pub end: Option<u64>, // Missing when the recording was cut short
+}
+
+impl Recording {
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+        Self::parse(&text).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let seed = match lines.next().map(|(_, line)| line.split_once(' ')) {
+            Some(Some(("seed", seed))) => seed.trim().parse().map_err(|_| format!("Invalid seed {:?}", seed))?,
+            _ => return Err("A recording starts with its seed".to_string()),
+        };
+
+        let mut recording = Recording { seed, ..Default::default() };
+        for (number, line) in lines {
+            let (step, input) = line.split_once(' ').unwrap_or((line, ""));
+            if step == "end" {
+                recording.end = Some(input.trim().parse().map_err(|_| format!("Line {} has an invalid step: {:?}", number + 1, line))?);
+                break;
+            }
+            let step = step.parse().map_err(|_| format!("Line {} should start with a step: {:?}", number + 1, line))?;
+            let input = Input::parse(input).map_err(|error| format!("Line {}: {}", number + 1, error))?;
+            recording.inputs.push((step, input));
+        }
+        Ok(recording)
+    }
+
+    // The step playback runs until
+    pub fn length(&self) -> u64 {
+        self.end.unwrap_or_else(|| self.inputs.last().map_or(0, |(step, _)| step + 1))
+    }
+}
+
+// Writes the inputs as they happen, so a recording survives a crash up to the last input
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+
+    pub fn create(path: &str, seed: u64) -> Result<Self, String> {
+        let file = File::create(path).map_err(|error| format!("Could not create {}: {}", path, error))?;
+        let mut recorder = Recorder {
+            writer: BufWriter::new(file),
+        };
+        recorder.write(&format!("seed {}", seed));
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, step: u64, input: &Input) {
+        self.write(&format!("{} {}", step, input.to_text()));
+    }
+
+    pub fn finish(&mut self, step: u64) {
+        self.write(&format!("end {}", step));
+    }
+
+    fn write(&mut self, line: &str) {
+        if let Err(error) = writeln!(self.writer, "{}", line).and_then(|_| self.writer.flush()) {
+            eprintln!("Could not record an input: {}", error);
+        }
+    }
+}
+
+// Feeds a recording back in at the steps it was recorded at
+pub struct Playback {
+    recording: Recording,
+    next: usize,
+}
+
+impl Playback {
+
+    pub fn new(recording: Recording) -> Self {
+        Playback {
+            recording,
+            next: 0,
+        }
+    }
+
+    pub fn length(&self) -> u64 {
+        self.recording.length()
+    }
+
+    // The inputs that were applied before this step
+    pub fn due(&mut self, step: u64) -> Vec<Input> {
+        let mut inputs = vec![];
+        while let Some((input_step, input)) = self.recording.inputs.get(self.next) {
+            if *input_step > step {
+                break;
+            }
+            inputs.push(input.clone());
+            self.next += 1;
+        }
+        inputs
+    }
+
+    pub fn finished(&self, step: u64) -> bool {
+        step >= self.length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Recording {
+        Recording {
+            seed: 42,
+            inputs: vec![
+                (0, Input::Solver(1)),
+                (0, Input::Kernel(Kernel::Spiky)),
+                (0, Input::Particles(5000)),
+                (0, Input::Scenario(Scenario::DamBreak)),
+                (3, Input::MouseMoved([10.5, 20.25])),
+                (3, Input::Modifiers { shift: true, control: false }),
+                (4, Input::MouseButton(1, true)),
+                (5, Input::Touch { id: 3, phase: TouchPhase::Down, position: [100.0, 50.5], pressure: 0.75 }),
+                (6, Input::Touch { id: 3, phase: TouchPhase::Up, position: [110.0, 50.5], pressure: 1.0 }),
+                (9, Input::Scroll(-2.0)),
+                (9, Input::Vorticity(false)),
+                (12, Input::Mask("masks/a b.png".to_string())),
+                (12, Input::MouseButton(1, false)),
+                (15, Input::Parameter(Parameter::Viscosity, 0.25)),
+            ],
+            end: Some(20),
+        }
+    }
+
+    fn to_text(recording: &Recording) -> String {
+        let mut text = format!("seed {}\n", recording.seed);
+        for (step, input) in &recording.inputs {
+            text += &format!("{} {}\n", step, input.to_text());
+        }
+        text + &format!("end {}\n", recording.end.unwrap())
+    }
+
+    #[test]
+    fn recordings_round_trip() {
+        let recording = example();
+        assert_eq!(Recording::parse(&to_text(&recording)).unwrap(), recording);
+        assert!(Recording::parse("3 tool\n").is_err());
+        assert!(Recording::parse("seed 1\n3 teleport\n").is_err());
+        assert!(Recording::parse("seed 1\nsoon tool\n").is_err());
+    }
+
+    #[test]
+    fn cut_short_recordings_end_after_the_last_input() {
+        let recording = Recording::parse("seed 7\n2 tool\n5 box\n").unwrap();
+        assert_eq!(recording.end, None);
+        assert_eq!(recording.length(), 6);
+    }
+
+    #[test]
+    fn playback_feeds_inputs_at_their_steps() {
+        let mut playback = Playback::new(example());
+        assert_eq!(playback.due(0).len(), 4);
+        assert!(playback.due(2).is_empty());
+        assert_eq!(playback.due(3), [Input::MouseMoved([10.5, 20.25]), Input::Modifiers { shift: true, control: false }]);
+        assert_eq!(playback.due(10).len(), 5);
+        assert!(!playback.finished(19));
+        assert!(playback.finished(20));
+    }
+
+    #[test]
+    fn recorder_writes_a_playable_file() {
+        let path = std::env::temp_dir().join(format!("fluid-recording-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        {
+            let mut recorder = Recorder::create(path, 42).unwrap();
+            for (step, input) in &example().inputs {
+                recorder.record(*step, input);
+            }
+            recorder.finish(20);
+        }
+        let recording = Recording::load(path);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(recording.unwrap(), example());
+    }
+}
@@ -0,0 +1,245 @@
+use std::f32::consts::PI;
+use crate::{RigidBody, SimParams, GRAVITY, RIGID_BODY_BOX, SCREEN_SIZE};
+
+const WIDTH: f32 = SCREEN_SIZE.0 as f32;
+const HEIGHT: f32 = SCREEN_SIZE.1 as f32;
+
+const FOUNTAIN_SPEED: f32 = 30.0; // How fast the fountain jet leaves the floor
+const FOUNTAIN_RATE: u32 = 8; // How many particles the fountain emits each step
+const CHANNEL_SPEED: f32 = 10.0; // How fast the flow past the cylinder enters the channel
+const CHANNEL_RATE: u32 = 16; // How many particles enter the channel each step
+const SLOSH_AMPLITUDE: f32 = 0.3; // The largest sideways acceleration of the sloshing tank, relative to gravity
+const SLOSH_PERIOD: f32 = 1500.0; // How many steps the sloshing tank takes to rock back and forth
+
+// The starting setups that can be picked with --scenario or the number keys, the default is the block filling the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    DamBreak, // A column of fluid collapsing into the empty tank
+    DoubleDamBreak, // Two columns collapsing into each other
+    Droplet, // A drop falling into a still pool
+    Fountain, // A jet from the floor of a pool, drained in the corner
+    Cylinder, // A channel flowing past a fixed cylinder, without gravity
+    SloshingTank, // A shallow pool in a tank rocking from side to side
+}
+
+// An area that starts filled with fluid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Rect { min: [f32; 2], max: [f32; 2] },
+    Disc { centre: [f32; 2], radius: f32 },
+}
+
+impl Region {
+    pub fn contains(self, point: [f32; 2]) -> bool {
+        match self {
+            Region::Rect { min, max } => point[0] >= min[0] && point[0] < max[0] && point[1] >= min[1] && point[1] < max[1],
+            Region::Disc { centre, radius } => (point[0] - centre[0]).powi(2) + (point[1] - centre[1]).powi(2) < radius * radius,
+        }
+    }
+
+    fn bounds(self) -> ([f32; 2], [f32; 2]) {
+        match self {
+            Region::Rect { min, max } => (min, max),
+            Region::Disc { centre, radius } => ([centre[0] - radius, centre[1] - radius], [centre[0] + radius, centre[1] + radius]),
+        }
+    }
+
+    // The points of a lattice with this spacing inside the region
+    pub fn fill(self, spacing: [f32; 2]) -> Vec<[f32; 2]> {
+        let (min, max) = self.bounds();
+        let columns = ((max[0] - min[0]) / spacing[0]).floor() as u32;
+        let rows = ((max[1] - min[1]) / spacing[1]).floor() as u32;
+
+        let mut points = vec![];
+        for i in 0..columns {
+            for j in 0..rows {
+                let point = [
+                    min[0] + (i as f32 + 0.5) * spacing[0],
+                    min[1] + (j as f32 + 0.5) * spacing[1],
+                ];
+                if self.contains(point) {
+                    points.push(point);
+                }
+            }
+        }
+        points
+    }
+}
+
+// Whether a point is inside a rigid body, only used to keep the fluid out of the obstacles
+fn inside_rigid_body(body: &RigidBody, point: [f32; 2]) -> bool {
+    let offset = [point[0] - body.position[0], point[1] - body.position[1]];
+    let (sin, cos) = (-body.angle).sin_cos();
+    let local = [offset[0] * cos - offset[1] * sin, offset[0] * sin + offset[1] * cos];
+    if body.shape == RIGID_BODY_BOX {
+        local[0].abs() < body.size[0] && local[1].abs() < body.size[1]
+    } else {
+        local[0] * local[0] + local[1] * local[1] < body.size[0] * body.size[0]
+    }
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 6] = [
+        Scenario::DamBreak,
+        Scenario::DoubleDamBreak,
+        Scenario::Droplet,
+        Scenario::Fountain,
+        Scenario::Cylinder,
+        Scenario::SloshingTank,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scenario| scenario.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scenario::DamBreak => "dam-break",
+            Scenario::DoubleDamBreak => "double-dam-break",
+            Scenario::Droplet => "droplet",
+            Scenario::Fountain => "fountain",
+            Scenario::Cylinder => "cylinder",
+            Scenario::SloshingTank => "sloshing",
+        }
+    }
+
+    // Where the fluid starts, overlapping regions are filled twice
+    pub fn fluid(self) -> Vec<Region> {
+        match self {
+            Scenario::DamBreak => vec![Region::Rect { min: [0.0, 0.25 * HEIGHT], max: [0.4 * WIDTH, HEIGHT] }],
+            Scenario::DoubleDamBreak => vec![
+                Region::Rect { min: [0.0, 0.35 * HEIGHT], max: [0.3 * WIDTH, HEIGHT] },
+                Region::Rect { min: [0.7 * WIDTH, 0.35 * HEIGHT], max: [WIDTH, HEIGHT] },
+            ],
+            Scenario::Droplet => vec![
+                Region::Rect { min: [0.0, 0.7 * HEIGHT], max: [WIDTH, HEIGHT] },
+                Region::Disc { centre: [0.5 * WIDTH, 0.25 * HEIGHT], radius: 0.1 * HEIGHT },
+            ],
+            Scenario::Fountain => vec![Region::Rect { min: [0.0, 0.75 * HEIGHT], max: [WIDTH, HEIGHT] }],
+            Scenario::Cylinder => vec![Region::Rect { min: [0.0, 0.2 * HEIGHT], max: [WIDTH, 0.8 * HEIGHT] }],
+            Scenario::SloshingTank => vec![Region::Rect { min: [0.0, 0.6 * HEIGHT], max: [WIDTH, HEIGHT] }],
+        }
+    }
+
+    // Rigid bodies that never move, they take the first rigid body slots
+    pub(crate) fn obstacles(self) -> Vec<RigidBody> {
+        match self {
+            Scenario::Cylinder => vec![
+                // The walls of the channel
+                RigidBody::new_box([0.5 * WIDTH, 0.1 * HEIGHT], [0.5 * WIDTH, 0.1 * HEIGHT], 1.0).fixed(),
+                RigidBody::new_box([0.5 * WIDTH, 0.9 * HEIGHT], [0.5 * WIDTH, 0.1 * HEIGHT], 1.0).fixed(),
+                RigidBody::new_disc([0.3 * WIDTH, 0.5 * HEIGHT], 0.1 * HEIGHT, 1.0).fixed(),
+            ],
+            _ => vec![],
+        }
+    }
+
+    // The starting particle positions on a lattice with this spacing, outside the obstacles and the drain
+    pub fn particle_positions(self, spacing: [f32; 2]) -> Vec<[f32; 2]> {
+        let (centre, radius) = self.drain();
+        let drain = Region::Disc { centre, radius };
+        let obstacles = self.obstacles();
+
+        self.fluid()
+            .into_iter()
+            .flat_map(|region| region.fill(spacing))
+            .filter(|&point| !drain.contains(point) && !obstacles.iter().any(|body| inside_rigid_body(body, point)))
+            .collect()
+    }
+
+    // Sets the gravity, emitter and drain of the scenario, the other parameters are left as they are
+    pub(crate) fn apply(self, params: &mut SimParams) {
+        params.clear_scenario();
+        params.gravity = self.gravity(0);
+        (params.drain_position, params.drain_radius) = self.drain();
+
+        match self {
+            Scenario::Fountain => {
+                params.emitter_position = [0.5 * WIDTH, HEIGHT - 10.0];
+                params.emitter_velocity = [0.0, -FOUNTAIN_SPEED];
+                params.emitter_radius = 6.0;
+                params.emitter_rate = FOUNTAIN_RATE;
+            }
+            Scenario::Cylinder => {
+                params.emitter_position = [10.0, 0.5 * HEIGHT];
+                params.emitter_velocity = [CHANNEL_SPEED, 0.0];
+                params.emitter_radius = 0.25 * HEIGHT;
+                params.emitter_rate = CHANNEL_RATE;
+            }
+            _ => {}
+        }
+    }
+
+    // The centre and radius of the disc where particles leave the simulation, a radius of 0 has no drain
+    pub fn drain(self) -> ([f32; 2], f32) {
+        match self {
+            Scenario::Fountain => ([0.0, HEIGHT], 30.0),
+            Scenario::Cylinder => ([WIDTH, 0.5 * HEIGHT], 0.2 * HEIGHT),
+            _ => ([0.0, 0.0], 0.0),
+        }
+    }
+
+    // The gravity after this many steps of the scenario, the sloshing tank rocks by tilting it from side to side
+    pub fn gravity(self, step: u32) -> [f32; 2] {
+        match self {
+            Scenario::Cylinder => [0.0, 0.0],
+            Scenario::SloshingTank => [SLOSH_AMPLITUDE * GRAVITY * (2.0 * PI * step as f32 / SLOSH_PERIOD).sin(), GRAVITY],
+            _ => [0.0, GRAVITY],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FREE_POOL_SIZE, MAX_OBSTACLES, MAX_PARTICLES, PARTICLE_SPACING};
+
+    #[test]
+    fn names_round_trip() {
+        for scenario in Scenario::ALL {
+            assert_eq!(Scenario::from_name(scenario.name()), Some(scenario));
+        }
+        assert_eq!(Scenario::from_name("block"), None);
+    }
+
+    #[test]
+    fn scenarios_fit_in_the_buffers() {
+        for scenario in Scenario::ALL {
+            let positions = scenario.particle_positions(PARTICLE_SPACING);
+            assert!(!positions.is_empty(), "{} has no fluid", scenario.name());
+            assert!(positions.len() + FREE_POOL_SIZE as usize <= MAX_PARTICLES as usize, "{} does not fit in the buffers", scenario.name());
+            assert!(scenario.obstacles().len() <= MAX_OBSTACLES as usize);
+            for point in positions {
+                assert!(point[0] > 0.0 && point[0] < WIDTH && point[1] > 0.0 && point[1] < HEIGHT, "{} starts outside the screen", scenario.name());
+            }
+        }
+    }
+
+    #[test]
+    fn fluid_starts_outside_the_obstacles() {
+        let obstacles = Scenario::Cylinder.obstacles();
+        assert_eq!(obstacles.len(), 3);
+        for point in Scenario::Cylinder.particle_positions(PARTICLE_SPACING) {
+            assert!(!obstacles.iter().any(|body| inside_rigid_body(body, point)));
+        }
+    }
+
+    #[test]
+    fn regions_fill_at_the_lattice_density() {
+        let rect = Region::Rect { min: [0.0, 0.0], max: [10.0, 20.0] };
+        assert_eq!(rect.fill([1.0, 2.0]).len(), 100);
+
+        let disc = Region::Disc { centre: [50.0, 50.0], radius: 20.0 };
+        let expected = PI * 20.0 * 20.0;
+        assert!((disc.fill([1.0, 1.0]).len() as f32 - expected).abs() < 0.02 * expected);
+    }
+
+    #[test]
+    fn sloshing_tank_rocks_back_and_forth() {
+        let sideways = |step| Scenario::SloshingTank.gravity(step)[0];
+        assert_eq!(sideways(0), 0.0);
+        assert!(sideways(SLOSH_PERIOD as u32 / 4) > 0.0);
+        assert!(sideways(3 * SLOSH_PERIOD as u32 / 4) < 0.0);
+        assert_eq!(Scenario::DamBreak.gravity(100), [0.0, GRAVITY]);
+    }
+}
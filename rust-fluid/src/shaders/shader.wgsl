@@ -7,49 +7,66 @@ struct Grid {
     y: i32,
 }
 
-struct Particle {
-    position: vec2<f32>, // 8 bytes
-    velocity: vec2<f32>, // 8 bytes
-    radius: f32, // 4 bytes
-    density: f32, // 4 bytes
-    forces: vec4<f32>, // 16 bytes
-}
-
-const WORKGROUP_SIZE: u32 = 16;
-const IPS_WORKGROUP_SIZE: u32 = 16;
-
-const SCREEN_SIZE: vec2<f32> = vec2<f32>(1200.0, 600.0); // Size of the screen
-const GRID_SIZE: vec2<f32> = vec2<f32>(80.0, 40.0);
-
-const PARTICLE_AMOUNT_X: u32 = 192 * 4; // The number of particles in the x direction
-const PARTICLE_AMOUNT_Y: u32 = 96 * 4; // The number of particles in the y direction
-const TOTAL_PARTICLES: i32 = i32(PARTICLE_AMOUNT_X * PARTICLE_AMOUNT_Y); // The total number of particles
-const RADIUS_OF_INFLUENCE: f32 = 75.0 / 4.0; // The radius of the sphere of influence. Also the radius to search for particles to calculate the density
-const TARGET_DENSITY: f32 = 0.2; // The target density of the fluid
-const PRESSURE_MULTIPLIER: f32 = 500.0; // The multiplier for the pressure force
-const GRAVITY: f32 = 0.2; // The strength of gravity
+// The structs and constants shared with lib.rs, generated by shared_wgsl()
+#include "shared.wgsl"
+
 const LOOK_AHEAD_TIME: f32 = 1.0 / 60.0; // The time to look ahead when calculating the predicted position
-const VISCOSITY: f32 = 0.1; // The viscosity of the fluid
-const DAMPENING: f32 = 0.95; // How much to slow down particles when they collide with the walls
-const dt: f32 = 1.0 / 8.0; // The time step
-
-const NUM_DIGITS: u32 = 5; // The number of digits in the grid index
-const BASE: i32 = 10; // Base for the histogram
-const BUCKET_SIZE: u32 = 32; // The amount of numbers in each bucket for the inclusive prefix sum
-const NUM_BUCKETS: u32 = (u32(TOTAL_PARTICLES) + BUCKET_SIZE - 1) / BUCKET_SIZE;
-
-const grids_to_check = vec2<i32>(i32(RADIUS_OF_INFLUENCE / SCREEN_SIZE.x * GRID_SIZE.x + 1.0), i32(RADIUS_OF_INFLUENCE / SCREEN_SIZE.y * GRID_SIZE.y + 1.0));
-@group(0) @binding(0) var<storage, read_write> particles: array<Particle, u32(TOTAL_PARTICLES)>;
-@group(0) @binding(1) var<storage, read_write> particle_lookup: array<i32, u32(GRID_SIZE.x * GRID_SIZE.y)>;
-@group(0) @binding(2) var<storage, read_write> particle_counts: array<atomic<i32>, u32(GRID_SIZE.x * GRID_SIZE.y)>;
-@group(0) @binding(3) var<storage, read> mouse_info: array<f32, 4>; // 0-Up; 1-Down, x-pos, y-pos, 0-Repel; 1-Attract
-@group(0) @binding(4) var<storage, read_write> histogram: array<array<atomic<u32>, u32(NUM_BUCKETS)>, u32(BASE)>;
-@group(0) @binding(5) var<storage, read_write> inclusive_prefix_sum: array<array<atomic<u32>, u32(NUM_BUCKETS)>, u32(BASE)>;
+const PI: f32 = 3.141592653589;
+
+const RIGID_BODY_NONE: u32 = 0;
+const BOUNDARY_SAMPLE_SPACING: f32 = 1.5; // The distance between the boundary sample points on the surface of a rigid body
+const MAX_BOUNDARY_SAMPLES: u32 = 256; // The maximum number of boundary sample points on a rigid body
+const BOUNDARY_SAMPLE_MASS: f32 = PI * PARTICLE_RADIUS * PARTICLE_RADIUS; // The mass of a boundary sample point, the same as a particle
+const RIGID_BODY_FORCE_SCALE: f32 = 10000.0; // Fixed point scale used to accumulate the forces on the rigid bodies with atomics
+const RIGID_BODY_RESTITUTION: f32 = 0.3; // How much the rigid bodies bounce off the walls
+
+const HEAT_SOURCE_TRANSFER: f32 = 0.5; // How quickly the heat sources heat or cool the particles inside them
+const WALL_HEAT_DISTANCE: f32 = RADIUS_OF_INFLUENCE; // How close a particle has to be to a wall to exchange heat with it
+const RENDER_MODE_TEMPERATURE: u32 = 1;
+
+const PBF_RELAXATION: f32 = 0.00001; // Keeps the density constraint multiplier finite when a particle has few neighbours
+const PBF_TENSILE_STRENGTH: f32 = 0.1; // How strongly particles are pushed apart to stop them clumping
+const PBF_TENSILE_DISTANCE: f32 = 0.2 * RADIUS_OF_INFLUENCE; // The distance the tensile correction is measured relative to
+const PBF_TENSILE_POWER: f32 = 4.0;
+const PBF_XSPH_VISCOSITY: f32 = 0.05; // How much each particle's velocity is blended with its neighbours
+
+// The kernels, the same order as in kernels.rs
+const KERNEL_QUADRATIC: u32 = 0;
+const KERNEL_POLY6: u32 = 1;
+const KERNEL_SPIKY: u32 = 2;
+const KERNEL_CUBIC_SPLINE: u32 = 3;
+const KERNEL_WENDLAND_C2: u32 = 4;
+
+const TOOL_PUSH: u32 = 0; // Left repels and right attracts
+const TOOL_DRAG: u32 = 1; // Gives the particles the velocity of the cursor
+const TOOL_VORTEX: u32 = 2; // Left spins the particles clockwise and right counterclockwise
+const TOOL_FREEZE: u32 = 3; // Stops the particles
+const TOOL_POUR: u32 = 4; // Activates particles from the free pool at the cursor
+const TOOL_ERASE: u32 = 5; // Deactivates the particles under the cursor
+const MOUSE_REPEL_FORCE: f32 = 270.0; // The force at the centre of the push tool when repelling
+const MOUSE_ATTRACT_FORCE: f32 = 2.7; // The force at the centre of the push tool when attracting
+const MOUSE_DRAG_RATE: f32 = 0.5; // How much of the difference to the cursor velocity is removed each step
+const MOUSE_VORTEX_ACCELERATION: f32 = 1.0; // The acceleration at the centre of the vortex tool
+const TOOL_OUTLINE_WIDTH: f32 = 1.0; // The width of the circle showing the tool radius
+const POUR_RATE: u32 = 64; // How many particles the pour tool activates each step
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>; // One per slot, capacity.particles long
+@group(0) @binding(1) var<storage, read_write> particle_lookup: array<i32>; // One per grid cell
+@group(0) @binding(2) var<storage, read_write> particle_counts: array<atomic<i32>>; // One per grid cell
+@group(0) @binding(3) var<storage, read> pointers: array<Pointer, MAX_POINTERS>; // The mouse followed by the touches, each with its own tool, radius and strength
+@group(0) @binding(4) var<storage, read_write> histogram: array<atomic<u32>>; // One per bucket for each digit, see sort_index()
+@group(0) @binding(5) var<storage, read_write> inclusive_prefix_sum: array<atomic<u32>>;
 @group(0) @binding(6) var<storage, read> current_digit_index: u32;
-@group(0) @binding(7) var<storage, read_write> sorted_data: array<Particle, u32(TOTAL_PARTICLES)>;
+@group(0) @binding(7) var<storage, read_write> sorted_data: array<Particle>;
 @group(0) @binding(8) var<storage, read> scan_stage: u32;
-@group(0) @binding(9) var<storage, read_write> scanned_inclusive_prefix_sum: array<array<u32, u32(NUM_BUCKETS)>, u32(BASE)>;
+@group(0) @binding(9) var<storage, read_write> scanned_inclusive_prefix_sum: array<u32>;
 @group(0) @binding(10) var<storage, read_write> digit_histogram: array<atomic<u32>, u32(BASE)>;
+@group(0) @binding(11) var<storage, read_write> rigid_bodies: array<RigidBody, MAX_RIGID_BODIES>;
+@group(0) @binding(12) var<storage, read_write> rigid_body_forces: array<atomic<i32>, u32(MAX_RIGID_BODIES * 3)>; // x-force, y-force, torque
+@group(0) @binding(13) var<storage, read> params: SimParams;
+@group(0) @binding(14) var<storage, read_write> particle_pool: ParticlePool;
+@group(0) @binding(15) var<storage, read> walls: array<u32>; // The walls painted in an image mask, row by row
+@group(0) @binding(16) var<uniform> capacity: Capacity; // How many particle slots and sort buckets the buffers have
 
 @vertex
 fn vs_main(@builtin(vertex_index) i: u32) -> VertexOutput {
@@ -69,10 +86,10 @@ fn vs_main(@builtin(vertex_index) i: u32) -> VertexOutput {
 }
 
 
-@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
 fn main_density(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let index = global_id.y * PARTICLE_AMOUNT_X + global_id.x;
-    if index < 0 || index >= u32(TOTAL_PARTICLES) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
         return;
     }
 
@@ -81,10 +98,21 @@ fn main_density(@builtin(global_invocation_id) global_id: vec3<u32>) {
     particles[index].density = density;
 }
 
-@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
+fn main_vorticity(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
+        return;
+    }
+
+    // Calculate the curl of the velocity around the particle
+    particles[index].vorticity = calculate_vorticity(index);
+}
+
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
 fn main_forces(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let index = global_id.y * PARTICLE_AMOUNT_X + global_id.x;
-    if index < 0 || index >= u32(TOTAL_PARTICLES) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
         return;
     }
     
@@ -92,10 +120,10 @@ fn main_forces(@builtin(global_invocation_id) global_id: vec3<u32>) {
     particles[index].forces = calculate_forces(index);
 }
 
-@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
 fn main_move(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let index = global_id.y * PARTICLE_AMOUNT_X + global_id.x;
-    if index < 0 || index >= u32(TOTAL_PARTICLES) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
         return;
     }
 
@@ -104,51 +132,157 @@ fn main_move(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let radius = particles[index].radius;
     let density = particles[index].density;
 
+    // Diffuse heat
+    let temperature = particles[index].temperature + particles[index].temperature_rate * dt;
+    particles[index].temperature = temperature;
+
     var acceleration = vec2<f32>(force.xy / max(density, 0.0001));
     acceleration += force.zw;
-    acceleration.y += GRAVITY;
+    acceleration += params.gravity;
+
+    // Boussinesq buoyancy, hot particles rise and cold particles sink
+    acceleration.y -= params.buoyancy * (temperature - params.reference_temperature);
     // if density == 0.0 {
-    //     acceleration = vec2<f32>(0.0, GRAVITY);
+    //     acceleration = params.gravity;
     // }
 
-    particles[index].velocity += acceleration;
+    particles[index].velocity = apply_mouse_tool(particles[index].position, particles[index].velocity + acceleration);
+    let previous_position = particles[index].position;
     particles[index].position += particles[index].velocity * dt;
 
+    // Bounce off the walls painted in the mask
+    if is_wall(particles[index].position) {
+        let normal = wall_normal(particles[index].position);
+        particles[index].position = previous_position;
+        let normal_speed = dot(particles[index].velocity, normal);
+        if normal_speed < 0.0 {
+            particles[index].velocity -= (1.0 + params.wall_dampening) * normal_speed * normal;
+        }
+    }
+
     // Collide with the walls
     if particles[index].position.x - radius < 0.0 {
         particles[index].position.x = radius;
-        particles[index].velocity.x = -particles[index].velocity.x * DAMPENING;
+        particles[index].velocity.x = -particles[index].velocity.x * params.wall_dampening;
     }
     if particles[index].position.x + radius > SCREEN_SIZE.x {
         particles[index].position.x = SCREEN_SIZE.x - radius;
-        particles[index].velocity.x = -particles[index].velocity.x * DAMPENING;
+        particles[index].velocity.x = -particles[index].velocity.x * params.wall_dampening;
     }
     if particles[index].position.y - radius < 0.0 {
         particles[index].position.y = radius;
-        particles[index].velocity.y = -particles[index].velocity.y * DAMPENING;
+        particles[index].velocity.y = -particles[index].velocity.y * params.wall_dampening;
     }
     if particles[index].position.y + radius > SCREEN_SIZE.y {
         particles[index].position.y = SCREEN_SIZE.y - radius;
-        particles[index].velocity.y = -particles[index].velocity.y * DAMPENING;
+        particles[index].velocity.y = -particles[index].velocity.y * params.wall_dampening;
+    }
+
+    // Push the particle out of the rigid bodies
+    for (var b: u32 = 0; b < MAX_RIGID_BODIES; b = b + 1) {
+        let body = rigid_bodies[b];
+        if body.shape == RIGID_BODY_NONE {
+            continue;
+        }
+
+        let surface = rigid_body_surface(body, particles[index].position);
+        if surface.z >= radius {
+            continue;
+        }
+
+        let normal = surface.xy;
+        particles[index].position += normal * (radius - surface.z);
+
+        // Remove the velocity moving into the body
+        let relative_velocity = particles[index].velocity - rigid_body_velocity_at(body, particles[index].position);
+        let normal_speed = dot(relative_velocity, normal);
+        if normal_speed < 0.0 {
+            particles[index].velocity -= normal * normal_speed;
+        }
+    }
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE, 1)
+fn main_rigid_bodies(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= MAX_RIGID_BODIES {
+        return;
     }
+
+    // Read and reset the forces accumulated by the particles
+    let force = vec2<f32>(
+        f32(atomicExchange(&rigid_body_forces[index * 3], 0)),
+        f32(atomicExchange(&rigid_body_forces[index * 3 + 1], 0))
+    ) / RIGID_BODY_FORCE_SCALE;
+    let torque = f32(atomicExchange(&rigid_body_forces[index * 3 + 2], 0)) / RIGID_BODY_FORCE_SCALE;
+
+    var body = rigid_bodies[index];
+    if body.shape == RIGID_BODY_NONE || body.fixed != 0u {
+        return;
+    }
+
+    // Integrate the motion the same way as the particles
+    body.velocity += force / rigid_body_mass(body);
+    body.velocity += params.gravity * step_scale();
+    body.angular_velocity += torque / rigid_body_inertia(body);
+    body.position += body.velocity * time_step();
+    body.angle += body.angular_velocity * time_step();
+
+    // Collide with the walls
+    if body.shape == RIGID_BODY_BOX {
+        for (var c: i32 = 0; c < 4; c = c + 1) {
+            let corner = vec2<f32>(f32(c % 2) * 2.0 - 1.0, f32(c / 2) * 2.0 - 1.0) * body.size;
+            collide_rigid_body_with_walls(&body, corner);
+        }
+    } else {
+        collide_rigid_body_with_walls(&body, vec2<f32>(-body.size.x, 0.0));
+        collide_rigid_body_with_walls(&body, vec2<f32>(body.size.x, 0.0));
+        collide_rigid_body_with_walls(&body, vec2<f32>(0.0, -body.size.x));
+        collide_rigid_body_with_walls(&body, vec2<f32>(0.0, body.size.x));
+    }
+
+    rigid_bodies[index] = body;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let x: f32 = in.pos.x;
-    let y: f32 = in.pos.y;
+    // The simulation is stretched over the window
+    let x: f32 = in.pos.x * params.view_scale.x;
+    let y: f32 = in.pos.y * params.view_scale.y;
 
     var final_color: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 1.0);
 
+    // Draw the outline of the mouse tool over everything, around the cursor and every finger on the screen
+    for (var p: u32 = 0; p < MAX_POINTERS; p++) {
+        if (p == 0 || pointers[p].buttons != 0) && abs(length(vec2<f32>(x, y) - pointers[p].position) - pointers[p].radius) < TOOL_OUTLINE_WIDTH {
+            return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+        }
+    }
+
+    // Draw the walls painted in the mask
+    if is_wall(vec2<f32>(x, y)) {
+        return vec4<f32>(0.3, 0.3, 0.3, 1.0);
+    }
+
+    // Draw the rigid bodies on top of the fluid
+    for (var b: u32 = 0; b < MAX_RIGID_BODIES; b = b + 1) {
+        let body = rigid_bodies[b];
+        if body.shape != RIGID_BODY_NONE && rigid_body_surface(body, vec2<f32>(x, y)).z < 0.0 {
+            // Light bodies are drawn as wood and heavy bodies as stone
+            let heaviness = min(body.density / (2.0 * params.target_density), 1.0);
+            return vec4<f32>(mix(vec3<f32>(0.8, 0.6, 0.35), vec3<f32>(0.4, 0.4, 0.45), heaviness), 1.0);
+        }
+    }
+
     let grid = pos_to_grid(vec2<f32>(x, y));
     for (var g: i32 = -2; g <= 2; g=g+1){
             var gx: i32 = g / 2;
             var gy: i32 = g % 2;
-            if grid.x + gx < 0 || grid.x + gx >= i32(GRID_SIZE.x) || grid.y + gy < 0 || grid.y + gy >= i32(GRID_SIZE.y) {
+            if grid.x + gx < 0 || grid.x + gx >= i32(params.grid_size.x) || grid.y + gy < 0 || grid.y + gy >= i32(params.grid_size.y) {
                 continue;
             }
             let first_grid_index = grid_to_index(grid_add(grid, Grid(gx, gy)));
-            if first_grid_index < 0 || first_grid_index >= i32(GRID_SIZE.x * GRID_SIZE.y) {
+            if first_grid_index < 0 || first_grid_index >= grid_cell_count() {
                 continue;
             }
             
@@ -174,7 +308,11 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
                 let max_density: f32 = 0.4;
                 var density_t: f32 = (density - min_density) / (max_density - min_density);
                 density_t = min(max(density_t, 0.0), 1.0);
-                let color: vec3<f32> = vec3<f32>(speed_t, density_t, 1.0 - speed_t);
+                var color: vec3<f32> = vec3<f32>(speed_t, density_t, 1.0 - speed_t);
+
+                if params.render_mode == RENDER_MODE_TEMPERATURE {
+                    color = temperature_to_color(particles[i].temperature);
+                }
                     
                 final_color = vec4<f32>(color, 1.0);
                 break;
@@ -185,33 +323,99 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return final_color;
 }
 
+// Maps one degree below the reference temperature to blue and one degree above it to red
+fn temperature_to_color(temperature: f32) -> vec3<f32> {
+    let t = clamp((temperature - params.reference_temperature + 1.0) / 2.0, 0.0, 1.0);
+    if t < 0.5 {
+        return mix(vec3<f32>(0.1, 0.3, 1.0), vec3<f32>(1.0, 1.0, 1.0), t * 2.0);
+    }
+    return mix(vec3<f32>(1.0, 1.0, 1.0), vec3<f32>(1.0, 0.2, 0.1), t * 2.0 - 1.0);
+}
+
 fn density_to_pressure(density: f32) -> f32 {
-    let density_error = density - TARGET_DENSITY;
-    return density_error * PRESSURE_MULTIPLIER;
+    let density_error = density - params.target_density;
+    return density_error * params.pressure_multiplier;
 }
 
 fn smoothing_kernel(distance: f32) -> f32 {
+    return kernel_shape(params.kernel, distance) * params.kernel_normalisation;
+}
+
+// The kernel before it is normalised, see kernels.rs
+fn kernel_shape(kernel: u32, distance: f32) -> f32 {
     if distance >= RADIUS_OF_INFLUENCE {
         return 0.0;
     }
 
-    let volume = 3.141592653589 * pow(RADIUS_OF_INFLUENCE, 4.0) / 6.0;
-    return (RADIUS_OF_INFLUENCE - distance) * (RADIUS_OF_INFLUENCE - distance) / volume;
+    let h = RADIUS_OF_INFLUENCE;
+    let q = distance / h;
+    switch kernel {
+        case KERNEL_POLY6: {
+            let value = h * h - distance * distance;
+            return value * value * value;
+        }
+        case KERNEL_SPIKY: {
+            return (h - distance) * (h - distance) * (h - distance);
+        }
+        case KERNEL_CUBIC_SPLINE: {
+            if q <= 0.5 {
+                return 6.0 * (q * q * q - q * q) + 1.0;
+            }
+            return 2.0 * (1.0 - q) * (1.0 - q) * (1.0 - q);
+        }
+        case KERNEL_WENDLAND_C2: {
+            return pow(1.0 - q, 4.0) * (1.0 + 4.0 * q);
+        }
+        default: {
+            return (h - distance) * (h - distance);
+        }
+    }
+}
+
+// The derivative of the kernel shape with respect to the distance
+fn kernel_shape_derivative(kernel: u32, distance: f32) -> f32 {
+    if distance >= RADIUS_OF_INFLUENCE {
+        return 0.0;
+    }
+
+    let h = RADIUS_OF_INFLUENCE;
+    let q = distance / h;
+    switch kernel {
+        case KERNEL_POLY6: {
+            let value = h * h - distance * distance;
+            return -6.0 * distance * value * value;
+        }
+        case KERNEL_SPIKY: {
+            return -3.0 * (h - distance) * (h - distance);
+        }
+        case KERNEL_CUBIC_SPLINE: {
+            if q <= 0.5 {
+                return (18.0 * q * q - 12.0 * q) / h;
+            }
+            return -6.0 * (1.0 - q) * (1.0 - q) / h;
+        }
+        case KERNEL_WENDLAND_C2: {
+            return -20.0 * q * (1.0 - q) * (1.0 - q) * (1.0 - q) / h;
+        }
+        default: {
+            return -2.0 * (h - distance);
+        }
+    }
 }
 
 fn get_density(pos: vec2<f32>) -> f32 {
     let grid = pos_to_grid(pos);
     var density = 0.0;
 
-    for (var g: i32 = 0; g < (grids_to_check.x * 2 + 1) * (grids_to_check.y * 2 + 1); g=g+1){
-        let gx: i32 = g / (grids_to_check.y * 2 + 1) - grids_to_check.x;
-        let gy: i32 = g % (grids_to_check.y * 2 + 1) - grids_to_check.y;
+    for (var g: i32 = 0; g < (params.grids_to_check.x * 2 + 1) * (params.grids_to_check.y * 2 + 1); g=g+1){
+        let gx: i32 = g / (params.grids_to_check.y * 2 + 1) - params.grids_to_check.x;
+        let gy: i32 = g % (params.grids_to_check.y * 2 + 1) - params.grids_to_check.y;
 
-        if grid.x + gx < 0 || grid.x + gx >= i32(GRID_SIZE.x) || grid.y + gy < 0 || grid.y + gy >= i32(GRID_SIZE.y) {
+        if grid.x + gx < 0 || grid.x + gx >= i32(params.grid_size.x) || grid.y + gy < 0 || grid.y + gy >= i32(params.grid_size.y) {
             continue;
         }
         let first_grid_index = grid_to_index(grid_add(grid, Grid(gx, gy)));
-        if first_grid_index < 0 || first_grid_index >= i32(GRID_SIZE.x * GRID_SIZE.y) {
+        if first_grid_index < 0 || first_grid_index >= grid_cell_count() {
             continue;
         }
             
@@ -234,18 +438,37 @@ fn get_density(pos: vec2<f32>) -> f32 {
 
     }
 
+    // Add the density of the rigid body boundary samples
+    for (var b: u32 = 0; b < MAX_RIGID_BODIES; b = b + 1) {
+        let body = rigid_bodies[b];
+        if body.shape == RIGID_BODY_NONE || length(pos - body.position) > rigid_body_bounding_radius(body) + RADIUS_OF_INFLUENCE {
+            continue;
+        }
+
+        let sample_count = rigid_body_sample_count(body);
+        for (var k: u32 = 0; k < sample_count; k = k + 1) {
+            let distance = length(pos - rigid_body_sample(body, k, sample_count));
+            density += smoothing_kernel(distance) * BOUNDARY_SAMPLE_MASS;
+        }
+    }
+
     return density;
 }
 
+// The grid is derived from the radius of influence on the CPU
 fn pos_to_grid(pos: vec2<f32>) -> Grid {
     return Grid(
-        max(min(i32(pos.x / SCREEN_SIZE.x * GRID_SIZE.x), i32(GRID_SIZE.x - 1)), 0),
-        max(min(i32(pos.y / SCREEN_SIZE.y * GRID_SIZE.y), i32(GRID_SIZE.y - 1)), 0)
+        max(min(i32(pos.x / params.cell_size.x), i32(params.grid_size.x) - 1), 0),
+        max(min(i32(pos.y / params.cell_size.y), i32(params.grid_size.y) - 1), 0)
     );
 }
 
+fn grid_cell_count() -> i32 {
+    return i32(params.grid_size.x * params.grid_size.y);
+}
+
 fn grid_to_index(grid: Grid) -> i32 {
-    return grid.y * i32(GRID_SIZE.x) + grid.x;
+    return grid.y * i32(params.grid_size.x) + grid.x;
 }
 
 fn grid_add(grid: Grid, offset: Grid) -> Grid {
@@ -261,17 +484,21 @@ fn calculate_forces(index: u32) -> vec4<f32> {
     let grid = pos_to_grid(position);
 
     let density: f32 = particles[index].density;
-
-    for (var g: i32 = 0; g < (grids_to_check.x * 2 + 1) * (grids_to_check.y * 2 + 1); g=g+1){
-        let gx: i32 = g / (grids_to_check.y * 2 + 1) - grids_to_check.x;
-        let gy: i32 = g % (grids_to_check.y * 2 + 1) - grids_to_check.y;
+    let temperature: f32 = particles[index].temperature;
+    var temperature_rate: f32 = 0.0;
+    let vorticity: f32 = particles[index].vorticity;
+    var vorticity_gradient = vec2<f32>(0.0, 0.0);
+
+    for (var g: i32 = 0; g < (params.grids_to_check.x * 2 + 1) * (params.grids_to_check.y * 2 + 1); g=g+1){
+        let gx: i32 = g / (params.grids_to_check.y * 2 + 1) - params.grids_to_check.x;
+        let gy: i32 = g % (params.grids_to_check.y * 2 + 1) - params.grids_to_check.y;
             
-        if grid.x + gx < 0 || grid.x + gx >= i32(GRID_SIZE.x) || grid.y + gy < 0 || grid.y + gy >= i32(GRID_SIZE.y) {
+        if grid.x + gx < 0 || grid.x + gx >= i32(params.grid_size.x) || grid.y + gy < 0 || grid.y + gy >= i32(params.grid_size.y) {
             continue;
         }
             
         let first_grid_index: i32 = grid_to_index(grid_add(grid, Grid(gx, gy)));
-        if (first_grid_index < 0 || first_grid_index >= i32(GRID_SIZE.x * GRID_SIZE.y)) {
+        if (first_grid_index < 0 || first_grid_index >= grid_cell_count()) {
             continue;
         }
 
@@ -283,7 +510,7 @@ fn calculate_forces(index: u32) -> vec4<f32> {
         var ending_index: i32 = starting_index + particle_counts[first_grid_index];
 
         for(var i: i32 = starting_index; i <= ending_index; i=i+1){
-            if i == -1 || i == i32(index) || i >= i32(TOTAL_PARTICLES) {
+            if i == -1 || i == i32(index) || i >= i32(capacity.particles) {
                 continue;
             }
             let offset: vec2<f32> = position - (particles[i].position + particles[i].velocity * LOOK_AHEAD_TIME);
@@ -303,26 +530,93 @@ fn calculate_forces(index: u32) -> vec4<f32> {
             // Viscosity force
             let viscosity_influence = viscosity_kernel(distance);
             var viscosity_force = (particles[i].velocity - particles[index].velocity) * viscosity_influence;
-            viscosity_force *= VISCOSITY;
+            viscosity_force *= params.viscosity;
+
+            // Heat diffusion, uses the same kernel as the viscosity
+            temperature_rate += (particles[i].temperature - temperature) * viscosity_influence * params.thermal_diffusivity;
+
+            // Gradient of the vorticity magnitude
+            let volume = 3.141592653589 * particles[i].radius * particles[i].radius / max(other_density, 0.000001);
+            vorticity_gradient -= dir * slope * volume * (abs(particles[i].vorticity) - abs(vorticity));
+
+            // Apply the forces, the position based solver handles pressure and viscosity itself
+            if params.solver_mode == SOLVER_EXPLICIT {
+                forces += vec4<f32>(pressure_force.x, pressure_force.y, viscosity_force.x, viscosity_force.y);
+            }
+        }
+    }
+
+    // Vorticity confinement, pushes the fluid around the swirls to keep them spinning
+    let vorticity_gradient_length = length(vorticity_gradient);
+    if params.vorticity_strength > 0.0 && vorticity_gradient_length > 0.000001 {
+        let normal = vorticity_gradient / vorticity_gradient_length;
+        let confinement_force = vec2<f32>(normal.y, -normal.x) * vorticity * params.vorticity_strength;
+        forces += vec4<f32>(0.0, 0.0, confinement_force.x, confinement_force.y);
+    }
+
+    // Exchange heat with the heat sources and the walls
+    particles[index].temperature_rate = temperature_rate + external_heat_rate(position, temperature);
+
+    // Interact with the rigid body boundary samples
+    let particle_mass = 3.141592653589 * particles[index].radius * particles[index].radius;
+    for (var b: u32 = 0; b < MAX_RIGID_BODIES; b = b + 1) {
+        let body = rigid_bodies[b];
+        if body.shape == RIGID_BODY_NONE || length(position - body.position) > rigid_body_bounding_radius(body) + RADIUS_OF_INFLUENCE {
+            continue;
+        }
+
+        var body_force = vec2<f32>(0.0, 0.0);
+        var body_torque: f32 = 0.0;
+        let sample_count = rigid_body_sample_count(body);
+        for (var k: u32 = 0; k < sample_count; k = k + 1) {
+            let sample_position = rigid_body_sample(body, k, sample_count);
+            let offset: vec2<f32> = position - sample_position;
+            let distance: f32 = length(offset);
+            if distance == 0.0 || distance > RADIUS_OF_INFLUENCE {
+                continue;
+            }
+            let dir = offset / distance;
+
+            // Pressure force, the boundary mirrors the pressure of the particle but never pulls it in
+            // The position based solver pushes particles out of the boundary in its density constraint instead
+            var pressure_force = vec2<f32>(0.0, 0.0);
+            if params.solver_mode == SOLVER_EXPLICIT {
+                let slope = smoothing_kernel_derivative(distance);
+                let pressure = max(density_to_pressure(density), 0.0);
+                pressure_force = dir * pressure * slope * BOUNDARY_SAMPLE_MASS / max(density, 0.000001);
+            }
+
+            // Viscosity force, the boundary moves with the body
+            let sample_velocity = rigid_body_velocity_at(body, sample_position);
+            let viscosity_force = (sample_velocity - particles[index].velocity) * viscosity_kernel(distance) * params.viscosity;
 
-            // Apply the forces
             forces += vec4<f32>(pressure_force.x, pressure_force.y, viscosity_force.x, viscosity_force.y);
+
+            // The body receives the opposite of the change in momentum of the particle
+            let reaction = -(pressure_force / max(density, 0.0001) + viscosity_force) * particle_mass * step_scale();
+            body_force += reaction;
+            body_torque += cross_2d(sample_position - body.position, reaction);
         }
+
+        add_rigid_body_reaction(b, body_force, body_torque);
     }
 
-    // Check for mouse interaction
-    if mouse_info[0] == 1.0 || mouse_info[3] == 1.0 {
-        let mouse_pos = vec2<f32>(mouse_info[1], mouse_info[2]);
-        let offset = position - mouse_pos;
-        let distance = sqrt(offset.x * offset.x + offset.y * offset.y);
-        if distance < RADIUS_OF_INFLUENCE {
-            let dir = vec2<f32>(offset.x / distance, offset.y / distance);
-            var mouse_force = dir * smoothing_kernel(distance) * 100000.0;
-            if mouse_info[3] == 1.0 {
-                mouse_force *= -0.005; // Attract
+    // Check for mouse and touch interaction, the other tools change the velocity directly when the particles move
+    for (var p: u32 = 0; p < MAX_POINTERS; p++) {
+        let pointer = pointers[p];
+        if pointer.tool != TOOL_PUSH || pointer.buttons == 0 {
+            continue;
+        }
+        let offset = position - pointer.position;
+        let distance = length(offset);
+        if distance < pointer.radius && distance > 0.0 {
+            let dir = offset / distance;
+            var mouse_force = dir * mouse_tool_falloff(distance, pointer.radius) * pointer.strength * pointer.pressure;
+            if (pointer.buttons & MOUSE_RIGHT) != 0 {
+                mouse_force *= -MOUSE_ATTRACT_FORCE;
             }
             else {
-                mouse_force *= 0.5; // Repel
+                mouse_force *= MOUSE_REPEL_FORCE;
             }
             forces += vec4<f32>(mouse_force.x, mouse_force.y, 0.0, 0.0);
         }
@@ -332,23 +626,108 @@ fn calculate_forces(index: u32) -> vec4<f32> {
 
 }
 
-fn smoothing_kernel_derivative(distance: f32) -> f32 {
-    if distance >= RADIUS_OF_INFLUENCE {
-        return 0.0;
+fn calculate_vorticity(index: u32) -> f32 {
+    var vorticity: f32 = 0.0;
+
+    let position: vec2<f32> = particles[index].position + particles[index].velocity * LOOK_AHEAD_TIME;
+    let velocity: vec2<f32> = particles[index].velocity;
+
+    let grid = pos_to_grid(position);
+
+    for (var g: i32 = 0; g < (params.grids_to_check.x * 2 + 1) * (params.grids_to_check.y * 2 + 1); g=g+1){
+        let gx: i32 = g / (params.grids_to_check.y * 2 + 1) - params.grids_to_check.x;
+        let gy: i32 = g % (params.grids_to_check.y * 2 + 1) - params.grids_to_check.y;
+
+        if grid.x + gx < 0 || grid.x + gx >= i32(params.grid_size.x) || grid.y + gy < 0 || grid.y + gy >= i32(params.grid_size.y) {
+            continue;
+        }
+
+        let first_grid_index: i32 = grid_to_index(grid_add(grid, Grid(gx, gy)));
+        let starting_index = particle_lookup[first_grid_index];
+        if starting_index == -1 {
+            continue;
+        }
+
+        var ending_index: i32 = starting_index + particle_counts[first_grid_index];
+
+        for(var i: i32 = starting_index; i <= ending_index; i=i+1){
+            if i == i32(index) || i >= i32(capacity.particles) {
+                continue;
+            }
+            let offset: vec2<f32> = position - (particles[i].position + particles[i].velocity * LOOK_AHEAD_TIME);
+            let distance: f32 = length(offset);
+            if distance == 0.0 || distance > RADIUS_OF_INFLUENCE {
+                continue;
+            }
+
+            // The curl of the velocity, using the gradient of the kernel
+            let gradient = -offset / distance * smoothing_kernel_derivative(distance);
+            let volume = 3.141592653589 * particles[i].radius * particles[i].radius / max(particles[i].density, 0.000001);
+            vorticity += cross_2d(gradient, particles[i].velocity - velocity) * volume;
+        }
     }
 
-    let scale = 12.0 / (pow(RADIUS_OF_INFLUENCE, 4.0) * 3.141592653589);
-    return (RADIUS_OF_INFLUENCE - distance) * scale;
+    return vorticity;
+}
+
+// How steeply the kernel falls off, the negative of its derivative so it points away from the neighbour
+fn smoothing_kernel_derivative(distance: f32) -> f32 {
+    return -kernel_shape_derivative(params.kernel, distance) * params.kernel_normalisation;
 }
 
 fn viscosity_kernel(distance: f32) -> f32 {
-    if distance >= RADIUS_OF_INFLUENCE {
-        return 0.0;
+    return kernel_shape(KERNEL_POLY6, distance) * params.viscosity_kernel_normalisation;
+}
+
+// 1 at the cursor and 0 at the edge of the tool
+fn mouse_tool_falloff(distance: f32, radius: f32) -> f32 {
+    let t = max(1.0 - distance / radius, 0.0);
+    return t * t;
+}
+
+// Applies the drag, vortex and freeze tools of every pointer to the velocity of a particle
+fn apply_mouse_tool(position: vec2<f32>, velocity: vec2<f32>) -> vec2<f32> {
+    var result = velocity;
+    for (var p: u32 = 0; p < MAX_POINTERS; p++) {
+        result = apply_pointer_tool(pointers[p], position, result);
+    }
+    return result;
+}
+
+fn apply_pointer_tool(pointer: Pointer, position: vec2<f32>, velocity: vec2<f32>) -> vec2<f32> {
+    if pointer.buttons == 0 || pointer.tool == TOOL_PUSH {
+        return velocity;
     }
 
-    let volume = 3.141592653589 * pow(RADIUS_OF_INFLUENCE, 8.0) / 4.0;
-    let value = RADIUS_OF_INFLUENCE * RADIUS_OF_INFLUENCE - distance * distance;
-    return value * value * value / volume;
+    let offset = position - pointer.position;
+    let distance = length(offset);
+    if distance >= pointer.radius {
+        return velocity;
+    }
+    let falloff = mouse_tool_falloff(distance, pointer.radius);
+
+    switch pointer.tool {
+        case TOOL_DRAG: {
+            let cursor_velocity = pointer.movement / time_step();
+            return mix(velocity, cursor_velocity, min(falloff * MOUSE_DRAG_RATE * pointer.strength * pointer.pressure, 1.0));
+        }
+        case TOOL_VORTEX: {
+            if distance == 0.0 {
+                return velocity;
+            }
+            var tangent = vec2<f32>(-offset.y, offset.x) / distance;
+            if (pointer.buttons & MOUSE_RIGHT) != 0 {
+                tangent = -tangent;
+            }
+            return velocity + tangent * falloff * MOUSE_VORTEX_ACCELERATION * pointer.strength * pointer.pressure * step_scale();
+        }
+        case TOOL_FREEZE: {
+            return vec2<f32>(0.0, 0.0);
+        }
+        default: {
+            return velocity;
+        }
+    }
 }
 
 fn calculate_shared_pressure(density_a: f32, density_b: f32) -> f32 {
@@ -357,7 +736,574 @@ fn calculate_shared_pressure(density_a: f32, density_b: f32) -> f32 {
     return (pressure_a + pressure_b) / 2.0;
 }
 
+fn external_heat_rate(position: vec2<f32>, temperature: f32) -> f32 {
+    var rate: f32 = 0.0;
+
+    for (var h: u32 = 0; h < MAX_HEAT_SOURCES; h = h + 1) {
+        let source = params.heat_sources[h];
+        if source.radius > 0.0 && length(position - source.position) < source.radius {
+            rate += (source.temperature - temperature) * HEAT_SOURCE_TRANSFER;
+        }
+    }
+
+    let wall_distances = vec4<f32>(position.x, SCREEN_SIZE.x - position.x, position.y, SCREEN_SIZE.y - position.y);
+    for (var w: i32 = 0; w < 4; w = w + 1) {
+        if wall_distances[w] < WALL_HEAT_DISTANCE {
+            rate += (params.wall_temperatures[w] - temperature) * params.wall_heat_transfer[w];
+        }
+    }
+
+    return rate;
+}
+
+// --- Rigid Bodies --- //
+fn rotate(v: vec2<f32>, angle: f32) -> vec2<f32> {
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec2<f32>(v.x * c - v.y * s, v.x * s + v.y * c);
+}
+
+fn cross_2d(a: vec2<f32>, b: vec2<f32>) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
+
+fn rigid_body_mass(body: RigidBody) -> f32 {
+    if body.shape == RIGID_BODY_BOX {
+        return body.density * 4.0 * body.size.x * body.size.y;
+    }
+    return body.density * PI * body.size.x * body.size.x;
+}
+
+fn rigid_body_inertia(body: RigidBody) -> f32 {
+    let mass = rigid_body_mass(body);
+    if body.shape == RIGID_BODY_BOX {
+        return mass * (body.size.x * body.size.x + body.size.y * body.size.y) / 3.0;
+    }
+    return mass * body.size.x * body.size.x / 2.0;
+}
+
+fn rigid_body_bounding_radius(body: RigidBody) -> f32 {
+    if body.shape == RIGID_BODY_BOX {
+        return length(body.size);
+    }
+    return body.size.x;
+}
+
+fn rigid_body_sample_count(body: RigidBody) -> u32 {
+    var perimeter = 2.0 * PI * body.size.x;
+    if body.shape == RIGID_BODY_BOX {
+        perimeter = 4.0 * (body.size.x + body.size.y);
+    }
+    return min(u32(ceil(perimeter / BOUNDARY_SAMPLE_SPACING)), MAX_BOUNDARY_SAMPLES);
+}
+
+// Returns the world position of the k-th boundary sample, evenly spaced along the surface
+fn rigid_body_sample(body: RigidBody, k: u32, sample_count: u32) -> vec2<f32> {
+    let t = f32(k) / f32(sample_count);
+    var local = vec2<f32>(0.0, 0.0);
+
+    if body.shape == RIGID_BODY_BOX {
+        let width = 2.0 * body.size.x;
+        let height = 2.0 * body.size.y;
+        let d = t * 2.0 * (width + height);
+        if d < width {
+            local = vec2<f32>(-body.size.x + d, -body.size.y);
+        } else if d < width + height {
+            local = vec2<f32>(body.size.x, -body.size.y + d - width);
+        } else if d < 2.0 * width + height {
+            local = vec2<f32>(body.size.x - (d - width - height), body.size.y);
+        } else {
+            local = vec2<f32>(-body.size.x, body.size.y - (d - 2.0 * width - height));
+        }
+    } else {
+        let angle = t * 2.0 * PI;
+        local = vec2<f32>(cos(angle), sin(angle)) * body.size.x;
+    }
+
+    return body.position + rotate(local, body.angle);
+}
+
+// Adds a change in momentum to the body, accumulated in fixed point because there are no float atomics
+fn add_rigid_body_reaction(body_index: u32, force: vec2<f32>, torque: f32) {
+    if force.x == 0.0 && force.y == 0.0 {
+        return;
+    }
+    atomicAdd(&rigid_body_forces[body_index * 3], i32(round(force.x * RIGID_BODY_FORCE_SCALE)));
+    atomicAdd(&rigid_body_forces[body_index * 3 + 1], i32(round(force.y * RIGID_BODY_FORCE_SCALE)));
+    atomicAdd(&rigid_body_forces[body_index * 3 + 2], i32(round(torque * RIGID_BODY_FORCE_SCALE)));
+}
+
+fn rigid_body_velocity_at(body: RigidBody, point: vec2<f32>) -> vec2<f32> {
+    let r = point - body.position;
+    return body.velocity + body.angular_velocity * vec2<f32>(-r.y, r.x);
+}
+
+// Returns the outward surface normal in xy and the signed distance to the surface in z
+fn rigid_body_surface(body: RigidBody, point: vec2<f32>) -> vec3<f32> {
+    let local = rotate(point - body.position, -body.angle);
+
+    if body.shape == RIGID_BODY_DISC {
+        let distance = length(local);
+        var normal = vec2<f32>(0.0, -1.0);
+        if distance > 0.0 {
+            normal = local / distance;
+        }
+        return vec3<f32>(rotate(normal, body.angle), distance - body.size.x);
+    }
+
+    let q = abs(local) - body.size;
+    if q.x > 0.0 || q.y > 0.0 {
+        let outside = max(q, vec2<f32>(0.0, 0.0));
+        let distance = length(outside);
+        let normal = sign(local) * outside / distance;
+        return vec3<f32>(rotate(normal, body.angle), distance);
+    }
+    if q.x > q.y {
+        return vec3<f32>(rotate(vec2<f32>(sign(local.x), 0.0), body.angle), q.x);
+    }
+    return vec3<f32>(rotate(vec2<f32>(0.0, sign(local.y)), body.angle), q.y);
+}
+
+// Resolves a collision between a point on the body (in body space) and the walls with an impulse
+fn collide_rigid_body_with_walls(body: ptr<function, RigidBody>, local_point: vec2<f32>) {
+    // The inward normal of each wall and the offset of its plane
+    var walls = array<vec3<f32>, 4>(
+        vec3<f32>(1.0, 0.0, 0.0), // Left
+        vec3<f32>(-1.0, 0.0, -SCREEN_SIZE.x), // Right
+        vec3<f32>(0.0, 1.0, 0.0), // Top
+        vec3<f32>(0.0, -1.0, -SCREEN_SIZE.y) // Bottom
+    );
+
+    for (var w: i32 = 0; w < 4; w = w + 1) {
+        let normal = walls[w].xy;
+        let r = rotate(local_point, (*body).angle);
+        let point = (*body).position + r;
+        let penetration = walls[w].z - dot(point, normal);
+        if penetration <= 0.0 {
+            continue;
+        }
+
+        // Move the body out of the wall
+        (*body).position += normal * penetration;
+
+        // Apply an impulse if the point is moving into the wall
+        let normal_speed = dot(rigid_body_velocity_at(*body, point), normal);
+        if normal_speed < 0.0 {
+            let r_cross_n = cross_2d(r, normal);
+            let mass = rigid_body_mass(*body);
+            let inertia = rigid_body_inertia(*body);
+            let impulse = -(1.0 + RIGID_BODY_RESTITUTION) * normal_speed / (1.0 / mass + r_cross_n * r_cross_n / inertia);
+            (*body).velocity += normal * impulse / mass;
+            (*body).angular_velocity += r_cross_n * impulse / inertia;
+        }
+    }
+}
+
+// --- Position Based Fluids --- //
+// The time step of the current solver
+fn time_step() -> f32 {
+    if params.solver_mode == SOLVER_POSITION_BASED {
+        return PBF_DT;
+    }
+    return dt;
+}
+
+// The accelerations are tuned per explicit step, so they are scaled up for longer steps
+fn step_scale() -> f32 {
+    return time_step() / dt;
+}
+
+fn clamp_to_walls(position: vec2<f32>, radius: f32) -> vec2<f32> {
+    return clamp(position, vec2<f32>(radius, radius), SCREEN_SIZE - vec2<f32>(radius, radius));
+}
+
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
+fn pbf_predict(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
+        return;
+    }
+
+    let force = particles[index].forces;
+    let density = particles[index].density;
+
+    // Diffuse heat
+    let temperature = particles[index].temperature + particles[index].temperature_rate * PBF_DT;
+    particles[index].temperature = temperature;
+
+    // Apply the external forces, pressure and viscosity are handled by the constraint projection
+    var acceleration = vec2<f32>(force.xy / max(density, 0.0001));
+    acceleration += force.zw;
+    acceleration += params.gravity;
+    acceleration.y -= params.buoyancy * (temperature - params.reference_temperature);
+
+    particles[index].velocity = apply_mouse_tool(particles[index].position, particles[index].velocity + acceleration * step_scale());
+
+    // Predict the new position, it is corrected by the constraint projection before the velocity is updated
+    particles[index].previous_position = particles[index].position;
+    particles[index].position = clamp_to_walls(particles[index].position + particles[index].velocity * PBF_DT, particles[index].radius);
+}
+
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
+fn pbf_lambda(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
+        return;
+    }
+
+    let position = particles[index].position;
+    let grid = pos_to_grid(position);
+
+    var density: f32 = 0.0;
+    var gradient_sum = vec2<f32>(0.0, 0.0); // The gradient of the constraint with respect to this particle
+    var gradient_length_squared: f32 = 0.0; // The squared gradients with respect to the neighbours
+
+    for (var g: i32 = 0; g < (params.grids_to_check.x * 2 + 1) * (params.grids_to_check.y * 2 + 1); g=g+1){
+        let gx: i32 = g / (params.grids_to_check.y * 2 + 1) - params.grids_to_check.x;
+        let gy: i32 = g % (params.grids_to_check.y * 2 + 1) - params.grids_to_check.y;
+
+        if grid.x + gx < 0 || grid.x + gx >= i32(params.grid_size.x) || grid.y + gy < 0 || grid.y + gy >= i32(params.grid_size.y) {
+            continue;
+        }
+
+        let first_grid_index: i32 = grid_to_index(grid_add(grid, Grid(gx, gy)));
+        let starting_index = particle_lookup[first_grid_index];
+        if starting_index == -1 {
+            continue;
+        }
+
+        var ending_index: i32 = starting_index + particle_counts[first_grid_index];
+
+        for(var i: i32 = starting_index; i <= ending_index; i=i+1){
+            if i >= i32(capacity.particles) {
+                continue;
+            }
+            let offset: vec2<f32> = position - particles[i].position;
+            let distance: f32 = length(offset);
+            if distance > RADIUS_OF_INFLUENCE {
+                continue;
+            }
+
+            let mass = 3.141592653589 * particles[i].radius * particles[i].radius;
+            density += smoothing_kernel(distance) * mass;
+
+            if i != i32(index) && distance > 0.0 {
+                let gradient = offset / distance * smoothing_kernel_derivative(distance) * mass / params.target_density;
+                gradient_sum += gradient;
+                gradient_length_squared += dot(gradient, gradient);
+            }
+        }
+    }
+
+    // The rigid body boundary samples count as fixed particles
+    for (var b: u32 = 0; b < MAX_RIGID_BODIES; b = b + 1) {
+        let body = rigid_bodies[b];
+        if body.shape == RIGID_BODY_NONE || length(position - body.position) > rigid_body_bounding_radius(body) + RADIUS_OF_INFLUENCE {
+            continue;
+        }
+
+        let sample_count = rigid_body_sample_count(body);
+        for (var k: u32 = 0; k < sample_count; k = k + 1) {
+            let offset = position - rigid_body_sample(body, k, sample_count);
+            let distance = length(offset);
+            if distance == 0.0 || distance > RADIUS_OF_INFLUENCE {
+                continue;
+            }
+            density += smoothing_kernel(distance) * BOUNDARY_SAMPLE_MASS;
+            gradient_sum += offset / distance * smoothing_kernel_derivative(distance) * BOUNDARY_SAMPLE_MASS / params.target_density;
+        }
+    }
+
+    // Only compression is corrected, stretching at the surface is handled by the tensile correction
+    let constraint = max(density / params.target_density - 1.0, 0.0);
+    particles[index].density = density;
+    particles[index].lambda = -constraint / (gradient_length_squared + dot(gradient_sum, gradient_sum) + PBF_RELAXATION);
+}
+
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
+fn pbf_delta(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
+        return;
+    }
+
+    let position = particles[index].position;
+    let lambda = particles[index].lambda;
+    let grid = pos_to_grid(position);
+    let tensile_reference = smoothing_kernel(PBF_TENSILE_DISTANCE);
+
+    var delta = vec2<f32>(0.0, 0.0);
+
+    for (var g: i32 = 0; g < (params.grids_to_check.x * 2 + 1) * (params.grids_to_check.y * 2 + 1); g=g+1){
+        let gx: i32 = g / (params.grids_to_check.y * 2 + 1) - params.grids_to_check.x;
+        let gy: i32 = g % (params.grids_to_check.y * 2 + 1) - params.grids_to_check.y;
+
+        if grid.x + gx < 0 || grid.x + gx >= i32(params.grid_size.x) || grid.y + gy < 0 || grid.y + gy >= i32(params.grid_size.y) {
+            continue;
+        }
+
+        let first_grid_index: i32 = grid_to_index(grid_add(grid, Grid(gx, gy)));
+        let starting_index = particle_lookup[first_grid_index];
+        if starting_index == -1 {
+            continue;
+        }
+
+        var ending_index: i32 = starting_index + particle_counts[first_grid_index];
+
+        for(var i: i32 = starting_index; i <= ending_index; i=i+1){
+            if i == i32(index) || i >= i32(capacity.particles) {
+                continue;
+            }
+            let offset: vec2<f32> = position - particles[i].position;
+            let distance: f32 = length(offset);
+            if distance == 0.0 || distance > RADIUS_OF_INFLUENCE {
+                continue;
+            }
+
+            // Tensile instability correction, an artificial pressure that stops particles clumping together
+            let tensile_correction = -PBF_TENSILE_STRENGTH * pow(smoothing_kernel(distance) / tensile_reference, PBF_TENSILE_POWER);
+
+            let mass = 3.141592653589 * particles[i].radius * particles[i].radius;
+            delta -= offset / distance * smoothing_kernel_derivative(distance) * mass * (lambda + particles[i].lambda + tensile_correction);
+        }
+    }
+
+    // The rigid body boundary samples mirror the multiplier of this particle
+    let particle_mass = 3.141592653589 * particles[index].radius * particles[index].radius;
+    for (var b: u32 = 0; b < MAX_RIGID_BODIES; b = b + 1) {
+        let body = rigid_bodies[b];
+        if body.shape == RIGID_BODY_NONE || length(position - body.position) > rigid_body_bounding_radius(body) + RADIUS_OF_INFLUENCE {
+            continue;
+        }
+
+        var body_delta = vec2<f32>(0.0, 0.0);
+        var body_torque: f32 = 0.0;
+        let sample_count = rigid_body_sample_count(body);
+        for (var k: u32 = 0; k < sample_count; k = k + 1) {
+            let sample_position = rigid_body_sample(body, k, sample_count);
+            let offset = position - sample_position;
+            let distance = length(offset);
+            if distance == 0.0 || distance > RADIUS_OF_INFLUENCE {
+                continue;
+            }
+            let sample_delta = -offset / distance * smoothing_kernel_derivative(distance) * BOUNDARY_SAMPLE_MASS * 2.0 * lambda / params.target_density;
+            body_delta += sample_delta;
+
+            // The body receives the opposite of the momentum the correction gives the particle
+            body_torque += cross_2d(sample_position - body.position, -sample_delta * particle_mass / PBF_DT);
+        }
+        delta += body_delta * params.target_density;
+
+        add_rigid_body_reaction(b, -body_delta * particle_mass / PBF_DT, body_torque);
+    }
+
+    // Store the correction in the forces until every particle has calculated theirs
+    particles[index].forces = vec4<f32>(delta / params.target_density, particles[index].forces.zw);
+}
+
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
+fn pbf_apply(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
+        return;
+    }
+
+    let radius = particles[index].radius;
+    var position = clamp_to_walls(particles[index].position + particles[index].forces.xy, radius);
+
+    // Push the particle out of the rigid bodies
+    for (var b: u32 = 0; b < MAX_RIGID_BODIES; b = b + 1) {
+        let body = rigid_bodies[b];
+        if body.shape == RIGID_BODY_NONE {
+            continue;
+        }
+
+        let surface = rigid_body_surface(body, position);
+        if surface.z < radius {
+            position += surface.xy * (radius - surface.z);
+        }
+    }
+
+    // Stay out of the walls painted in the mask
+    if is_wall(position) {
+        position = particles[index].previous_position;
+    }
+
+    // The velocity is the distance moved over the step
+    particles[index].position = position;
+    particles[index].velocity = (position - particles[index].previous_position) / PBF_DT;
+}
+
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
+fn pbf_viscosity(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
+        return;
+    }
+
+    let position = particles[index].position;
+    let velocity = particles[index].velocity;
+    let grid = pos_to_grid(position);
+
+    var velocity_change = vec2<f32>(0.0, 0.0);
+
+    for (var g: i32 = 0; g < (params.grids_to_check.x * 2 + 1) * (params.grids_to_check.y * 2 + 1); g=g+1){
+        let gx: i32 = g / (params.grids_to_check.y * 2 + 1) - params.grids_to_check.x;
+        let gy: i32 = g % (params.grids_to_check.y * 2 + 1) - params.grids_to_check.y;
+
+        if grid.x + gx < 0 || grid.x + gx >= i32(params.grid_size.x) || grid.y + gy < 0 || grid.y + gy >= i32(params.grid_size.y) {
+            continue;
+        }
+
+        let first_grid_index: i32 = grid_to_index(grid_add(grid, Grid(gx, gy)));
+        let starting_index = particle_lookup[first_grid_index];
+        if starting_index == -1 {
+            continue;
+        }
+
+        var ending_index: i32 = starting_index + particle_counts[first_grid_index];
+
+        for(var i: i32 = starting_index; i <= ending_index; i=i+1){
+            if i == i32(index) || i >= i32(capacity.particles) {
+                continue;
+            }
+            let distance: f32 = length(position - particles[i].position);
+            if distance > RADIUS_OF_INFLUENCE {
+                continue;
+            }
+
+            // XSPH viscosity, blends the velocity with the neighbours
+            let volume = 3.141592653589 * particles[i].radius * particles[i].radius / max(particles[i].density, 0.000001);
+            velocity_change += (particles[i].velocity - velocity) * smoothing_kernel(distance) * volume;
+        }
+    }
+
+    // Store the change in the forces until every particle has calculated theirs
+    particles[index].forces = vec4<f32>(particles[index].forces.xy, velocity_change * PBF_XSPH_VISCOSITY);
+}
+
+@compute @workgroup_size(PARTICLE_WORKGROUP_SIZE, 1)
+fn pbf_apply_viscosity(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if index >= capacity.particles || !is_active(index) {
+        return;
+    }
+
+    particles[index].velocity += particles[index].forces.zw;
+}
+
+// --- Walls --- //
+// Whether a point is inside a wall painted in the image mask
+fn is_wall(position: vec2<f32>) -> bool {
+    let cell = vec2<i32>(floor(position / WALL_CELL_SIZE));
+    if cell.x < 0 || cell.y < 0 || cell.x >= i32(WALL_GRID_SIZE.x) || cell.y >= i32(WALL_GRID_SIZE.y) {
+        return false;
+    }
+    return walls[u32(cell.y) * WALL_GRID_SIZE.x + u32(cell.x)] != 0u;
+}
+
+// Points out of the wall at a position, towards the neighbouring cells that are open
+fn wall_normal(position: vec2<f32>) -> vec2<f32> {
+    let dx = vec2<f32>(WALL_CELL_SIZE, 0.0);
+    let dy = vec2<f32>(0.0, WALL_CELL_SIZE);
+    let normal = vec2<f32>(
+        f32(is_wall(position - dx)) - f32(is_wall(position + dx)),
+        f32(is_wall(position - dy)) - f32(is_wall(position + dy))
+    );
+    if length(normal) == 0.0 {
+        return vec2<f32>(0.0, 0.0);
+    }
+    return normalize(normal);
+}
+
+// --- Particle Pool --- //
+// Inactive particles have a radius of 0
+fn is_active(index: u32) -> bool {
+    return particles[index].radius > 0.0;
+}
+
+// A random number between 0 and 1
+fn random(seed: u32) -> f32 {
+    // PCG hash
+    var state = seed * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return f32((word >> 22u) ^ word) / 4294967295.0;
+}
+
+// Activates a particle from the free pool somewhere in a disc
+fn spawn_particle(index: u32, centre: vec2<f32>, radius: f32, velocity: vec2<f32>) {
+    // Spread the particles evenly over the disc
+    let seed = index * 2u + particle_pool.seed * 1973u;
+    let angle = random(seed) * 2.0 * PI;
+    let distance = sqrt(random(seed + 1u)) * radius;
+    let position = centre + vec2<f32>(cos(angle), sin(angle)) * distance;
+
+    particles[index].position = clamp_to_walls(position, PARTICLE_RADIUS);
+    particles[index].previous_position = particles[index].position;
+    particles[index].velocity = velocity;
+    particles[index].radius = PARTICLE_RADIUS;
+    particles[index].density = params.target_density;
+    particles[index].temperature = params.reference_temperature;
+    particles[index].temperature_rate = 0.0;
+    particles[index].forces = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    particles[index].vorticity = 0.0;
+    particles[index].lambda = 0.0;
+}
+
+// Erases the particles under the eraser and in the drain, and pours and emits new ones from the free pool, runs after the sort
+@compute @workgroup_size(WORKGROUP_SIZE, 1)
+fn update_pool(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index: u32 = global_id.x;
+    if index >= capacity.particles {
+        return;
+    }
+
+    let position = particles[index].position;
+    var erased = false;
+    for (var p: u32 = 0; p < MAX_POINTERS; p++) {
+        erased = erased || (pointers[p].buttons != 0 && pointers[p].tool == TOOL_ERASE && length(position - pointers[p].position) < pointers[p].radius);
+    }
+    let drained = params.drain_radius > 0.0 && length(position - params.drain_position) < params.drain_radius;
+    if is_active(index) && (erased || drained) {
+        particles[index].position = INACTIVE_POSITION;
+        particles[index].velocity = vec2<f32>(0.0, 0.0);
+        particles[index].radius = 0.0;
+        particles[index].density = 0.0;
+    }
+
+    // The free pool starts at the first inactive particle, so the first few of them are poured by each pointer in turn and the next few emitted
+    var first_emitted = particle_pool.first_inactive;
+    for (var p: u32 = 0; p < MAX_POINTERS; p++) {
+        let pointer = pointers[p];
+        if pointer.buttons == 0 || pointer.tool != TOOL_POUR {
+            continue;
+        }
+        let pour_amount = u32(f32(POUR_RATE) * pointer.strength * pointer.pressure);
+        if index >= first_emitted && index < first_emitted + pour_amount {
+            spawn_particle(index, pointer.position, pointer.radius, pointer.movement / time_step());
+            return;
+        }
+        first_emitted += pour_amount;
+    }
+    if index >= first_emitted && index < first_emitted + params.emitter_rate {
+        spawn_particle(index, params.emitter_position, params.emitter_radius, params.emitter_velocity);
+    }
+}
+
+// The grid cell used to sort the particle, inactive particles go after all of them
+fn particle_sort_key(index: u32) -> i32 {
+    if !is_active(index) {
+        return grid_cell_count();
+    }
+    return grid_to_index(pos_to_grid(particles[index].position));
+}
+
 // --- Sort --- //
+// The histogram and prefix sums are flattened, with the buckets of each digit after each other
+fn sort_index(digit: u32, bucket: u32) -> u32 {
+    return digit * capacity.buckets + bucket;
+}
+
 fn val_to_digit(val: i32, digit_index: u32) -> i32 {
     let valf32 = f32(val);
     let divisor = pow(f32(BASE), f32(digit_index));
@@ -369,22 +1315,22 @@ fn val_to_digit(val: i32, digit_index: u32) -> i32 {
 @compute @workgroup_size(WORKGROUP_SIZE, 1)
 fn update_histogram(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index: u32 = global_id.x;
-    if (i32(index) >= TOTAL_PARTICLES) {
+    if (index >= capacity.particles) {
         return;
     }
     
     let bucket_index: u32 = index / BUCKET_SIZE;
-    let grid_index = grid_to_index(pos_to_grid(particles[index].position));
+    let grid_index = particle_sort_key(index);
     let digit = val_to_digit(grid_index, current_digit_index);
 
     // Update the inclusive prefix sum
-    atomicAdd(&histogram[digit][bucket_index], 1u);
+    atomicAdd(&histogram[sort_index(u32(digit), bucket_index)], 1u);
 
     // Update the digit histogram
     atomicAdd(&digit_histogram[digit], 1u);
 
     // Update particle counts if is the last digit being sorted
-    if (current_digit_index == NUM_DIGITS - 1) {
+    if (current_digit_index == NUM_DIGITS - 1 && grid_index != grid_cell_count()) {
         atomicAdd(&particle_counts[grid_index], 1);
     }
 }
@@ -394,27 +1340,27 @@ fn update_histogram(@builtin(global_invocation_id) global_id: vec3<u32>) {
 fn update_inclusive_prefix_sum(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index: u32 = global_id.x;
     let digit: u32 = global_id.y;
-    if (index >= NUM_BUCKETS || digit >= u32(BASE)) {
+    if (index >= capacity.buckets || digit >= u32(BASE)) {
         return;
     }
     
     let lookup_distance: u32 = u32(pow(2.0, f32(scan_stage)));
 
     if (index >= lookup_distance) {
-        scanned_inclusive_prefix_sum[digit][index] = inclusive_prefix_sum[digit][index] + inclusive_prefix_sum[digit][index - lookup_distance];
+        scanned_inclusive_prefix_sum[sort_index(digit, index)] = inclusive_prefix_sum[sort_index(digit, index)] + inclusive_prefix_sum[sort_index(digit, index - lookup_distance)];
     } else {
-        scanned_inclusive_prefix_sum[digit][index] = inclusive_prefix_sum[digit][index];
+        scanned_inclusive_prefix_sum[sort_index(digit, index)] = inclusive_prefix_sum[sort_index(digit, index)];
     }
 }
 
 @compute @workgroup_size(WORKGROUP_SIZE, 1)
 fn update_indices(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index: u32 = global_id.x;
-    if (i32(index) >= TOTAL_PARTICLES) {
+    if (index >= capacity.particles) {
         return;
     }
 
-    let grid_index = grid_to_index(pos_to_grid(particles[index].position));
+    let grid_index = particle_sort_key(index);
     let digit: i32 = val_to_digit(grid_index, current_digit_index);
 
     // Calculate the number of elements before it
@@ -426,11 +1372,11 @@ fn update_indices(@builtin(global_invocation_id) global_id: vec3<u32>) {
     // Calculate the local offset
     let bucket_index: u32 = index / BUCKET_SIZE;
     let bucket_start: u32 = bucket_index * BUCKET_SIZE;
-    let bucket_end: u32 = min(bucket_start + BUCKET_SIZE, u32(TOTAL_PARTICLES));
+    let bucket_end: u32 = min(bucket_start + BUCKET_SIZE, capacity.particles);
 
-    var local_offset: u32 = inclusive_prefix_sum[digit][bucket_index] - 1u;
+    var local_offset: u32 = inclusive_prefix_sum[sort_index(u32(digit), bucket_index)] - 1u;
     for (var i: u32 = bucket_end - 1; i > index; i = i - 1u) {
-        let other_grid_index = grid_to_index(pos_to_grid(particles[i].position));
+        let other_grid_index = particle_sort_key(i);
         if (val_to_digit(other_grid_index, current_digit_index) == digit) {
             local_offset -= 1u;
         }
@@ -443,18 +1389,25 @@ fn update_indices(@builtin(global_invocation_id) global_id: vec3<u32>) {
 @compute @workgroup_size(WORKGROUP_SIZE, 1)
 fn update_lookup(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index: u32 = global_id.x;
-    if (i32(index) >= TOTAL_PARTICLES) {
+    if (index >= capacity.particles) {
         return;
     }
 
-    let grid_index = grid_to_index(pos_to_grid(particles[index].position));
+    let grid_index = particle_sort_key(index);
+    var prev_grid_index: i32 = -1;
+    if (index > 0) {
+        prev_grid_index = particle_sort_key(index - 1);
+    }
 
-    if (index == 0) {
-        particle_lookup[grid_index] = i32(index);
-    } else {
-        let prev_grid_index = grid_to_index(pos_to_grid(particles[index - 1].position));
-        if (grid_index != prev_grid_index) {
-            particle_lookup[grid_index] = i32(index);
+    // The inactive particles are at the end, the first of them is where the free pool starts
+    if (grid_index == grid_cell_count()) {
+        if (prev_grid_index != grid_cell_count()) {
+            particle_pool.first_inactive = index;
         }
-    }   
+        return;
+    }
+
+    if (grid_index != prev_grid_index) {
+        particle_lookup[grid_index] = i32(index);
+    }
 }
\ No newline at end of file
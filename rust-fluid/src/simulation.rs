@@ -0,0 +1,749 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use bytemuck::Zeroable;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use wgpu::BufferUsages;
+use crate::grid::GridConfig;
+use crate::mask::ImageMask;
+use renderer_backend::{
+    adapter::{select_adapter, AdapterOptions}, bind_group_layout_builder::{create_bind_group_layouts, PipelineBindings},
+    buffers::{create_buffer, create_reader_buffer, read_buffer}, compute_pipeline_builder::ComputePipelineBuilder, profiler::Profiler,
+    shader_watcher::validated, window::request_device,
+};
+use crate::scenarios::Scenario;
+use crate::{
+    grid_config, initial_particles, mask_particles, preprocess_shader, scenario_particles, Capacity,
+    CapacityBuffers, HeatSource, Particle, ParticlePool, Pointer, RigidBody, SimParams, BASE, BUOYANCY, DEFAULT_ACTIVE_PARTICLES,
+    GRAVITY, HEAT_SOURCE_RADIUS, MAX_HEAT_SOURCES, MAX_OBSTACLES, MAX_POINTERS, MAX_RIGID_BODIES, NUM_DIGITS, PBF_ITERATIONS, PBF_TIME_STEP, PIPELINES,
+    PRESSURE_MULTIPLIER, RIGID_BODY_DISPATCH_SIZE, SHADER, SOLVER_POSITION_BASED, TARGET_DENSITY, THERMAL_DIFFUSIVITY, TIME_STEP, TOTAL_PARTICLES,
+    VISCOSITY, VORTICITY_STRENGTH, WALL_DAMPENING, WALL_GRID_SIZE,
+};
+
+const SORT_INDICES: u32 = 32; // The digits and scan stages are copied from a buffer counting up to this, enough for MAX_PARTICLES
+const OBSTACLE_DENSITY: f32 = 1.0; // Obstacles are fixed, so their density only matters to the shader
+
+// How an embedded simulation starts
+#[derive(Debug, Clone)]
+pub struct FluidConfig {
+    pub grid: GridConfig,
+    pub seed: u64, // Where the poured particles land comes from the seed
+    pub particles: u32, // How many particles start active in the block filling the screen, the rest are the free pool
+}
+
+impl Default for FluidConfig {
+    fn default() -> Self {
+        FluidConfig {
+            grid: grid_config(None),
+            seed: 0,
+            particles: DEFAULT_ACTIVE_PARTICLES,
+        }
+    }
+}
+
+// The physical parameters that can change while the simulation runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FluidParams {
+    pub gravity: [f32; 2], // The scenarios that tilt the tank overwrite it every step
+    pub target_density: f32,
+    pub pressure_multiplier: f32,
+    pub viscosity: f32,
+    pub wall_dampening: f32, // How much of their speed the particles keep when they hit a wall
+    pub vorticity_strength: f32,
+    pub buoyancy: f32,
+    pub thermal_diffusivity: f32,
+}
+
+impl Default for FluidParams {
+    fn default() -> Self {
+        FluidParams {
+            gravity: [0.0, GRAVITY],
+            target_density: TARGET_DENSITY,
+            pressure_multiplier: PRESSURE_MULTIPLIER,
+            viscosity: VISCOSITY,
+            wall_dampening: WALL_DAMPENING,
+            vorticity_strength: VORTICITY_STRENGTH,
+            buoyancy: BUOYANCY,
+            thermal_diffusivity: THERMAL_DIFFUSIVITY,
+        }
+    }
+}
+
+impl FluidParams {
+    fn from_sim_params(params: &SimParams) -> Self {
+        FluidParams {
+            gravity: params.gravity,
+            target_density: params.target_density,
+            pressure_multiplier: params.pressure_multiplier,
+            viscosity: params.viscosity,
+            wall_dampening: params.wall_dampening,
+            vorticity_strength: params.vorticity_strength,
+            buoyancy: params.buoyancy,
+            thermal_diffusivity: params.thermal_diffusivity,
+        }
+    }
+
+    // Leaves the rest of the parameters, like the kernel and the scenario, as they were
+    fn apply(&self, params: &mut SimParams) {
+        params.gravity = self.gravity;
+        params.target_density = self.target_density;
+        params.pressure_multiplier = self.pressure_multiplier;
+        params.viscosity = self.viscosity;
+        params.wall_dampening = self.wall_dampening;
+        params.vorticity_strength = self.vorticity_strength;
+        params.buoyancy = self.buoyancy;
+        params.thermal_diffusivity = self.thermal_diffusivity;
+    }
+}
+
+// A solid shape held in place that the fluid flows around, in simulation coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Obstacle {
+    Box { centre: [f32; 2], half_size: [f32; 2] },
+    Disc { centre: [f32; 2], radius: f32 },
+}
+
+// The fluid solver on a device and queue it shares with whoever embeds it, it records its passes into their encoders
+// and leaves drawing to them, particle_buffer() holds the particles in the layout of Particle
+pub struct FluidSimulation {
+    pub(crate) device: Arc<wgpu::Device>,
+    pub(crate) queue: Arc<wgpu::Queue>,
+    pub(crate) shader_source: String, // The shader after preprocessing
+    pub(crate) bind_group_layouts: HashMap<&'static str, PipelineBindings>, // One per pipeline, with only the buffers it uses
+    compute_pipelines: HashMap<&'static str, wgpu::ComputePipeline>, // By the names in PIPELINES
+    bind_groups: HashMap<&'static str, wgpu::BindGroup>, // By the names in PIPELINES, rebuilt when the buffers are replaced
+    pub(crate) profiler: Profiler, // Disabled unless --profile is passed
+    profile_on_cpu: bool, // The CPU timer waits for each pass, so they are submitted one at a time
+    capacity: Capacity,
+    capacity_buffer: wgpu::Buffer,
+    particle_buffer: wgpu::Buffer,
+    particle_reader_buffer: wgpu::Buffer,
+    sorted_data_buffer: wgpu::Buffer,
+    histogram_buffer: wgpu::Buffer,
+    inclusive_prefix_sum_buffer: wgpu::Buffer,
+    scanned_inclusive_prefix_sum_buffer: wgpu::Buffer,
+    particle_lookup_buffer: wgpu::Buffer,
+    particle_counts_buffer: wgpu::Buffer,
+    lookup_reset_buffer: wgpu::Buffer, // -1 for every cell, copied over the lookup before each sort
+    sort_indices_buffer: wgpu::Buffer, // 0, 1, 2, ..., copied into the digit and scan stage between the passes
+    current_digit_index_buffer: wgpu::Buffer,
+    scan_stage_buffer: wgpu::Buffer,
+    digit_histogram_buffer: wgpu::Buffer,
+    pointers_buffer: wgpu::Buffer, // The mouse followed by the touches
+    rigid_bodies_buffer: wgpu::Buffer,
+    rigid_body_forces_buffer: wgpu::Buffer,
+    walls_buffer: wgpu::Buffer, // The walls painted in an image mask, 1 for solid cells
+    obstacles: u32, // How many of the MAX_OBSTACLES slots hold obstacles
+    next_rigid_body: u32, // How many bodies have been spawned, they cycle through the slots after MAX_OBSTACLES
+    scenario: Option<Scenario>, // None for the block filling the screen
+    scenario_steps: u32, // How many steps since the scenario was loaded
+    pub(crate) params: SimParams,
+    params_buffer: wgpu::Buffer,
+    next_heat_source: usize, // The slot the next placed heat source is written to
+    particle_pool: ParticlePool,
+    particle_pool_buffer: wgpu::Buffer,
+    particle_pool_read_buffer: wgpu::Buffer,
+    sim_time: f32, // How much simulated time has passed
+}
+
+// The layouts and compute pipelines built from one shader, swapped in together
+pub(crate) struct Pipelines {
+    pub(crate) shader_source: String,
+    pub(crate) bind_group_layouts: HashMap<&'static str, PipelineBindings>,
+    compute_pipelines: HashMap<&'static str, wgpu::ComputePipeline>,
+}
+
+impl FluidSimulation {
+    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, config: FluidConfig) -> Result<Self, String> {
+        let FluidConfig { grid, seed, particles } = config;
+        let shader_source = preprocess_shader(SHADER)?;
+        let bind_group_layouts = create_bind_group_layouts(&device, &shader_source, &PIPELINES)?;
+        // A pipeline that fails validation is returned to the host application instead of panicking in it
+        let compute_pipelines = validated(&device, || Ok(create_compute_pipelines(&device, &shader_source, &bind_group_layouts)))?;
+
+        // Every random initial condition comes from the seed, so a run can be repeated
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        // Buffers for the particles and the sort, sized by how many particles there are
+        let particles = initial_particles(particles);
+        let capacity = Capacity::new(particles.len() as u32);
+        let CapacityBuffers {
+            particles: particle_buffer,
+            particle_reader: particle_reader_buffer,
+            sorted_data: sorted_data_buffer,
+            histogram: histogram_buffer,
+            inclusive_prefix_sum: inclusive_prefix_sum_buffer,
+            scanned_inclusive_prefix_sum: scanned_inclusive_prefix_sum_buffer,
+        } = CapacityBuffers::new(&device, &capacity);
+        queue.write_buffer(&particle_buffer, 0, bytemuck::cast_slice(&particles));
+        let buffer = |label: &str, contents: &[u8], usage: BufferUsages| create_buffer(&device, label, contents, usage);
+        let storage = BufferUsages::STORAGE | BufferUsages::COPY_DST;
+        let capacity_buffer = buffer("Capacity Buffer", bytemuck::cast_slice(&[capacity]), BufferUsages::UNIFORM | BufferUsages::COPY_DST);
+
+        // The neighbour grid, the sort fills it in
+        let lookup_reset = vec![-1i32; grid.cell_count()];
+        let particle_lookup_buffer = buffer("Particle Lookup Buffer Data", bytemuck::cast_slice(&lookup_reset), storage);
+        let particle_counts_buffer = buffer("Particle Counts Buffer Data", bytemuck::cast_slice(&vec![0i32; grid.cell_count()]), storage);
+        let lookup_reset_buffer = buffer("Lookup Reset Buffer", bytemuck::cast_slice(&lookup_reset), BufferUsages::COPY_SRC);
+
+        // --- Sort Buffers --- //
+        let sort_indices: Vec<u32> = (0..SORT_INDICES).collect();
+        let sort_indices_buffer = buffer("Sort Indices Buffer", bytemuck::cast_slice(&sort_indices), BufferUsages::COPY_SRC);
+        let current_digit_index_buffer = buffer("Current Digit Index Buffer", bytemuck::cast_slice(&[0u32]), storage);
+        let scan_stage_buffer = buffer("Scan Stage Buffer", bytemuck::cast_slice(&[0u32]), storage);
+        let digit_histogram_buffer = buffer("Digit Histogram Buffer", bytemuck::cast_slice(&[0u32; BASE as usize]), storage);
+
+        // The mouse and the touches, nothing is pressed until the app says so
+        let pointers_buffer = buffer("Pointers Buffer", bytemuck::cast_slice(&[Pointer::zeroed(); MAX_POINTERS as usize]), storage);
+
+        // Rigid bodies, all slots start inactive
+        let rigid_bodies_buffer = buffer("Rigid Bodies Buffer", bytemuck::cast_slice(&[RigidBody::zeroed(); MAX_RIGID_BODIES as usize]), storage | BufferUsages::COPY_SRC);
+        let rigid_body_forces_buffer = buffer("Rigid Body Forces Buffer", bytemuck::cast_slice(&[0i32; MAX_RIGID_BODIES as usize * 3]), storage);
+
+        // Walls painted in an image mask, there are none until one is loaded
+        let walls = vec![0u32; (WALL_GRID_SIZE[0] * WALL_GRID_SIZE[1]) as usize];
+        let walls_buffer = buffer("Walls Buffer", bytemuck::cast_slice(&walls), storage);
+
+        // Particle pool, the sort finds where the free pool starts
+        let particle_pool = ParticlePool {
+            first_inactive: capacity.particles,
+            seed: rng.gen(),
+        };
+        let particle_pool_buffer = buffer("Particle Pool Buffer Data", bytemuck::cast_slice(&[particle_pool]), storage | BufferUsages::COPY_SRC);
+        let particle_pool_read_buffer = create_reader_buffer(&device, "Particle Pool Read Buffer", std::mem::size_of::<ParticlePool>() as u64);
+
+        // Simulation parameters that can change while running
+        let params = SimParams::new(&grid);
+        let params_buffer = buffer("Sim Params Buffer", bytemuck::cast_slice(&[params]), storage);
+
+        let mut simulation = FluidSimulation {
+            device,
+            queue,
+            shader_source,
+            bind_group_layouts,
+            compute_pipelines,
+            bind_groups: HashMap::new(),
+            profiler: Profiler::disabled(),
+            profile_on_cpu: false,
+            capacity,
+            capacity_buffer,
+            particle_buffer,
+            particle_reader_buffer,
+            sorted_data_buffer,
+            histogram_buffer,
+            inclusive_prefix_sum_buffer,
+            scanned_inclusive_prefix_sum_buffer,
+            particle_lookup_buffer,
+            particle_counts_buffer,
+            lookup_reset_buffer,
+            sort_indices_buffer,
+            current_digit_index_buffer,
+            scan_stage_buffer,
+            digit_histogram_buffer,
+            pointers_buffer,
+            rigid_bodies_buffer,
+            rigid_body_forces_buffer,
+            walls_buffer,
+            obstacles: 0,
+            next_rigid_body: 0,
+            scenario: None,
+            scenario_steps: 0,
+            params,
+            params_buffer,
+            next_heat_source: 0,
+            particle_pool,
+            particle_pool_buffer,
+            particle_pool_read_buffer,
+            sim_time: 0.0,
+        };
+        simulation.create_bind_groups();
+        simulation.submit(Self::sort_particles);
+        Ok(simulation)
+    }
+
+    // A simulation on a device of its own, for running without a window
+    pub async fn headless(config: FluidConfig, adapter: &AdapterOptions) -> Result<Self, String> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let adapter = select_adapter(&instance, None, adapter).await?;
+        println!("{:?}", adapter.get_info());
+        let (device, queue) = request_device(&adapter, device_limits()).await?;
+        Self::new(Arc::new(device), Arc::new(queue), config)
+    }
+
+    pub(crate) fn set_profiler(&mut self, profiler: Profiler) {
+        // The profiler falls back to timing on the CPU when the adapter has no timestamp queries
+        self.profile_on_cpu = profiler.is_enabled() && !self.device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        self.profiler = profiler;
+    }
+
+    // Builds the layouts and compute pipelines of another shader next to the running ones, nothing changes until set_pipelines
+    // Validation errors in the pipelines go to the caller's error scope
+    pub(crate) fn build_pipelines(&self, shader_source: String) -> Result<Pipelines, String> {
+        let bind_group_layouts = create_bind_group_layouts(&self.device, &shader_source, &PIPELINES)?;
+        let buffers = self.named_buffers();
+        bind_group_layouts.values().try_for_each(|bindings| bindings.check_buffers(&buffers))?;
+        let compute_pipelines = create_compute_pipelines(&self.device, &shader_source, &bind_group_layouts);
+        Ok(Pipelines { shader_source, bind_group_layouts, compute_pipelines })
+    }
+
+    // Swaps in pipelines from build_pipelines, the buffers are kept and bound to them
+    pub(crate) fn set_pipelines(&mut self, pipelines: Pipelines) {
+        self.shader_source = pipelines.shader_source;
+        self.bind_group_layouts = pipelines.bind_group_layouts;
+        self.compute_pipelines = pipelines.compute_pipelines;
+        self.create_bind_groups();
+    }
+
+    // The buffers behind the variables in the shader, bind groups find their buffers by these names
+    fn named_buffers(&self) -> HashMap<&'static str, &wgpu::Buffer> {
+        HashMap::from([
+            ("particles", &self.particle_buffer),
+            ("particle_lookup", &self.particle_lookup_buffer),
+            ("particle_counts", &self.particle_counts_buffer),
+            ("pointers", &self.pointers_buffer),
+            ("histogram", &self.histogram_buffer),
+            ("inclusive_prefix_sum", &self.inclusive_prefix_sum_buffer),
+            ("current_digit_index", &self.current_digit_index_buffer),
+            ("sorted_data", &self.sorted_data_buffer),
+            ("scan_stage", &self.scan_stage_buffer),
+            ("scanned_inclusive_prefix_sum", &self.scanned_inclusive_prefix_sum_buffer),
+            ("digit_histogram", &self.digit_histogram_buffer),
+            ("rigid_bodies", &self.rigid_bodies_buffer),
+            ("rigid_body_forces", &self.rigid_body_forces_buffer),
+            ("walls", &self.walls_buffer),
+            ("params", &self.params_buffer),
+            ("particle_pool", &self.particle_pool_buffer),
+            ("capacity", &self.capacity_buffer),
+        ])
+    }
+
+    // The render pipeline of the window draws with the same buffers
+    fn create_bind_groups(&mut self) {
+        let buffers = self.named_buffers();
+        let bind_groups = PIPELINES
+            .iter()
+            .map(|&(pipeline, _)| (pipeline, self.bind_group_layouts[pipeline].create_bind_group(&self.device, &buffers)))
+            .collect();
+        self.bind_groups = bind_groups;
+    }
+
+    pub(crate) fn render_bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_groups["render"]
+    }
+
+    // The device the simulation runs on, the one it was given or the one headless() opened
+    pub fn device(&self) -> &Arc<wgpu::Device> {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Arc<wgpu::Queue> {
+        &self.queue
+    }
+
+    // The particles in the layout of Particle, capacity() slots long, for drawing them in another renderer
+    pub fn particle_buffer(&self) -> &wgpu::Buffer {
+        &self.particle_buffer
+    }
+
+    // How many particle slots there are, the active particles and then the free pool
+    pub fn capacity(&self) -> u32 {
+        self.capacity.particles
+    }
+
+    // How much simulated time has passed
+    pub fn time(&self) -> f32 {
+        self.sim_time
+    }
+
+    pub fn params(&self) -> FluidParams {
+        FluidParams::from_sim_params(&self.params)
+    }
+
+    // Takes effect from the next step
+    pub fn set_params(&mut self, params: FluidParams) {
+        params.apply(&mut self.params);
+    }
+
+    // Obstacles stay until the simulation starts over, they have their own slots so they never replace a spawned body
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) -> Result<(), String> {
+        if self.obstacles >= MAX_OBSTACLES {
+            return Err(format!("There is only room for {} obstacles", MAX_OBSTACLES));
+        }
+        let rigid_body = match obstacle {
+            Obstacle::Box { centre, half_size } => RigidBody::new_box(centre, half_size, OBSTACLE_DENSITY),
+            Obstacle::Disc { centre, radius } => RigidBody::new_disc(centre, radius, OBSTACLE_DENSITY),
+        };
+        self.write_rigid_body(self.obstacles, rigid_body.fixed());
+        self.obstacles += 1;
+        Ok(())
+    }
+
+    fn write_rigid_body(&self, slot: u32, rigid_body: RigidBody) {
+        self.queue.write_buffer(
+            &self.rigid_bodies_buffer,
+            (slot as usize * std::mem::size_of::<RigidBody>()) as u64,
+            bytemuck::cast_slice(&[rigid_body]),
+        );
+    }
+
+    pub(crate) fn spawn_rigid_body(&mut self, rigid_body: RigidBody) {
+        // Overwrite the oldest slot once all of them are in use, the obstacle slots are never used
+        let slot = MAX_OBSTACLES + self.next_rigid_body % (MAX_RIGID_BODIES - MAX_OBSTACLES);
+        self.write_rigid_body(slot, rigid_body);
+        self.next_rigid_body += 1;
+    }
+
+    pub(crate) fn place_heat_source(&mut self, position: [f32; 2], temperature: f32) {
+        // Overwrite the oldest slot once all of them are in use
+        let slot = self.next_heat_source % MAX_HEAT_SOURCES as usize;
+        self.params.heat_sources[slot] = HeatSource {
+            position,
+            radius: HEAT_SOURCE_RADIUS,
+            temperature,
+        };
+        self.next_heat_source += 1;
+    }
+
+    pub(crate) fn clear_heat_sources(&mut self) {
+        self.params.heat_sources = [HeatSource::zeroed(); MAX_HEAT_SOURCES as usize];
+    }
+
+    // The mouse followed by the touches, each with its own tool, for the next step
+    // Only the first MAX_POINTERS are used, the slots after the last pointer are released
+    pub fn set_pointers(&self, pointers: &[Pointer]) {
+        let mut slots = [Pointer::zeroed(); MAX_POINTERS as usize];
+        for (slot, pointer) in slots.iter_mut().zip(pointers) {
+            *slot = *pointer;
+        }
+        self.queue.write_buffer(&self.pointers_buffer, 0, bytemuck::cast_slice(&slots));
+    }
+
+    // Every slot, the inactive ones in the free pool have a radius of 0
+    pub fn read_particles(&self) -> Vec<Particle> {
+        read_buffer(&self.device, &self.queue, &self.particle_buffer, &self.particle_reader_buffer)
+    }
+
+    // The number of active particles, the sort moves them in front of the free pool
+    pub fn live_particles(&self) -> u32 {
+        let pool: Vec<ParticlePool> = read_buffer(&self.device, &self.queue, &self.particle_pool_buffer, &self.particle_pool_read_buffer);
+        pool.first().map_or(0, |pool| pool.first_inactive)
+    }
+
+    // Blocks until the GPU has finished everything submitted so far
+    pub fn wait(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    fn create_encoder(&self, label: &str) -> wgpu::CommandEncoder {
+        self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some(label) })
+    }
+
+    // Records with an encoder of its own and submits it, for the work done outside of a step
+    fn submit(&self, record: impl FnOnce(&Self, &mut wgpu::CommandEncoder)) {
+        let mut encoder = self.create_encoder("Fluid Encoder");
+        record(self, &mut encoder);
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    // Records a compute pass of one of the PIPELINES, the name is used for the profiler
+    fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, pipeline: &str, workgroups: [u32; 2], name: &'static str) {
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(name),
+                timestamp_writes: self.profiler.compute_pass(name),
+            });
+            compute_pass.set_pipeline(&self.compute_pipelines[pipeline]);
+            compute_pass.set_bind_group(0, &self.bind_groups[pipeline], &[]);
+            compute_pass.dispatch_workgroups(workgroups[0], workgroups[1], 1);
+        }
+
+        // The CPU timer has to wait for the pass, so it is submitted on its own
+        if self.profile_on_cpu {
+            let recorded = std::mem::replace(encoder, self.create_encoder("Fluid Encoder"));
+            self.queue.submit(std::iter::once(recorded.finish()));
+            self.profiler.end_pass(&self.device);
+        }
+    }
+
+    // Dispatches a compute shader over every particle
+    fn dispatch_particles(&self, encoder: &mut wgpu::CommandEncoder, pipeline: &str, name: &'static str) {
+        self.dispatch(encoder, pipeline, [self.capacity.particle_workgroups(), 1], name);
+    }
+
+    // Copies one of the counting numbers into a buffer, so the digit and the scan stage change between passes in the same encoder
+    fn set_sort_index(&self, encoder: &mut wgpu::CommandEncoder, buffer: &wgpu::Buffer, index: u32) {
+        let size = std::mem::size_of::<u32>() as u64;
+        encoder.copy_buffer_to_buffer(&self.sort_indices_buffer, index as u64 * size, buffer, 0, size);
+    }
+
+    // Clears the start of each cell, the sort fills it in again
+    pub fn reset_lookup(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_buffer_to_buffer(&self.lookup_reset_buffer, 0, &self.particle_lookup_buffer, 0, self.lookup_reset_buffer.size());
+    }
+
+    pub fn sort_particles(&self, encoder: &mut wgpu::CommandEncoder) {
+        for i in 0..NUM_DIGITS {
+            self.sort_particles_by_digit(encoder, i);
+        }
+    }
+
+    fn sort_particles_by_digit(&self, encoder: &mut wgpu::CommandEncoder, digit: u32) {
+        // Reset the histograms, and the particle counts if it is the last digit
+        encoder.clear_buffer(&self.histogram_buffer, 0, None);
+        encoder.clear_buffer(&self.digit_histogram_buffer, 0, None);
+        if digit == NUM_DIGITS - 1 {
+            encoder.clear_buffer(&self.particle_counts_buffer, 0, None);
+        }
+        self.set_sort_index(encoder, &self.current_digit_index_buffer, digit);
+        self.dispatch(encoder, "update_histogram", [self.capacity.sort_workgroups(), 1], "sort histogram");
+
+        // Set the inclusive prefix sum buffer to the histogram buffer
+        encoder.copy_buffer_to_buffer(&self.histogram_buffer, 0, &self.inclusive_prefix_sum_buffer, 0, self.histogram_buffer.size());
+
+        for i in 0..self.capacity.scan_stages() {
+            self.set_sort_index(encoder, &self.scan_stage_buffer, i);
+            self.dispatch(encoder, "update_inclusive_prefix_sum", [self.capacity.scan_workgroups(), BASE], "scan");
+
+            // Copy the scanned inclusive prefix sum buffer to the inclusive prefix sum buffer
+            encoder.copy_buffer_to_buffer(
+                &self.scanned_inclusive_prefix_sum_buffer,
+                0,
+                &self.inclusive_prefix_sum_buffer,
+                0,
+                self.inclusive_prefix_sum_buffer.size(),
+            );
+        }
+
+        // Scatter the particles by the digit and copy them back
+        self.dispatch(encoder, "update_indices", [self.capacity.sort_workgroups(), 1], "scatter");
+        encoder.copy_buffer_to_buffer(&self.sorted_data_buffer, 0, &self.particle_buffer, 0, self.particle_buffer.size());
+
+        // Update particle lookup if it is the last digit
+        if digit == NUM_DIGITS - 1 {
+            self.dispatch(encoder, "update_lookup", [self.capacity.sort_workgroups(), 1], "lookup");
+        }
+    }
+
+    // Predicts the new positions, then projects them onto the density constraint
+    // The forces pass has already added the external forces
+    fn step_position_based(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.dispatch_particles(encoder, "pbf_predict", "pbf predict");
+
+        // Sort the predicted positions so the neighbour search finds the new neighbours
+        self.reset_lookup(encoder);
+        self.sort_particles(encoder);
+
+        for _ in 0..PBF_ITERATIONS {
+            self.dispatch_particles(encoder, "pbf_lambda", "pbf lambda");
+            self.dispatch_particles(encoder, "pbf_delta", "pbf delta");
+            self.dispatch_particles(encoder, "pbf_apply", "pbf apply");
+        }
+
+        self.dispatch_particles(encoder, "pbf_viscosity", "pbf viscosity");
+        self.dispatch_particles(encoder, "pbf_apply_viscosity", "pbf apply viscosity");
+
+        self.compute_rigid_bodies(encoder);
+    }
+
+    // Records one step, the parameters and the pool are written to the queue first,
+    // so the encoder has to be submitted before the next step is recorded
+    pub fn step(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(scenario) = self.scenario {
+            self.params.gravity = scenario.gravity(self.scenario_steps);
+            self.scenario_steps += 1;
+        }
+        self.queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+
+        // The sort finds the start of the free pool again, if there are no inactive particles it stays at the end
+        self.particle_pool.first_inactive = self.capacity.particles;
+        self.particle_pool.seed = self.particle_pool.seed.wrapping_add(1);
+        self.queue.write_buffer(&self.particle_pool_buffer, 0, bytemuck::cast_slice(&[self.particle_pool]));
+
+        self.compute_density(encoder);
+        self.compute_vorticity(encoder);
+        self.compute_forces(encoder);
+
+        if self.params.solver_mode == SOLVER_POSITION_BASED {
+            self.step_position_based(encoder);
+        } else {
+            self.compute_move(encoder);
+            self.compute_rigid_bodies(encoder);
+
+            // Sort the particles
+            self.reset_lookup(encoder);
+            self.sort_particles(encoder);
+        }
+
+        // Pour and erase particles
+        self.dispatch(encoder, "update_pool", [self.capacity.sort_workgroups(), 1], "pool");
+
+        self.sim_time += if self.params.solver_mode == SOLVER_POSITION_BASED { PBF_TIME_STEP } else { TIME_STEP };
+    }
+
+    pub fn compute_density(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.dispatch_particles(encoder, "main_density", "density");
+    }
+
+    pub fn compute_vorticity(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.dispatch_particles(encoder, "main_vorticity", "vorticity");
+    }
+
+    pub fn compute_forces(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.dispatch_particles(encoder, "main_forces", "forces");
+    }
+
+    pub fn compute_move(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.dispatch_particles(encoder, "main_move", "move");
+    }
+
+    fn compute_rigid_bodies(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.dispatch(encoder, "main_rigid_bodies", [RIGID_BODY_DISPATCH_SIZE, 1], "rigid bodies");
+    }
+
+    // Replaces the particles with `active` particles at rest, for benchmarks at different particle counts
+    pub fn reset_particles(&mut self, active: u32) {
+        self.replace_particles(initial_particles(active.min(TOTAL_PARTICLES)));
+        self.submit(|simulation, encoder| {
+            simulation.reset_lookup(encoder);
+            simulation.sort_particles(encoder);
+        });
+    }
+
+    // Writes the particles, the buffers and the bind groups using them are replaced when the number of slots changes
+    fn replace_particles(&mut self, particles: Vec<Particle>) {
+        let capacity = Capacity::new(particles.len() as u32);
+        if capacity != self.capacity {
+            log::info!("Resizing the particle buffers from {} to {} slots", self.capacity.particles, capacity.particles);
+            let buffers = CapacityBuffers::new(&self.device, &capacity);
+            self.particle_buffer = buffers.particles;
+            self.particle_reader_buffer = buffers.particle_reader;
+            self.sorted_data_buffer = buffers.sorted_data;
+            self.histogram_buffer = buffers.histogram;
+            self.inclusive_prefix_sum_buffer = buffers.inclusive_prefix_sum;
+            self.scanned_inclusive_prefix_sum_buffer = buffers.scanned_inclusive_prefix_sum;
+            self.capacity = capacity;
+            self.queue.write_buffer(&self.capacity_buffer, 0, bytemuck::cast_slice(&[capacity]));
+            self.create_bind_groups();
+        }
+
+        self.queue.write_buffer(&self.particle_buffer, 0, bytemuck::cast_slice(&particles));
+    }
+
+    // Starts a scenario over, writing its particles, obstacles and parameters into the existing buffers
+    pub(crate) fn load_scenario(&mut self, scenario: Scenario) {
+        self.scenario = Some(scenario);
+        scenario.apply(&mut self.params);
+        let walls = vec![0; (WALL_GRID_SIZE[0] * WALL_GRID_SIZE[1]) as usize];
+        self.restart(scenario_particles(scenario), &scenario.obstacles(), &walls);
+    }
+
+    // Starts over with the block filling the screen, with this many particles active and the rest in the free pool
+    pub fn load_block(&mut self, active: u32) {
+        self.scenario = None;
+        self.params.clear_scenario();
+        let walls = vec![0; (WALL_GRID_SIZE[0] * WALL_GRID_SIZE[1]) as usize];
+        self.restart(initial_particles(active), &[], &walls);
+    }
+
+    // Starts over with the fluid and walls painted in an image mask
+    pub(crate) fn load_mask(&mut self, mask: &ImageMask) {
+        self.scenario = None;
+        self.params.clear_scenario();
+        self.restart(mask_particles(mask), &[], &mask.walls());
+    }
+
+    // Replaces the particles, rigid bodies and walls in the existing buffers
+    fn restart(&mut self, particles: Vec<Particle>, obstacles: &[RigidBody], walls: &[u32]) {
+        self.scenario_steps = 0;
+        self.replace_particles(particles);
+
+        // The obstacles replace every rigid body
+        assert!(obstacles.len() <= MAX_OBSTACLES as usize, "Too many obstacles");
+        let mut rigid_bodies = [RigidBody::zeroed(); MAX_RIGID_BODIES as usize];
+        rigid_bodies[..obstacles.len()].copy_from_slice(obstacles);
+        self.queue.write_buffer(&self.rigid_bodies_buffer, 0, bytemuck::cast_slice(&rigid_bodies));
+        self.queue.write_buffer(
+            &self.rigid_body_forces_buffer,
+            0,
+            bytemuck::cast_slice(&[0i32; MAX_RIGID_BODIES as usize * 3]),
+        );
+        self.obstacles = obstacles.len() as u32;
+        self.next_rigid_body = 0;
+
+        self.queue.write_buffer(&self.walls_buffer, 0, bytemuck::cast_slice(walls));
+        self.queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+
+        self.submit(|simulation, encoder| {
+            simulation.reset_lookup(encoder);
+            simulation.sort_particles(encoder);
+        });
+    }
+}
+
+// The limits the device given to FluidSimulation::new needs, the particle passes run 1024 invocations per workgroup
+pub fn device_limits() -> wgpu::Limits {
+    wgpu::Limits {
+        max_compute_invocations_per_workgroup: 1024,
+        ..wgpu::Limits::default()
+    }
+}
+
+// Every pipeline in PIPELINES except the render pipeline, which needs the format of the window
+fn create_compute_pipelines(
+    device: &wgpu::Device,
+    shader_source: &str,
+    bind_group_layouts: &HashMap<&'static str, PipelineBindings>,
+) -> HashMap<&'static str, wgpu::ComputePipeline> {
+    PIPELINES
+        .iter()
+        .filter(|(pipeline, _)| *pipeline != "render")
+        .map(|&(pipeline, entry_points)| {
+            let mut builder = ComputePipelineBuilder::new();
+            builder.set_shader_module(shader_source, entry_points[0]);
+            builder.set_bind_group_layout(bind_group_layouts[pipeline].layout());
+            (pipeline, builder.build_pipeline(device))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use renderer_backend::adapter::adapter_available;
+    use crate::{LIGHT_RIGID_BODY_DENSITY, RIGID_BODY_BOX, RIGID_BODY_DISC};
+
+    #[test]
+    fn params_round_trip_through_the_shader_params() {
+        let mut sim_params = SimParams::new(&grid_config(None));
+        assert_eq!(FluidParams::from_sim_params(&sim_params), FluidParams::default());
+
+        let params = FluidParams { gravity: [0.1, -0.3], viscosity: 0.4, buoyancy: 0.0, ..FluidParams::default() };
+        let kernel = sim_params.kernel;
+        params.apply(&mut sim_params);
+        assert_eq!(FluidParams::from_sim_params(&sim_params), params);
+        assert_eq!(sim_params.kernel, kernel);
+    }
+
+    #[test]
+    fn obstacles_and_spawned_bodies_keep_their_slots() {
+        if !pollster::block_on(adapter_available(&AdapterOptions::default())) {
+            eprintln!("There is no adapter, skipping the rigid body slots");
+            return;
+        }
+        let mut sim = pollster::block_on(FluidSimulation::headless(FluidConfig::default(), &AdapterOptions::default())).unwrap();
+        sim.spawn_rigid_body(RigidBody::new_disc([300.0, 200.0], 25.0, LIGHT_RIGID_BODY_DENSITY));
+        for _ in 0..MAX_OBSTACLES {
+            sim.add_obstacle(Obstacle::Box { centre: [600.0, 100.0], half_size: [30.0, 20.0] }).unwrap();
+        }
+        assert!(sim.add_obstacle(Obstacle::Disc { centre: [600.0, 300.0], radius: 10.0 }).is_err());
+
+        let reader = create_reader_buffer(&sim.device, "Rigid Bodies Read Buffer", sim.rigid_bodies_buffer.size());
+        let rigid_bodies: Vec<RigidBody> = read_buffer(&sim.device, &sim.queue, &sim.rigid_bodies_buffer, &reader);
+        let count = |shape, fixed| rigid_bodies.iter().filter(|body| body.shape == shape && body.fixed == fixed).count();
+        assert_eq!(count(RIGID_BODY_DISC, 0), 1, "The spawned disc was replaced");
+        assert_eq!(count(RIGID_BODY_BOX, 1), MAX_OBSTACLES as usize);
+    }
+}
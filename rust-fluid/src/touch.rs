@@ -0,0 +1,142 @@
+use bytemuck::Zeroable;
+use crate::{MouseTool, Pointer, MAX_TOUCHES, MOUSE_LEFT};
+
+// What a finger or pen did, the same for every platform so a recording plays back anywhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPhase {
+    Down,
+    Move,
+    Up, // Also sent when the platform cancels the touch
+}
+
+impl TouchPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            TouchPhase::Down => "down",
+            TouchPhase::Move => "move",
+            TouchPhase::Up => "up",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [TouchPhase::Down, TouchPhase::Move, TouchPhase::Up].into_iter().find(|phase| phase.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Touch {
+    id: u64,
+    position: [f32; 2],
+    last_position: [f32; 2], // Where it was last step, to find how far it moved
+    pressure: f32,
+    radius: f32,
+    tool: u32,
+    strength: f32,
+}
+
+// The fingers and pens on the screen, each keeps its pointer slot until it is lifted so the slots don't jump around
+pub struct Touches {
+    slots: [Option<Touch>; MAX_TOUCHES as usize],
+}
+
+impl Touches {
+    pub fn new() -> Self {
+        Touches { slots: [None; MAX_TOUCHES as usize] }
+    }
+
+    // Touches after the slots run out are ignored until one of the others is lifted
+    // A new touch takes the tool, radius and strength of `mouse` and keeps them until it is lifted, so several people can use different tools
+    pub(crate) fn update(&mut self, id: u64, phase: TouchPhase, position: [f32; 2], pressure: f32, mouse: &MouseTool) {
+        let slot = self.slots.iter().position(|touch| touch.is_some_and(|touch| touch.id == id));
+        match (phase, slot) {
+            (TouchPhase::Up, Some(slot)) => self.slots[slot] = None,
+            (TouchPhase::Down | TouchPhase::Move, Some(slot)) => {
+                let touch = self.slots[slot].as_mut().unwrap();
+                touch.position = position;
+                touch.pressure = pressure;
+            }
+            (TouchPhase::Down, None) => {
+                if let Some(free) = self.slots.iter_mut().find(|touch| touch.is_none()) {
+                    *free = Some(Touch { id, position, last_position: position, pressure, radius: mouse.radius, tool: mouse.tool, strength: mouse.strength });
+                }
+            }
+            (TouchPhase::Move | TouchPhase::Up, None) => {}
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.slots.iter().flatten().count()
+    }
+
+    // The pointers sent to the GPU each step, touches act like the left mouse button
+    pub fn pointers(&mut self) -> [Pointer; MAX_TOUCHES as usize] {
+        let mut pointers = [Pointer::zeroed(); MAX_TOUCHES as usize];
+        for (pointer, touch) in pointers.iter_mut().zip(&mut self.slots) {
+            if let Some(touch) = touch {
+                *pointer = Pointer {
+                    position: touch.position,
+                    movement: [touch.position[0] - touch.last_position[0], touch.position[1] - touch.last_position[1]],
+                    pressure: touch.pressure,
+                    buttons: MOUSE_LEFT,
+                    radius: touch.radius,
+                    tool: touch.tool,
+                    strength: touch.strength,
+                    _padding: 0.0,
+                };
+                touch.last_position = touch.position;
+            }
+        }
+        pointers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TOOL_PUSH;
+
+    const TOOL_DRAG: u32 = 1; // The second of TOOL_NAMES
+
+    fn mouse(tool: u32, radius: f32) -> MouseTool {
+        MouseTool { tool, radius, ..MouseTool::new() }
+    }
+
+    #[test]
+    fn touches_keep_their_slots() {
+        let mut touches = Touches::new();
+        touches.update(7, TouchPhase::Down, [10.0, 10.0], 1.0, &mouse(TOOL_PUSH, 30.0));
+        touches.update(9, TouchPhase::Down, [50.0, 50.0], 0.5, &mouse(TOOL_PUSH, 30.0));
+        touches.update(7, TouchPhase::Up, [10.0, 10.0], 1.0, &mouse(TOOL_PUSH, 30.0));
+        touches.update(9, TouchPhase::Move, [53.0, 46.0], 0.25, &mouse(TOOL_PUSH, 30.0));
+
+        let pointers = touches.pointers();
+        assert_eq!(touches.count(), 1);
+        assert_eq!(pointers[0].buttons, 0);
+        assert_eq!(pointers[1], Pointer { position: [53.0, 46.0], movement: [3.0, -4.0], pressure: 0.25, buttons: MOUSE_LEFT, radius: 30.0, tool: TOOL_PUSH, strength: 1.0, _padding: 0.0 });
+        // The movement is measured from the last step
+        assert_eq!(touches.pointers()[1].movement, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn touches_past_the_slots_are_ignored() {
+        let mut touches = Touches::new();
+        for id in 0..MAX_TOUCHES as u64 + 2 {
+            touches.update(id, TouchPhase::Down, [0.0, 0.0], 1.0, &mouse(TOOL_PUSH, 30.0));
+        }
+        assert_eq!(touches.count(), MAX_TOUCHES as usize);
+        touches.update(MAX_TOUCHES as u64, TouchPhase::Move, [1.0, 1.0], 1.0, &mouse(TOOL_PUSH, 30.0));
+        assert!(touches.pointers().iter().all(|pointer| pointer.position == [0.0, 0.0]));
+    }
+
+    #[test]
+    fn touches_keep_the_tool_they_started_with() {
+        let mut touches = Touches::new();
+        touches.update(1, TouchPhase::Down, [10.0, 10.0], 1.0, &mouse(TOOL_PUSH, 30.0));
+        touches.update(2, TouchPhase::Down, [50.0, 50.0], 1.0, &MouseTool { strength: 4.0, ..mouse(TOOL_DRAG, 80.0) });
+        touches.update(1, TouchPhase::Move, [12.0, 10.0], 1.0, &mouse(TOOL_DRAG, 80.0));
+
+        let pointers = touches.pointers();
+        assert_eq!((pointers[0].tool, pointers[0].radius, pointers[0].strength), (TOOL_PUSH, 30.0, 1.0));
+        assert_eq!((pointers[1].tool, pointers[1].radius, pointers[1].strength), (TOOL_DRAG, 80.0, 4.0));
+    }
+}